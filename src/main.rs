@@ -1,4 +1,6 @@
+use std::env;
 use std::io;
+use std::time::SystemTime;
 use termion::event::Key;
 use termion::raw::IntoRawMode;
 use tui::backend::TermionBackend;
@@ -6,10 +8,12 @@ use tui::Terminal;
 
 mod utils;
 use crate::utils::events::{Event, Events};
+use crate::utils::keymap::{self, Action, KeyResult, Keymap, KeymapMode};
 use crate::utils::QuitOption;
 
 mod model;
-use crate::model::app::App;
+use crate::model::app::{App, AppMode, CommandMode};
+use crate::model::search::SearchDirection;
 
 mod view;
 use crate::view::View;
@@ -18,70 +22,202 @@ fn main() -> Result<(), io::Error> {
     println!("Hello, world!");
     // Setup buffers, load configs
     // Construct program state
-    let mut app: App = App::new();
+    let args: Vec<String> = env::args().collect();
+    let mut app: App = App::new(&args);
+    // Same format as `:set clipboard=`, e.g. "osc52" when working over SSH
+    if let Ok(clipboard) = env::var("RACCOON_CLIPBOARD") {
+        app.set_clipboard_option(&clipboard);
+    }
+    // A bad keymap shouldn't stop the editor from starting, so fall back
+    // to the defaults and say why
+    let mut keymap = Keymap::load().unwrap_or_else(|e| {
+        app.set_message(e);
+        Keymap::new()
+    });
 
     // Construct the event queue
-    let events = Events::new();
+    // Quitting is decided by the keymap, so that every printable key can
+    // be typed into the buffer
+    let mut events = Events::new();
+    events.disable_exit_key();
 
     // Enter raw mode
     let stdout = io::stdout().into_raw_mode()?;
     let backend = TermionBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let terminal = Terminal::new(backend)?;
     let mut view = View::new(terminal);
 
     // Loop:
     // get next event from event queue
     // handle next event, update program state
     loop {
+        let output = app.take_terminal_output();
+        if !output.is_empty() {
+            view.write_to_terminal(&output)?;
+        }
         view.update_display(&app)?;
 
+        // Whatever edits were made since the last tick are put in the swap
+        // file before giving up, so they can be recovered
         let event = match events.next() {
             Ok(event) => event,
-            Err(e) => panic!("{:?}", e),
+            Err(e) => {
+                app.update_swap();
+                panic!("{:?}", e)
+            }
         };
 
-        match handle_event(event, &mut app) {
-            Ok(QuitOption::Quitting) => break,
+        match handle_event(event, &mut app, &mut keymap, view.page_height()) {
+            Ok(QuitOption::Quitting) => {
+                app.remove_swap();
+                break;
+            }
             Ok(QuitOption::NotQuitting) => {}
-            Err(x) => panic!("{:?}", x),
+            Err(x) => {
+                app.update_swap();
+                panic!("{:?}", x)
+            }
         };
     }
 
     Ok(())
 }
 
-fn handle_event(event: Event, app: &mut App) -> Result<QuitOption, ()> {
+fn handle_event(
+    event: Event,
+    app: &mut App,
+    keymap: &mut Keymap,
+    page_height: usize,
+) -> Result<QuitOption, ()> {
     match event {
-        // Full list of keys can be found at
-        // https://docs.rs/termion/1.1.1/termion/event/enum.Key.html
-        Event::Tick { .. } => Ok(QuitOption::NotQuitting),
-        Event::Input {
-            key: Key::Char('q'),
-            ..
-        } => Ok(QuitOption::Quitting),
-        Event::Input {
-            key: Key::Char(c), ..
-        } => {
-            app.add_char(c);
-            Ok(QuitOption::NotQuitting)
+        Event::Tick { time } => {
+            app.tick(time);
+            // A bound key still waiting on the rest of a longer binding
+            let timed_out = keymap_mode(app).and_then(|mode| {
+                let result = keymap.time_out(mode, time)?;
+                Some(handle_key_result(result, time, app, keymap, page_height))
+            });
+            Ok(timed_out.unwrap_or(QuitOption::NotQuitting))
         }
-        Event::Input {
-            key: Key::Backspace,
-            ..
-        } => {
-            app.remove_char();
-            Ok(QuitOption::NotQuitting)
+        Event::Input { key, time } => {
+            match app.mode() {
+                // Normal and Visual mode keys make up commands of their own
+                AppMode::Normal | AppMode::Visual => {
+                    app.handle_vi_key(key);
+                    return Ok(QuitOption::NotQuitting);
+                }
+                // As are the answers to `:s///c`
+                AppMode::Command(CommandMode::Confirm) => {
+                    app.confirm_replace(key);
+                    return Ok(QuitOption::NotQuitting);
+                }
+                AppMode::Command(CommandMode::SaveBeforeQuit) => return Ok(app.confirm_quit(key)),
+                AppMode::Command(CommandMode::Recover) => return Ok(app.confirm_recover(key)),
+                AppMode::Command(CommandMode::Reload) => {
+                    app.confirm_reload(key);
+                    return Ok(QuitOption::NotQuitting);
+                }
+                _ => {}
+            }
+            match keymap_mode(app) {
+                Some(mode) => {
+                    let result = keymap.resolve(mode, key, time);
+                    Ok(handle_key_result(result, time, app, keymap, page_height))
+                }
+                None => Ok(QuitOption::NotQuitting),
+            }
         }
-        Event::Input {
-            key: Key::Right, ..
-        } => {
-            app.move_cursor_right();
-            Ok(QuitOption::NotQuitting)
+        // Only where keys would go through the keymap, like any other input
+        Event::ShiftedInput { key } => match (keymap_mode(app), keymap::shifted_action(key)) {
+            (Some(_), Some(action)) => Ok(perform_action(action, app, page_height)),
+            _ => Ok(QuitOption::NotQuitting),
+        },
+    }
+}
+
+// Bindings that apply in the app's mode, if keys go through the keymap
+fn keymap_mode(app: &App) -> Option<KeymapMode> {
+    match app.mode() {
+        AppMode::Edit if app.is_modal() => Some(KeymapMode::Insert),
+        AppMode::Edit => Some(KeymapMode::Edit),
+        AppMode::Command(mode) if !mode.asks_question() => Some(KeymapMode::Command),
+        _ => None,
+    }
+}
+
+fn handle_key_result(
+    result: KeyResult,
+    time: SystemTime,
+    app: &mut App,
+    keymap: &mut Keymap,
+    page_height: usize,
+) -> QuitOption {
+    match result {
+        KeyResult::Action(action) => perform_action(action, app, page_height),
+        KeyResult::Pending => QuitOption::NotQuitting,
+        // Anything not bound to an action is typed as text
+        KeyResult::Unbound(keys) => {
+            for key in keys {
+                if let Key::Char(c) = key {
+                    app.add_char(c);
+                }
+            }
+            QuitOption::NotQuitting
         }
-        Event::Input { key: Key::Left, .. } => {
-            app.move_cursor_left();
-            Ok(QuitOption::NotQuitting)
+        KeyResult::ActionThen(action, keys) => {
+            if perform_action(action, app, page_height) == QuitOption::Quitting {
+                return QuitOption::Quitting;
+            }
+            // The action may have changed the mode the keys are read in
+            for key in keys {
+                let mode = match keymap_mode(app) {
+                    Some(mode) => mode,
+                    None => break,
+                };
+                let result = keymap.resolve(mode, key, time);
+                if handle_key_result(result, time, app, keymap, page_height) == QuitOption::Quitting
+                {
+                    return QuitOption::Quitting;
+                }
+            }
+            QuitOption::NotQuitting
         }
-        _ => Ok(QuitOption::NotQuitting),
     }
 }
+
+fn perform_action(action: Action, app: &mut App, page_height: usize) -> QuitOption {
+    match action {
+        Action::Quit => return app.quit(false),
+        Action::Save => app.handle_regular_save(),
+        Action::SaveAs => app.handle_save_as_new_file(),
+        Action::OpenFile => app.handle_open_file(),
+        Action::CommandLine => app.handle_command_line(),
+        Action::NormalMode => app.enter_normal_mode(),
+        Action::SubmitCommand => return app.submit_command(),
+        Action::CancelCommand => app.cancel_command(),
+        Action::Undo => app.undo(),
+        Action::Redo => app.redo(),
+        Action::DeleteBack => app.remove_char(),
+        Action::Cut => app.cut(),
+        Action::Copy => app.copy(),
+        Action::Paste => app.paste(),
+        Action::PastePrevious => app.paste_previous(),
+        Action::MoveLeft => app.move_cursor_left(),
+        Action::MoveRight => app.move_cursor_right(),
+        Action::MoveUp => app.move_cursor_up(),
+        Action::MoveDown => app.move_cursor_down(),
+        Action::SelectLeft => app.select_left(),
+        Action::SelectRight => app.select_right(),
+        Action::SelectUp => app.select_up(),
+        Action::SelectDown => app.select_down(),
+        Action::AddCursorBelow => app.add_cursor_below(),
+        Action::AddCursorAtNextMatch => app.add_cursor_at_next_match(),
+        Action::SearchForward => app.start_search(SearchDirection::Forward),
+        Action::SearchBackward => app.start_search(SearchDirection::Backward),
+        Action::SearchNext => app.search_next(false, 1),
+        Action::SearchPrevious => app.search_next(true, 1),
+        Action::PageUp => app.page_up(page_height),
+        Action::PageDown => app.page_down(page_height),
+    }
+    QuitOption::NotQuitting
+}
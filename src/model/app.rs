@@ -1,25 +1,182 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use termion::event::Key;
+
 use super::buffer::Buffer;
-use super::cursor::Cursor;
+use super::command::{Address, Command, LineRange, Substitute};
+use super::diff;
+use super::encoding::{self, Encoding};
+use super::lineending::LineEnding;
+use super::nodelist::BufferType;
+use super::original::{self, Original};
+use super::registers::{Register, Registers};
+use super::search::{self, Replacement, Search, SearchDirection};
+use super::swap::{self, Swap};
+use super::vi::{self, InsertAt, Motion, Operator, ViCommand, ViState};
+use crate::utils::clipboard::{Clipboard, CommandClipboard, Osc52Clipboard};
+use crate::utils::save;
+use crate::utils::stamp::FileStamp;
+use crate::utils::QuitOption;
+
+// Lines kept between the cursor and the top or bottom of the screen
+pub const DEFAULT_SCROLL_MARGIN: usize = 3;
+
+// Least time between writes of the swap file while edits are being made
+pub const SWAP_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CommandMode {
+    Read,
+    Write,
+    Execute,
+    // The command buffer holds a pattern, searched for as it is typed
+    Search(SearchDirection),
+    // Asking whether to make each replacement of a `:s///c`
+    Confirm,
+    // Asking whether to save unsaved changes before quitting
+    SaveBeforeQuit,
+    // Asking whether to recover the edits in a swap file left behind
+    Recover,
+    // Asking whether to reload the file after something else changed it
+    Reload,
+}
+
+impl CommandMode {
+    // Whether the mode asks a question answered with a single key, rather
+    // than having a line typed into the command buffer
+    pub fn asks_question(self) -> bool {
+        matches!(
+            self,
+            CommandMode::Confirm
+                | CommandMode::SaveBeforeQuit
+                | CommandMode::Recover
+                | CommandMode::Reload
+        )
+    }
+}
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum AppMode {
+    // Typing inserts text. With modal editing on this is vi's Insert mode.
     Edit,
-    Command,
+    // Vi style modes, only entered with modal editing on
+    Normal,
+    Visual,
+    Command(CommandMode),
+}
+
+// What the gutter shows next to each line
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    // Distance from the cursor line, which itself shows its absolute number
+    Relative,
 }
 
 pub struct App {
     buffer: Buffer,
-    cursor: Cursor,
+    command_buffer: Buffer,
     app_mode: AppMode,
+    file_path: Option<String>,
+    message: Option<String>,
+    scroll_margin: usize,
+    line_numbers: LineNumbers,
+    // Whether Esc leaves Edit mode for Normal mode rather than the command line
+    modal: bool,
+    vi: ViState,
+    registers: Registers,
+    // Host clipboard that the unnamed register is kept in step with
+    clipboard: Option<Box<dyn Clipboard>>,
+    // Last search made, for `n` and `N`
+    last_search: Option<Search>,
+    // Where the cursor was when the search being typed was started
+    search_origin: usize,
+    // Whether matches of the last search are highlighted
+    highlight_search: bool,
+    pending_replace: Option<PendingReplace>,
+    // The paste just made, which `paste_previous` can swap for older ones
+    paste_cycle: Option<PasteCycle>,
+    // Whether to quit once the file name being prompted for is saved to
+    quit_after_save: bool,
+    // Whether saving keeps the old contents of the file as `file~`
+    backup: bool,
+    // Swap file holding the unsaved edits, once one has been written
+    swap_file: Option<PathBuf>,
+    // Pieces the buffer was made of when the swap file was last written
+    swapped_pieces: Option<Vec<(BufferType, usize, usize)>>,
+    last_swap: SystemTime,
+    // Swap file found when opening the file, until it is recovered or not
+    pending_recovery: Option<(PathBuf, Swap)>,
+    // Swap file that isn't ours to write: one that an editor still running
+    // elsewhere keeps for the same file, or one that couldn't be read
+    foreign_swap: Option<PathBuf>,
+    // The file as it was last read or written, to notice other changes to it
+    disk_stamp: Option<FileStamp>,
+    // Whether the file changed on disk and the user has yet to say whether
+    // to reload it
+    changed_on_disk: bool,
+    // Differences from the buffer to the file on disk, shown in place of
+    // the text while asking whether to reload it
+    diff: Option<Buffer>,
+}
+
+// Text pasted at `start` from entry `index` of the yank ring
+struct PasteCycle {
+    start: usize,
+    text: String,
+    index: usize,
+}
+
+// A `:s` with the `c` flag, going through its matches one at a time. The
+// accepted ones are all made at the end so they undo together.
+struct PendingReplace {
+    replacements: Vec<Replacement>,
+    // Index of the match being asked about
+    next: usize,
+    accepted: Vec<Replacement>,
 }
 
 impl App {
-    pub fn new() -> App {
-        App {
+    pub fn new(args: &[String]) -> App {
+        let mut app = App {
             buffer: Buffer::new(),
-            cursor: Cursor::new(),
+            command_buffer: Buffer::new(),
             app_mode: AppMode::Edit,
+            file_path: None,
+            message: None,
+            scroll_margin: DEFAULT_SCROLL_MARGIN,
+            line_numbers: LineNumbers::Absolute,
+            modal: false,
+            vi: ViState::new(),
+            registers: Registers::new(),
+            clipboard: None,
+            last_search: None,
+            search_origin: 0,
+            highlight_search: false,
+            pending_replace: None,
+            paste_cycle: None,
+            quit_after_save: false,
+            backup: false,
+            swap_file: None,
+            swapped_pieces: None,
+            last_swap: SystemTime::UNIX_EPOCH,
+            pending_recovery: None,
+            foreign_swap: None,
+            disk_stamp: None,
+            changed_on_disk: false,
+            diff: None,
+        };
+
+        // The first argument is the name of the program itself
+        if let Some(file_path) = args.get(1) {
+            app.load_file(file_path.clone());
         }
+        app
     }
 
     // App should only release immutable references to the buffer?
@@ -27,35 +184,1462 @@ impl App {
         &self.buffer
     }
 
-    pub fn app_mode(&self) -> AppMode {
+    // Buffer whose text is on screen, which is usually the one being edited
+    pub fn shown_buffer(&self) -> &Buffer {
+        self.diff.as_ref().unwrap_or(&self.buffer)
+    }
+
+    pub fn changed_on_disk(&self) -> bool {
+        self.changed_on_disk
+    }
+
+    pub fn command_buffer(&self) -> &Buffer {
+        &self.command_buffer
+    }
+
+    pub fn mode(&self) -> AppMode {
         self.app_mode
     }
 
-    pub fn get_text_as_iter(&self) -> Vec<String> {
-        vec![self.buffer.as_str()]
+    pub fn set_mode(&mut self, app_mode: AppMode) {
+        if self.app_mode == AppMode::Visual && app_mode != AppMode::Visual {
+            self.buffer.clear_anchor();
+        }
+        // vi commands only work with a single cursor
+        if matches!(app_mode, AppMode::Normal | AppMode::Visual) {
+            self.buffer.clear_extra_cursors();
+        }
+        self.app_mode = app_mode;
+        self.command_buffer = Buffer::new();
+    }
+
+    pub fn file_path(&self) -> Option<&String> {
+        self.file_path.as_ref()
+    }
+
+    // Latest status or error message to be shown to the user
+    pub fn message(&self) -> Option<&String> {
+        self.message.as_ref()
+    }
+
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    pub fn scroll_margin(&self) -> usize {
+        self.scroll_margin
+    }
+
+    pub fn line_numbers(&self) -> LineNumbers {
+        self.line_numbers
+    }
+
+    pub fn is_modal(&self) -> bool {
+        self.modal
+    }
+
+    // Turns vi style modal editing on or off, starting out in Normal mode
+    // or Edit mode respectively
+    pub fn set_modal(&mut self, modal: bool) {
+        self.modal = modal;
+        self.vi.reset();
+        self.set_mode(if modal {
+            AppMode::Normal
+        } else {
+            AppMode::Edit
+        });
+    }
+
+    // Picks the host clipboard from a `:set clipboard=` value: "none",
+    // "osc52", or a copy command and optional paste command separated by a
+    // comma such as "wl-copy,wl-paste -n"
+    pub fn set_clipboard_option(&mut self, value: &str) {
+        self.clipboard = match value.trim() {
+            "" | "none" => None,
+            "osc52" => Some(Box::new(Osc52Clipboard::new())),
+            spec => CommandClipboard::from_spec(spec)
+                .map(|clipboard| Box::new(clipboard) as Box<dyn Clipboard>),
+        };
+    }
+
+    // Escape sequences from the host clipboard, such as OSC 52, that the
+    // view has to write to the terminal
+    pub fn take_terminal_output(&mut self) -> String {
+        self.clipboard
+            .as_mut()
+            .map_or_else(String::new, |clipboard| clipboard.take_terminal_output())
+    }
+
+    // Start and end offsets of the Visual mode selection, which includes
+    // the grapheme cluster under the cursor
+    pub fn visual_range(&self) -> Option<(usize, usize)> {
+        if self.app_mode != AppMode::Visual {
+            return None;
+        }
+        self.buffer.selection()
+    }
+
+    #[cfg(test)]
+    pub fn get_buffer_text(&self) -> String {
+        self.buffer.as_str()
+    }
+
+    pub fn get_command_buffer_text(&self) -> String {
+        self.command_buffer.as_str()
+    }
+
+    #[cfg(test)]
+    pub fn get_text_based_on_mode(&self) -> Vec<String> {
+        match self.app_mode {
+            AppMode::Command(_) => vec![self.get_command_buffer_text()],
+            _ => vec![self.get_buffer_text()],
+        }
+    }
+
+    pub fn init_new_file(file_path: String) -> io::Result<()> {
+        File::create(file_path)?;
+        Ok(())
+    }
+
+    // Opens the file named in the command buffer
+    pub fn open_file(&mut self) {
+        let file_path = self.get_command_buffer_text();
+        self.load_file(file_path);
+        self.leave_command_mode();
+    }
+
+    // Saves to the file named in the command buffer when prompting for
+    // one, otherwise to the file that is currently open. Returns whether
+    // the file was written.
+    pub fn save_file(&mut self) -> bool {
+        let file_path = match (self.app_mode, &self.file_path) {
+            (AppMode::Command(CommandMode::Write), _) => self.get_command_buffer_text(),
+            (_, Some(file_path)) => file_path.clone(),
+            (_, None) => return false,
+        };
+
+        self.leave_command_mode();
+        self.write_file(file_path)
+    }
+
+    pub fn handle_regular_save(&mut self) {
+        match (self.app_mode, &self.file_path) {
+            (AppMode::Command(CommandMode::Write), _) | (_, Some(_)) => {
+                self.save_file();
+            }
+            (_, None) => self.handle_save_as_new_file(),
+        }
+    }
+
+    pub fn handle_save_as_new_file(&mut self) {
+        self.set_mode(AppMode::Command(CommandMode::Write));
+    }
+
+    pub fn handle_open_file(&mut self) {
+        self.set_mode(AppMode::Command(CommandMode::Read));
+    }
+
+    // With extra cursors about, the first Esc only gets rid of them
+    pub fn handle_command_line(&mut self) {
+        if self.app_mode == AppMode::Edit && !self.buffer.extra_cursors().is_empty() {
+            self.buffer.clear_extra_cursors();
+            return;
+        }
+        self.set_mode(AppMode::Command(CommandMode::Execute));
+    }
+
+    // Starts typing a search pattern on the command line. The cursor
+    // jumps to the first match as the pattern is typed.
+    pub fn start_search(&mut self, direction: SearchDirection) {
+        if let AppMode::Command(_) = self.app_mode {
+            return;
+        }
+        self.search_origin = self.buffer.cursor_position();
+        self.set_mode(AppMode::Command(CommandMode::Search(direction)));
+    }
+
+    // Carries out whatever the command buffer was prompting for
+    pub fn submit_command(&mut self) -> QuitOption {
+        match self.app_mode {
+            AppMode::Command(CommandMode::Read) => self.open_file(),
+            AppMode::Command(CommandMode::Write) => {
+                let quit = mem::take(&mut self.quit_after_save);
+                if self.save_file() && quit {
+                    return QuitOption::Quitting;
+                }
+            }
+            AppMode::Command(CommandMode::Execute) => return self.execute_command(),
+            AppMode::Command(CommandMode::Search(direction)) => self.submit_search(direction),
+            _ => {}
+        }
+        QuitOption::NotQuitting
+    }
+
+    // An empty pattern searches for the last one again
+    fn submit_search(&mut self, direction: SearchDirection) {
+        let pattern = self.get_command_buffer_text();
+        self.leave_command_mode();
+
+        let pattern = match (pattern.is_empty(), &self.last_search) {
+            (false, _) => pattern,
+            (true, Some(last_search)) => last_search.pattern.clone(),
+            (true, None) => {
+                self.message = Some(String::from("No previous search pattern"));
+                return;
+            }
+        };
+        let search = Search::new(pattern, direction);
+        self.search_from(self.search_origin, &search);
+        self.highlight_search = true;
+        self.last_search = Some(search);
+    }
+
+    // Repeats the last search `count` times, the other way if `reverse`
+    pub fn search_next(&mut self, reverse: bool, count: usize) {
+        let mut search = match &self.last_search {
+            Some(search) => search.clone(),
+            None => {
+                self.message = Some(String::from("No previous search pattern"));
+                return;
+            }
+        };
+        if reverse {
+            search.direction = search.direction.reversed();
+        }
+
+        // Saying the search wrapped holds even if later repeats didn't
+        self.highlight_search = true;
+        let mut wrapped_message = None;
+        for _ in 0..count {
+            if !self.search_from(self.buffer.cursor_position(), &search) {
+                return;
+            }
+            wrapped_message = self.message.take().or(wrapped_message);
+        }
+        self.message = wrapped_message;
+    }
+
+    // Moves the cursor to the next match after `from`, saying so if the
+    // search had to wrap around. Returns false if there is no match.
+    fn search_from(&mut self, from: usize, search: &Search) -> bool {
+        let regex = match search::compile(&search.pattern, false) {
+            Ok(regex) => regex,
+            Err(e) => {
+                self.message = Some(e);
+                return false;
+            }
+        };
+        match search::find_next(&self.buffer, &regex, from, search.direction) {
+            Some(found) => {
+                self.buffer.move_cursor_to(found.start);
+                self.message = match (found.wrapped, search.direction) {
+                    (false, _) => None,
+                    (true, SearchDirection::Forward) => {
+                        Some(String::from("Search hit BOTTOM, continuing at TOP"))
+                    }
+                    (true, SearchDirection::Backward) => {
+                        Some(String::from("Search hit TOP, continuing at BOTTOM"))
+                    }
+                };
+                true
+            }
+            None => {
+                self.buffer.move_cursor_to(from);
+                self.message = Some(format!("Pattern not found: {}", search.pattern));
+                false
+            }
+        }
+    }
+
+    // Moves the cursor to the first match of the pattern typed so far, or
+    // back to where the search started if there is none
+    fn update_incremental_search(&mut self) {
+        let direction = match self.app_mode {
+            AppMode::Command(CommandMode::Search(direction)) => direction,
+            _ => return,
+        };
+
+        // Half typed patterns are often not valid yet, so those just find
+        // nothing
+        let pattern = self.get_command_buffer_text();
+        let target = search::compile(&pattern, false)
+            .ok()
+            .filter(|_| !pattern.is_empty())
+            .and_then(|regex| {
+                search::find_next(&self.buffer, &regex, self.search_origin, direction)
+            })
+            .map_or(self.search_origin, |found| found.start);
+        self.buffer.move_cursor_to(target);
+    }
+
+    // Pattern whose matches should be highlighted: the one being typed, or
+    // else the last one searched for unless `:noh` was given since
+    pub fn search_highlight(&self) -> Option<String> {
+        match (self.app_mode, &self.last_search) {
+            (AppMode::Command(CommandMode::Search(_)), _) => {
+                Some(self.get_command_buffer_text()).filter(|pattern| !pattern.is_empty())
+            }
+            (_, Some(search)) if self.highlight_search => Some(search.pattern.clone()),
+            _ => None,
+        }
+    }
+
+    // Parses and runs the command line, e.g. `:w`, `:e file` or `:q`
+    fn execute_command(&mut self) -> QuitOption {
+        let input = self.get_command_buffer_text();
+        self.leave_command_mode();
+
+        let command = match Command::parse(&input) {
+            Ok(command) => command,
+            Err(e) => {
+                self.message = Some(e);
+                return QuitOption::NotQuitting;
+            }
+        };
+
+        match command {
+            Command::Write(file_path) => {
+                self.write_command(file_path);
+            }
+            Command::WriteQuit(file_path) => {
+                if self.write_command(file_path) {
+                    return QuitOption::Quitting;
+                }
+            }
+            Command::Edit { force: false, .. } | Command::EditWithEncoding { force: false, .. }
+                if self.buffer.is_modified() =>
+            {
+                self.message = Some(String::from(
+                    "No write since last change (add ! to override)",
+                ));
+            }
+            Command::Edit { file_path, .. } => self.load_file(file_path),
+            Command::EditWithEncoding {
+                encoding,
+                file_path,
+                ..
+            } => self.edit_with_encoding(&encoding, file_path),
+            Command::Quit { force } => return self.quit(force),
+            Command::Set { option, value } => self.set_option(&option, value.as_deref()),
+            Command::NoHighlight => self.highlight_search = false,
+            Command::Substitute(substitute) => self.substitute(substitute),
+        }
+        QuitOption::NotQuitting
+    }
+
+    // Replaces every match in the range at once, or asks about each one
+    // first with the `c` flag. An empty pattern is the last one searched for.
+    fn substitute(&mut self, substitute: Substitute) {
+        let typed = match (substitute.pattern.is_empty(), &self.last_search) {
+            (false, _) => substitute.pattern.clone(),
+            (true, Some(last_search)) => last_search.pattern.clone(),
+            (true, None) => {
+                self.message = Some(String::from("No previous search pattern"));
+                return;
+            }
+        };
+        // The case flag goes into the pattern so that `n` finds the same
+        // matches afterwards
+        let pattern = if substitute.ignore_case {
+            format!("(?i){}", typed)
+        } else {
+            typed.clone()
+        };
+        let regex = match search::compile(&pattern, false) {
+            Ok(regex) => regex,
+            Err(e) => {
+                self.message = Some(e);
+                return;
+            }
+        };
+        let (first_line, last_line) = match self.line_range(substitute.range) {
+            Ok(lines) => lines,
+            Err(e) => {
+                self.message = Some(e);
+                return;
+            }
+        };
+
+        let lines: Vec<(usize, usize)> = (first_line..=last_line)
+            .map(|line| {
+                (
+                    self.buffer.offset_of_line(line),
+                    self.buffer.offset_of_line(line + 1),
+                )
+            })
+            .collect();
+        let text = self.buffer.as_str();
+        let replacements = search::replacements(
+            &text,
+            &lines,
+            &regex,
+            &substitute.replacement,
+            substitute.global,
+        );
+        self.last_search = Some(Search::new(pattern, SearchDirection::Forward));
+        self.highlight_search = true;
+
+        if replacements.is_empty() {
+            self.message = Some(format!("Pattern not found: {}", typed));
+        } else if substitute.confirm {
+            self.search_origin = self.buffer.cursor_position();
+            self.pending_replace = Some(PendingReplace {
+                replacements,
+                next: 0,
+                accepted: Vec::new(),
+            });
+            self.set_mode(AppMode::Command(CommandMode::Confirm));
+            self.show_pending_replace();
+        } else {
+            self.apply_replacements(replacements);
+        }
+    }
+
+    // First and last lines of `range`, counted from 0
+    fn line_range(&self, range: LineRange) -> Result<(usize, usize), String> {
+        let num_lines = self.buffer.num_lines();
+        let line = |address| match address {
+            Address::Line(line) if (1..=num_lines).contains(&line) => Ok(line - 1),
+            Address::Line(_) => Err(String::from("Invalid range")),
+            Address::Current => Ok(self.buffer.current_line()),
+            Address::Last => Ok(num_lines - 1),
+        };
+        let (start, end) = (line(range.start)?, line(range.end)?);
+        Ok((start.min(end), start.max(end)))
+    }
+
+    // Selects the match being asked about
+    fn show_pending_replace(&mut self) {
+        let pending = match &self.pending_replace {
+            Some(pending) => pending,
+            None => return,
+        };
+        let current = pending.replacements[pending.next].clone();
+
+        self.buffer.clear_anchor();
+        self.buffer.move_cursor_to(current.end);
+        self.buffer.set_anchor(false);
+        self.buffer.move_cursor_to(current.start);
+        self.message = Some(format!("Replace with {} (y/n/a/q/l)?", current.text));
+    }
+
+    // Answers for the match being asked about: `y` replaces it, `n` skips
+    // it, `a` replaces it and all the rest, `l` replaces it and stops and
+    // `q` stops
+    pub fn confirm_replace(&mut self, key: Key) {
+        let pending = match &mut self.pending_replace {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        let current = pending.replacements[pending.next].clone();
+        let finished = match key {
+            Key::Char('y') => {
+                pending.accepted.push(current);
+                false
+            }
+            Key::Char('n') => false,
+            Key::Char('a') => {
+                let rest = pending.replacements.drain(pending.next..);
+                pending.accepted.extend(rest);
+                true
+            }
+            Key::Char('l') => {
+                pending.accepted.push(current);
+                true
+            }
+            Key::Char('q') | Key::Esc | Key::Ctrl('c') => true,
+            _ => return,
+        };
+
+        pending.next += 1;
+        if finished || pending.next >= pending.replacements.len() {
+            self.finish_pending_replace();
+        } else {
+            self.show_pending_replace();
+        }
+    }
+
+    fn finish_pending_replace(&mut self) {
+        let pending = match self.pending_replace.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        self.buffer.clear_anchor();
+        self.leave_command_mode();
+        if pending.accepted.is_empty() {
+            self.buffer.move_cursor_to(self.search_origin);
+            self.message = None;
+        } else {
+            self.apply_replacements(pending.accepted);
+        }
+    }
+
+    // Makes the replacements as a single undo step, leaving the cursor at
+    // the start of the last line changed as vi does
+    fn apply_replacements(&mut self, replacements: Vec<Replacement>) {
+        let mut lines: Vec<usize> = replacements.iter().map(|found| found.line).collect();
+        lines.dedup();
+        let edits: Vec<(usize, usize, String)> = replacements
+            .into_iter()
+            .map(|found| (found.start, found.end, found.text))
+            .collect();
+
+        self.buffer.replace_ranges(&edits);
+        let (line_start, _) = self.buffer.line_bounds(self.buffer.cursor_position());
+        self.buffer.move_cursor_to(line_start);
+        self.message = Some(format!(
+            "{} on {}",
+            plural(edits.len(), "substitution"),
+            plural(lines.len(), "line")
+        ));
+    }
+
+    fn set_option(&mut self, option: &str, value: Option<&str>) {
+        let number = value.map(str::parse::<usize>);
+        match (option, number) {
+            ("tabstop", Some(Ok(tab_stop))) | ("ts", Some(Ok(tab_stop))) if tab_stop > 0 => {
+                self.buffer.set_tab_stop(tab_stop)
+            }
+            ("scrolloff", Some(Ok(scroll_margin))) | ("so", Some(Ok(scroll_margin))) => {
+                self.scroll_margin = scroll_margin
+            }
+            ("clipboard", Some(_)) | ("cb", Some(_)) => {
+                self.set_clipboard_option(value.unwrap_or_default())
+            }
+            // Without a value, says which one the file has
+            ("fileformat", _) | ("ff", _) => match value.map(LineEnding::from_name) {
+                Some(Some(line_ending)) => self.buffer.set_line_ending(line_ending),
+                Some(None) => {
+                    self.message = Some(format!(
+                        "Invalid argument: {}={}",
+                        option,
+                        value.unwrap_or_default()
+                    ))
+                }
+                None => {
+                    self.message = Some(format!("fileformat={}", self.buffer.line_ending().name()))
+                }
+            },
+            ("fileencoding", _) | ("fenc", _) => match value.map(Encoding::from_name) {
+                Some(Some(encoding)) => self.buffer.set_encoding(encoding),
+                Some(None) => {
+                    self.message = Some(format!(
+                        "Invalid argument: {}={}",
+                        option,
+                        value.unwrap_or_default()
+                    ))
+                }
+                None => {
+                    self.message = Some(format!("fileencoding={}", self.buffer.encoding().name()))
+                }
+            },
+            ("backup", None) | ("bk", None) => self.backup = true,
+            ("nobackup", None) | ("nobk", None) => self.backup = false,
+            ("vi", None) => self.set_modal(true),
+            ("novi", None) => self.set_modal(false),
+            ("number", None) | ("nu", None) => self.line_numbers = LineNumbers::Absolute,
+            ("relativenumber", None) | ("rnu", None) => self.line_numbers = LineNumbers::Relative,
+            ("nonumber", None) | ("nonu", None) | ("norelativenumber", None) | ("nornu", None) => {
+                self.line_numbers = LineNumbers::Off
+            }
+            ("tabstop", _) | ("ts", _) | ("scrolloff", _) | ("so", _) => {
+                self.message = Some(format!(
+                    "Invalid argument: {}={}",
+                    option,
+                    value.unwrap_or_default()
+                ))
+            }
+            _ => self.message = Some(format!("Unknown option: {}", option)),
+        }
+    }
+
+    // `:e ++enc=name`, which reads the file again or another one in the
+    // given encoding
+    fn edit_with_encoding(&mut self, name: &str, file_path: Option<String>) {
+        let encoding = match Encoding::from_name(name) {
+            Some(encoding) => encoding,
+            None => {
+                self.message = Some(format!("Invalid encoding: {}", name));
+                return;
+            }
+        };
+        match file_path.or_else(|| self.file_path.clone()) {
+            Some(file_path) => self.load_file_as(file_path, Some(encoding)),
+            None => self.message = Some(String::from("No file name")),
+        }
+    }
+
+    // Maps a large file that can be used as it is, and reads anything else
+    fn read_file(file_path: &str, encoding: Option<Encoding>) -> io::Result<Original> {
+        let mut file = File::open(file_path)?;
+        if encoding.is_none_or(|encoding| encoding == Encoding::Utf8) {
+            if let Some(original) = Original::map(&file, original::MAP_MIN_LEN)? {
+                return Ok(original);
+            }
+        }
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        Ok(Original::new(contents))
+    }
+
+    // Writes to `file_path` if given, otherwise to the current file
+    fn write_command(&mut self, file_path: Option<String>) -> bool {
+        match file_path.or_else(|| self.file_path.clone()) {
+            Some(file_path) => self.write_file(file_path),
+            None => {
+                self.message = Some(String::from("No file name"));
+                false
+            }
+        }
+    }
+
+    // Quits unless there are unsaved changes, in which case it asks
+    // whether to save them first. `force` quits regardless, as `:q!` does.
+    pub fn quit(&mut self, force: bool) -> QuitOption {
+        if force || !self.buffer.is_modified() {
+            return QuitOption::Quitting;
+        }
+
+        self.set_mode(AppMode::Command(CommandMode::SaveBeforeQuit));
+        let file_name = self.file_path.as_deref().unwrap_or("[No Name]");
+        self.message = Some(format!(
+            "Save changes to \"{}\"? (y)es, (n)o, (c)ancel",
+            file_name
+        ));
+        QuitOption::NotQuitting
+    }
+
+    // Answers the prompt left by `quit`. Saving a file without a name
+    // prompts for one and quits once it has been written.
+    pub fn confirm_quit(&mut self, key: Key) -> QuitOption {
+        match key {
+            Key::Char('y') => {
+                self.message = None;
+                if self.file_path.is_none() {
+                    self.handle_save_as_new_file();
+                    self.quit_after_save = true;
+                } else if self.save_file() {
+                    return QuitOption::Quitting;
+                }
+            }
+            Key::Char('n') => return QuitOption::Quitting,
+            Key::Char('c') | Key::Esc | Key::Ctrl('c') => {
+                self.message = None;
+                self.leave_command_mode();
+            }
+            _ => {}
+        }
+        QuitOption::NotQuitting
+    }
+
+    // Answers the prompt shown on opening a file with a swap file left
+    // behind. Recovering rebuilds the buffer from it, and deleting it
+    // carries on with the file as it is on disk. A swap file still in use
+    // by another editor can't be deleted, only left to it.
+    pub fn confirm_recover(&mut self, key: Key) -> QuitOption {
+        let (path, swap) = match self.pending_recovery.take() {
+            Some(pending) => pending,
+            None => return QuitOption::NotQuitting,
+        };
+        let in_use = swap.owner_is_running();
+
+        match key {
+            Key::Char('r') => match swap.restore(&mut self.buffer) {
+                Ok(()) => {
+                    if in_use {
+                        self.foreign_swap = Some(path);
+                    } else {
+                        self.swap_file = Some(path);
+                    }
+                    self.message = Some(String::from(
+                        "Recovered unsaved changes, save the file to keep them",
+                    ));
+                }
+                Err(e) => {
+                    self.message = Some(format!("{}: {}", e, recovery_choices(in_use)));
+                    self.pending_recovery = Some((path, swap));
+                    return QuitOption::NotQuitting;
+                }
+            },
+            Key::Char('o') if in_use => {
+                self.message = Some(format!(
+                    "Editing without a swap file, as \"{}\" is in use",
+                    path.display()
+                ));
+                self.foreign_swap = Some(path);
+            }
+            Key::Char('d') if !in_use => {
+                if let Err(e) = fs::remove_file(&path) {
+                    self.message = Some(format!("Unable to delete swap file: {}", e));
+                } else {
+                    self.message = None;
+                }
+            }
+            Key::Char('q') => return QuitOption::Quitting,
+            _ => {
+                self.pending_recovery = Some((path, swap));
+                return QuitOption::NotQuitting;
+            }
+        }
+        self.leave_command_mode();
+        QuitOption::NotQuitting
+    }
+
+    // Called on every tick of the event loop, to notice changes to the
+    // file on disk and keep the swap file up to date without writing it on
+    // every key. The check waits until any command being typed is done.
+    pub fn tick(&mut self, time: SystemTime) {
+        // Whatever wants to write to the file the buffer is mapped from is
+        // kept waiting until the buffer has a copy of its own
+        if self.buffer.mapped_file_wanted() {
+            self.buffer.read_in_original();
+        }
+        if !matches!(self.app_mode, AppMode::Command(_)) {
+            self.check_disk();
+        }
+
+        let due = time
+            .duration_since(self.last_swap)
+            .map_or(true, |elapsed| elapsed >= SWAP_INTERVAL);
+        if due {
+            self.last_swap = time;
+            self.update_swap();
+        }
+    }
+
+    // Writes the unsaved edits to the swap file if they changed since it
+    // was last written, or removes it once there are none. A swap file
+    // found on opening is left alone until the user decides what to do.
+    pub fn update_swap(&mut self) {
+        if self.pending_recovery.is_some() {
+            return;
+        }
+        if !self.buffer.is_modified() {
+            self.remove_swap();
+            return;
+        }
+
+        let path = match &self.file_path {
+            Some(file_path) => swap::swap_path(Path::new(file_path)),
+            None => return,
+        };
+        if self.foreign_swap.as_ref() == Some(&path) {
+            return;
+        }
+        let pieces: Vec<_> = self.buffer.pieces().collect();
+        if self.swapped_pieces.as_ref() == Some(&pieces) {
+            return;
+        }
+        // Saving under another name leaves the old swap file behind
+        if self
+            .swap_file
+            .as_ref()
+            .is_some_and(|swap_file| *swap_file != path)
+        {
+            self.remove_swap();
+        }
+
+        // A failed write isn't retried until the next edit, so as not to
+        // keep covering up other messages
+        self.swapped_pieces = Some(pieces);
+        let swap = Swap::from_buffer(&self.buffer);
+        let written = if self.swap_file.as_ref() == Some(&path) {
+            swap.write(&path)
+        } else {
+            swap.create(&path)
+        };
+        match written {
+            Ok(()) => self.swap_file = Some(path),
+            Err(e) => self.message = Some(format!("Unable to write swap file: {}", e)),
+        }
+    }
+
+    // Removes the swap file written for the buffer, if any
+    pub fn remove_swap(&mut self) {
+        self.swapped_pieces = None;
+        if let Some(path) = self.swap_file.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    // Whether the file was changed by something else since it was last read
+    // or written, in which case the user is asked whether to reload it
+    fn check_disk(&mut self) -> bool {
+        if self.changed_on_disk {
+            return true;
+        }
+        let (file_path, stamp) = match (&self.file_path, self.disk_stamp) {
+            (Some(file_path), Some(stamp)) => (file_path.clone(), stamp),
+            _ => return false,
+        };
+        // A file that went missing has nothing to reload from
+        let metadata = match fs::metadata(&file_path) {
+            Ok(metadata) if !stamp.matches(&metadata) => metadata,
+            _ => return false,
+        };
+        // A file that changed length changed for sure, without reading it
+        let current = if metadata.len() == stamp.len {
+            match FileStamp::read(Path::new(&file_path)) {
+                Ok(current) => current,
+                Err(_) => return false,
+            }
+        } else {
+            FileStamp::unread(&metadata)
+        };
+        // Stamped now, whatever the answer, so that keeping the buffer as it
+        // is needn't look at the file again
+        self.disk_stamp = Some(current);
+        // Only touched, so there is nothing to ask about
+        if stamp.same_contents(&current) {
+            return false;
+        }
+
+        self.changed_on_disk = true;
+        self.set_mode(AppMode::Command(CommandMode::Reload));
+        self.message = Some(format!(
+            "\"{}\" changed on disk: (r)eload, (k)eep ours, (d)iff",
+            file_path
+        ));
+        true
+    }
+
+    // Answers the prompt left by `check_disk`. Keeping the buffer as it is
+    // lets it be saved over the file, and the diff can be shown and hidden
+    // before deciding.
+    pub fn confirm_reload(&mut self, key: Key) {
+        let file_path = match &self.file_path {
+            Some(file_path) => file_path.clone(),
+            None => return,
+        };
+
+        match key {
+            Key::Char('r') => {
+                self.changed_on_disk = false;
+                self.diff = None;
+                self.leave_command_mode();
+                let encoding = self.buffer.encoding();
+                self.load_file_as(file_path.clone(), Some(encoding));
+                if self.message.is_none() {
+                    self.message = Some(format!("\"{}\" reloaded", file_path));
+                }
+            }
+            Key::Char('k') | Key::Esc | Key::Ctrl('c') => {
+                self.changed_on_disk = false;
+                self.diff = None;
+                self.message = None;
+                self.leave_command_mode();
+            }
+            Key::Char('d') => {
+                if self.diff.take().is_some() {
+                    return;
+                }
+                // Compared as the buffer sees it, so that lines don't all
+                // differ by how they end
+                let theirs = fs::read(&file_path).map(|bytes| {
+                    let text = self.buffer.encoding().decode(&bytes).text;
+                    self.buffer.line_ending().to_lf(text)
+                });
+                match theirs {
+                    Ok(theirs) => {
+                        let on_disk = format!("{} (on disk)", file_path);
+                        let diff =
+                            diff::unified_diff(&self.buffer.as_str(), &theirs, "buffer", &on_disk);
+                        let mut diff = Buffer::with_contents(diff);
+                        diff.set_tab_stop(self.buffer.tab_stop());
+                        self.diff = Some(diff);
+                    }
+                    Err(e) => self.message = Some(format!("Unable to read file: {}", e)),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Offers to recover the edits in a swap file left behind for the file
+    // just opened, most likely by an editor that crashed
+    fn check_swap(&mut self) {
+        self.foreign_swap = None;
+        let path = match &self.file_path {
+            Some(file_path) => swap::swap_path(Path::new(file_path)),
+            None => return,
+        };
+        // A dangling symlink counts, and fails to read below
+        if fs::symlink_metadata(&path).is_err() {
+            return;
+        }
+
+        match Swap::read(&path) {
+            Ok(swap) => {
+                self.set_mode(AppMode::Command(CommandMode::Recover));
+                let found = if swap.owner_is_running() {
+                    "in use by process"
+                } else {
+                    "from process"
+                };
+                self.message = Some(format!(
+                    "Found swap file \"{}\" {} {}: (r)ecover, {}",
+                    path.display(),
+                    found,
+                    swap.pid,
+                    recovery_choices(swap.owner_is_running())
+                ));
+                self.pending_recovery = Some((path, swap));
+            }
+            // It may still hold someone's edits, so it is never written over
+            Err(e) => {
+                self.message = Some(format!(
+                    "Unable to read swap file \"{}\": {}, editing without one",
+                    path.display(),
+                    e
+                ));
+                self.foreign_swap = Some(path);
+            }
+        }
     }
 
+    // Cancelling a search puts the cursor back where it started
+    pub fn cancel_command(&mut self) {
+        match self.app_mode {
+            AppMode::Command(CommandMode::Search(_)) => {
+                self.buffer.move_cursor_to(self.search_origin)
+            }
+            AppMode::Command(CommandMode::Confirm) => {
+                self.finish_pending_replace();
+                return;
+            }
+            _ => {}
+        }
+        self.leave_command_mode();
+    }
+
+    // Back to editing once a command is done with, in Normal mode if
+    // modal editing is on
+    fn leave_command_mode(&mut self) {
+        self.quit_after_save = false;
+        if let AppMode::Command(_) = self.app_mode {
+            self.set_mode(if self.modal {
+                AppMode::Normal
+            } else {
+                AppMode::Edit
+            });
+        }
+    }
+
+    pub fn enter_normal_mode(&mut self) {
+        self.vi.reset();
+        self.set_mode(AppMode::Normal);
+    }
+
+    // Feeds a key typed in Normal or Visual mode to the vi state machine,
+    // carrying out the command once the keys add up to one
+    pub fn handle_vi_key(&mut self, key: Key) {
+        let visual = self.app_mode == AppMode::Visual;
+        if let Some(command) = self.vi.feed(key, visual) {
+            self.execute_vi(command);
+        }
+    }
+
+    fn execute_vi(&mut self, command: ViCommand) {
+        match command {
+            ViCommand::Move(motion, count) => self.vi_move(motion, count),
+            ViCommand::Operate(operator, motion, count) => {
+                let (start, end) = vi::motion_range(&self.buffer, operator, motion, count);
+                self.vi_operate(operator, start, end, motion.is_linewise());
+            }
+            ViCommand::OperateLines(operator, count) => {
+                let (start, end) = vi::count_lines_range(&self.buffer, operator, count);
+                self.vi_operate(operator, start, end, true);
+            }
+            ViCommand::OperateSelection(operator) => {
+                if let Some((start, end)) = self.visual_range() {
+                    self.set_mode(AppMode::Normal);
+                    self.vi_operate(operator, start, end, false);
+                }
+            }
+            ViCommand::DeleteChar(count) => {
+                let (start, end) = vi::chars_range(&self.buffer, count);
+                self.vi_operate(Operator::Delete, start, end, false);
+            }
+            ViCommand::Paste { before, count } => self.vi_paste(before, count),
+            ViCommand::Insert(insert_at) => self.vi_insert(insert_at),
+            ViCommand::ToggleVisual => {
+                if self.app_mode == AppMode::Visual {
+                    self.set_mode(AppMode::Normal);
+                } else {
+                    self.buffer.clear_anchor();
+                    self.buffer.set_anchor(true);
+                    self.set_mode(AppMode::Visual);
+                }
+            }
+            ViCommand::Escape => self.set_mode(AppMode::Normal),
+            ViCommand::Undo(count) => {
+                for _ in 0..count {
+                    if !self.buffer.undo() {
+                        self.message = Some(String::from("Already at oldest change"));
+                        break;
+                    }
+                }
+            }
+            ViCommand::Redo(count) => {
+                for _ in 0..count {
+                    if !self.buffer.redo() {
+                        self.message = Some(String::from("Already at newest change"));
+                        break;
+                    }
+                }
+            }
+            ViCommand::CommandLine => self.handle_command_line(),
+            ViCommand::Search(direction) => self.start_search(direction),
+            ViCommand::SearchNext { reverse, count } => self.search_next(reverse, count),
+        }
+    }
+
+    fn vi_move(&mut self, motion: Motion, count: Option<usize>) {
+        let times = count.unwrap_or(1);
+        match motion {
+            Motion::Up => (0..times).for_each(|_| self.buffer.move_cursor_up()),
+            Motion::Down => (0..times).for_each(|_| self.buffer.move_cursor_down()),
+            _ => {
+                let target = vi::motion_target(&self.buffer, motion, count);
+                self.buffer.move_cursor_to(target);
+            }
+        }
+    }
+
+    // Carries out `operator` on the text between `start` and `end`, keeping
+    // what it took in the registers
+    fn vi_operate(&mut self, operator: Operator, start: usize, end: usize, linewise: bool) {
+        let text = match operator {
+            Operator::Delete | Operator::Change => self.buffer.delete_range(start, end),
+            Operator::Yank => {
+                self.buffer.move_cursor_to(start);
+                self.buffer.text_between(start, end)
+            }
+        };
+
+        let text = if linewise { as_whole_lines(text) } else { text };
+        self.store_register(self.vi.command_register(), Register::new(text, linewise));
+        if operator == Operator::Change {
+            self.set_mode(AppMode::Edit);
+        }
+    }
+
+    // Pastes a register `count` times, after or before the cursor. Whole
+    // lines go below or above the cursor line, and in Visual mode the
+    // selection is replaced.
+    fn vi_paste(&mut self, before: bool, count: usize) {
+        let register = match self.register_to_paste(self.vi.command_register()) {
+            Some(register) => register,
+            None => {
+                self.message = Some(String::from("Nothing in register"));
+                return;
+            }
+        };
+        let mut text = register.text.repeat(count);
+
+        if let Some((start, end)) = self.visual_range() {
+            self.set_mode(AppMode::Normal);
+            self.buffer.delete_range(start, end);
+        } else if register.linewise {
+            let line = self.buffer.current_line();
+            let insert_at = self
+                .buffer
+                .offset_of_line(if before { line } else { line + 1 });
+            let mut first_line_start = insert_at;
+
+            // The last line has no line break to paste after, so one goes
+            // in front of the pasted lines instead
+            let len = self.buffer.len();
+            if insert_at == len
+                && !self.buffer.is_empty()
+                && self.buffer.text_between(len - 1, len) != "\n"
+            {
+                text.pop();
+                text.insert(0, '\n');
+                first_line_start += 1;
+            }
+
+            self.buffer.move_cursor_to(insert_at);
+            self.buffer.insert_str(text);
+            self.buffer.move_cursor_to(first_line_start);
+            return;
+        } else if !before {
+            self.vi_move(Motion::Right, None);
+        }
+
+        // The cursor ends up on the last pasted char, like vi
+        self.buffer.insert_str(text);
+        let end = self.buffer.cursor_position();
+        self.buffer
+            .move_cursor_to(end - self.buffer.grapheme_len_before(end));
+    }
+
+    fn vi_insert(&mut self, insert_at: InsertAt) {
+        match insert_at {
+            InsertAt::Cursor => {}
+            InsertAt::AfterCursor => self.vi_move(Motion::Right, None),
+            InsertAt::LineStart => self.vi_move(Motion::LineStart, None),
+            InsertAt::LineEnd => self.vi_move(Motion::LineEnd, None),
+            InsertAt::LineBelow => {
+                self.vi_move(Motion::LineEnd, None);
+                self.buffer.insert('\n');
+            }
+            InsertAt::LineAbove => {
+                self.vi_move(Motion::LineStart, None);
+                self.buffer.insert('\n');
+                self.buffer.move_cursor_left();
+            }
+        }
+        self.set_mode(AppMode::Edit);
+    }
+
+    // Typing over a selection replaces it
     pub fn add_char(&mut self, c: char) {
-        self.buffer.insert(&self.cursor, c);
-        self.cursor.move_right();
+        let buffer = self.active_buffer();
+        buffer.delete_selection();
+        buffer.insert(c);
+        self.update_incremental_search();
     }
 
     pub fn remove_char(&mut self) {
-        if self.cursor.column() > 1 {
-            self.cursor.move_left();
-            self.buffer.remove(&self.cursor);
+        let buffer = self.active_buffer();
+        if buffer.delete_selection().is_none() {
+            buffer.remove();
         }
+        self.update_incremental_search();
     }
 
-    pub fn move_cursor_left(&mut self) {
-        if self.cursor.column() > 0 {
-            self.cursor.move_left();
+    // Moves the selection into the unnamed register
+    pub fn cut(&mut self) {
+        match self.active_buffer().delete_selection() {
+            Some(text) => self.store_register(None, Register::new(text, false)),
+            None => self.message = Some(String::from("Nothing selected")),
+        }
+    }
+
+    // Copies the selection into the unnamed register
+    pub fn copy(&mut self) {
+        let buffer = self.active_buffer();
+        let text = match buffer.selected_text() {
+            Some(text) => text,
+            None => {
+                self.message = Some(String::from("Nothing selected"));
+                return;
+            }
+        };
+        buffer.clear_anchor();
+        self.store_register(None, Register::new(text, false));
+    }
+
+    // Inserts the unnamed register at the cursor, over the selection if
+    // there is one
+    pub fn paste(&mut self) {
+        let text = match self.register_to_paste(None) {
+            Some(register) => register.text,
+            None => return,
+        };
+        let buffer = self.active_buffer();
+        buffer.delete_selection();
+        buffer.insert_str(text.clone());
+        self.paste_cycle =
+            if self.app_mode == AppMode::Edit && self.buffer.extra_cursors().is_empty() {
+                let end = self.buffer.cursor_position();
+                Some(PasteCycle {
+                    start: end - text.len(),
+                    text,
+                    index: 0,
+                })
+            } else {
+                None
+            };
+        self.update_incremental_search();
+    }
+
+    // Swaps the text just pasted for the yank or cut before it on the ring,
+    // going round to the latest again after the oldest. Pastes as usual if
+    // the last thing done wasn't a paste.
+    pub fn paste_previous(&mut self) {
+        let buffer = &self.buffer;
+        let cycle = self.paste_cycle.take().filter(|cycle| {
+            let end = cycle.start + cycle.text.len();
+            self.app_mode == AppMode::Edit
+                && buffer.extra_cursors().is_empty()
+                && buffer.cursor_position() == end
+                && end <= buffer.len()
+                && buffer.text_between(cycle.start, end) == cycle.text
+        });
+        let cycle = match cycle {
+            Some(cycle) => cycle,
+            None => return self.paste(),
+        };
+
+        let index = cycle.index + 1;
+        let text = match self.registers.ring_entry(index) {
+            Some(register) => register.text.clone(),
+            None => return,
+        };
+        let end = cycle.start + cycle.text.len();
+        self.buffer
+            .replace_ranges(&[(cycle.start, end, text.clone())]);
+        self.buffer.move_cursor_to(cycle.start + text.len());
+        self.paste_cycle = Some(PasteCycle {
+            start: cycle.start,
+            text,
+            index,
+        });
+    }
+
+    // Keeps yanked or cut text in the registers and on the host clipboard
+    fn store_register(&mut self, name: Option<char>, register: Register) {
+        if let Some(clipboard) = &mut self.clipboard {
+            if let Err(e) = clipboard.copy(&register.text) {
+                self.message = Some(format!("Unable to copy to clipboard: {}", e));
+            }
+        }
+        self.registers.store(name, register);
+    }
+
+    // Register `name` to paste from. The unnamed register gives way to
+    // the host clipboard once something else has been copied there.
+    fn register_to_paste(&mut self, name: Option<char>) -> Option<Register> {
+        let unnamed = matches!(name, None | Some('"'));
+        if let (true, Some(clipboard)) = (unnamed, &mut self.clipboard) {
+            match clipboard.paste() {
+                Ok(Some(text)) => {
+                    let is_newer = self
+                        .registers
+                        .get(None)
+                        .is_none_or(|register| register.text != text);
+                    if is_newer && !text.is_empty() {
+                        return Some(Register::new(text, false));
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => self.message = Some(format!("Unable to paste from clipboard: {}", e)),
+            }
         }
+        self.registers.get(name).cloned()
+    }
+
+    pub fn undo(&mut self) {
+        if !self.active_buffer().undo() {
+            self.message = Some(String::from("Already at oldest change"));
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if !self.active_buffer().redo() {
+            self.message = Some(String::from("Already at newest change"));
+        }
+    }
+
+    // Moving without shift drops the selection
+    pub fn move_cursor_left(&mut self) {
+        self.active_buffer().clear_anchor();
+        self.active_buffer().move_cursor_left();
     }
 
     pub fn move_cursor_right(&mut self) {
-        if self.cursor.column() < self.buffer.len_at_col(&self.cursor) - 1 {
-            self.cursor.move_right();
+        self.active_buffer().clear_anchor();
+        self.active_buffer().move_cursor_right();
+    }
+
+    pub fn move_cursor_up(&mut self) {
+        self.active_buffer().clear_anchor();
+        self.active_buffer().move_cursor_up();
+    }
+
+    pub fn move_cursor_down(&mut self) {
+        self.active_buffer().clear_anchor();
+        self.active_buffer().move_cursor_down();
+    }
+
+    // Extends the selection, starting one at the cursor if there is none
+    pub fn select_left(&mut self) {
+        self.active_buffer().set_anchor(false);
+        self.active_buffer().move_cursor_left();
+    }
+
+    pub fn select_right(&mut self) {
+        self.active_buffer().set_anchor(false);
+        self.active_buffer().move_cursor_right();
+    }
+
+    pub fn select_up(&mut self) {
+        self.active_buffer().set_anchor(false);
+        self.active_buffer().move_cursor_up();
+    }
+
+    pub fn select_down(&mut self) {
+        self.active_buffer().set_anchor(false);
+        self.active_buffer().move_cursor_down();
+    }
+
+    pub fn add_cursor_below(&mut self) {
+        if self.app_mode != AppMode::Edit {
+            return;
+        }
+        if !self.buffer.add_cursor_below() {
+            self.message = Some(String::from("No line below the last cursor"));
+        }
+    }
+
+    // Adds a cursor at the next occurrence of the selected text
+    pub fn add_cursor_at_next_match(&mut self) {
+        if self.app_mode != AppMode::Edit {
+            return;
+        }
+        if self.buffer.selection().is_none() {
+            self.message = Some(String::from("Nothing selected"));
+        } else if !self.buffer.add_cursor_at_next_match() {
+            self.message = Some(String::from("No more matches"));
+        }
+    }
+
+    // Moves the cursor up by a screenful of `lines`
+    pub fn page_up(&mut self, lines: usize) {
+        for _ in 0..lines {
+            self.active_buffer().move_cursor_up();
+        }
+    }
+
+    pub fn page_down(&mut self, lines: usize) {
+        for _ in 0..lines {
+            self.active_buffer().move_cursor_down();
+        }
+    }
+
+    fn active_buffer(&mut self) -> &mut Buffer {
+        match self.app_mode {
+            AppMode::Command(_) => &mut self.command_buffer,
+            _ => &mut self.buffer,
+        }
+    }
+
+    fn write_file(&mut self, file_path: String) -> bool {
+        // Saving over changes made by something else needs asking first
+        if self.file_path.as_ref() == Some(&file_path) && self.check_disk() {
+            return false;
+        }
+
+        let contents = match self.buffer.file_contents() {
+            Ok(contents) => contents,
+            Err(e) => {
+                self.message = Some(format!("Unable to write file: {}", e));
+                return false;
+            }
+        };
+        // Writing in place over the file the buffer is mapped from would
+        // change the text under it, so it is read in first
+        if let Ok(metadata) = fs::metadata(&file_path) {
+            if self.buffer.is_mapped_from(&metadata) {
+                self.buffer.read_in_original();
+            }
+        }
+        match save::write_atomically(Path::new(&file_path), &contents, self.backup) {
+            Ok(()) => {
+                self.disk_stamp = fs::metadata(&file_path)
+                    .ok()
+                    .map(|metadata| FileStamp::new(&contents, &metadata));
+                self.buffer.set_unmodified();
+                self.message = Some(format!("\"{}\" written", file_path));
+                self.file_path = Some(file_path);
+                true
+            }
+            Err(e) => {
+                self.message = Some(format!("Unable to write file: {}", e));
+                false
+            }
+        }
+    }
+
+    // Reads `file_path` into the buffer, creating it if it doesn't exist.
+    // Failures are reported through the message rather than panicking.
+    fn load_file(&mut self, file_path: String) {
+        self.load_file_as(file_path, None);
+    }
+
+    // Reads `file_path` as `encoding`, or as whatever encoding it looks to
+    // be in if none is given
+    fn load_file_as(&mut self, file_path: String, encoding: Option<Encoding>) {
+        let contents = if Path::new(&file_path).exists() {
+            App::read_file(&file_path, encoding)
+        } else {
+            App::init_new_file(file_path.clone()).map(|_| Original::new(Vec::new()))
+        };
+
+        match contents {
+            Ok(contents) => {
+                self.remove_swap();
+                // A mapped file isn't gone through just to stamp it
+                self.disk_stamp = fs::metadata(&file_path).ok().map(|metadata| {
+                    if contents.is_mapped() {
+                        FileStamp::unread(&metadata)
+                    } else {
+                        FileStamp::with_checksum(contents.checksum(), &metadata)
+                    }
+                });
+                self.changed_on_disk = false;
+                self.diff = None;
+                self.message = None;
+                let buffer = if contents.is_mapped() {
+                    Buffer::with_original(contents)
+                } else {
+                    let decoded = encoding::decode(contents.bytes(0..contents.len()), encoding);
+                    if decoded.invalid {
+                        self.message = Some(format!(
+                            "\"{}\" isn't all valid {}, shown as \u{FFFD} where it isn't",
+                            file_path,
+                            decoded.encoding.name()
+                        ));
+                    }
+                    Buffer::with_decoded(decoded)
+                };
+                let tab_stop = self.buffer.tab_stop();
+                self.buffer = buffer;
+                self.buffer.set_tab_stop(tab_stop);
+                self.file_path = Some(file_path);
+                self.check_swap();
+            }
+            Err(e) => self.message = Some(format!("Unable to open file: {}", e)),
         }
     }
 }
+
+// Text taken from whole lines, ending in a line break even if the last
+// line of the text had none of its own
+fn as_whole_lines(mut text: String) -> String {
+    if text.ends_with('\n') {
+        return text;
+    }
+    if text.starts_with('\n') {
+        text.remove(0);
+    }
+    text.push('\n');
+    text
+}
+
+// What else can be done with a swap file found on opening a file, which
+// is only deleted if no other editor is using it
+fn recovery_choices(in_use: bool) -> &'static str {
+    if in_use {
+        "(o)pen anyway, (q)uit"
+    } else {
+        "(d)elete it, (q)uit"
+    }
+}
+
+// "1 line" or "2 lines"
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/app_tests.rs"]
+mod tests;
@@ -1,47 +1,1504 @@
-use super::cursor::Cursor;
+use std::cmp::Reverse;
+use std::fs::Metadata;
+use std::mem;
 
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::cursor::{Cursor, ExtraCursor};
+use super::encoding::{Decoded, Encoding};
+use super::history::{CursorState, EditKind, History, NodeChange};
+use super::lineending::LineEnding;
+use super::nodelist::{self, BufferNode, BufferType, NodeList};
+use super::original::Original;
+use super::taggedtext::TaggedText;
+use super::texttag::{Tag, TextTag};
+use crate::utils::width::{self, DEFAULT_TAB_STOP};
+
+// Length of text read at a time when looking for a match
+const FIND_BLOCK_LEN: usize = 1 << 16;
+
+// Piece table backed text buffer. The original contents are never
+// modified; every insertion is appended to `added_str` and spliced
+// into the document through `node_list`.
+//
+// Text is stored as UTF-8 bytes and node offsets are byte offsets, but
+// the cursor moves, deletes and counts columns in grapheme clusters.
+#[derive(Debug)]
 pub struct Buffer {
-    contents: Vec<Vec<char>>,
+    original_str: Original,
+    added_str: Vec<u8>,
+    node_list: NodeList,
+    cursor: Cursor,
+    // Cursors besides the main one, in order of position. Edits are made
+    // at all of them; undoing goes back to just the main cursor.
+    extra_cursors: Vec<ExtraCursor>,
+    current_line: usize,
+    history: History,
+    tab_stop: usize,
+    // Pieces the text was made of when it was loaded or last saved. The
+    // stores are append-only, so the text is unchanged if the pieces are.
+    saved_pieces: Vec<(BufferType, usize, usize)>,
+    // How lines end in the file. The text itself always uses "\n".
+    line_ending: LineEnding,
+    saved_line_ending: LineEnding,
+    // Encoding of the file, which the text is converted to on saving
+    encoding: Encoding,
+    saved_encoding: Encoding,
+    // Whether the selection takes in the grapheme cluster under the
+    // cursor, as in vi's Visual mode
+    inclusive_selection: bool,
 }
 
 impl Buffer {
     pub fn new() -> Buffer {
         Buffer {
-            contents: vec![Vec::new()],
+            original_str: Original::new(Vec::new()),
+            added_str: Vec::new(),
+            node_list: NodeList::new(),
+            cursor: Cursor::new(),
+            extra_cursors: Vec::new(),
+            current_line: 0,
+            history: History::new(),
+            tab_stop: DEFAULT_TAB_STOP,
+            saved_pieces: Vec::new(),
+            line_ending: LineEnding::Lf,
+            saved_line_ending: LineEnding::Lf,
+            encoding: Encoding::Utf8,
+            saved_encoding: Encoding::Utf8,
+            inclusive_selection: false,
         }
     }
 
-    fn string_to_vec(string: String) -> Vec<char> {
-        string.chars().collect()
+    pub fn with_contents(contents: String) -> Buffer {
+        let mut buffer = Buffer::new();
+        if contents.is_empty() {
+            return buffer;
+        }
+
+        let node = BufferNode::new(
+            BufferType::Original,
+            0,
+            contents.len(),
+            Buffer::get_offsets(&contents),
+        );
+        buffer.node_list.insert_curr(node);
+        buffer.original_str = Original::new(contents.into_bytes());
+        buffer.set_unmodified();
+        buffer
     }
 
-    pub fn with_string(string: String) -> Buffer {
-        Buffer {
-            contents: vec![string.chars().collect()],
+    // Buffer over contents that are used as they are, such as a mapped
+    // file. They are split into the original's pieces, which don't even
+    // count their lines until they are needed.
+    pub fn with_original(original: Original) -> Buffer {
+        let nodes: Vec<_> = original
+            .pieces()
+            .into_iter()
+            .map(|range| BufferNode::lazy(BufferType::Original, range.start, range.len()))
+            .collect();
+
+        let mut buffer = Buffer::new();
+        if !nodes.is_empty() {
+            buffer.node_list = NodeList::with_contents(nodes);
         }
+        buffer.original_str = original;
+        buffer.set_unmodified();
+        buffer
     }
 
-    pub fn insert(&mut self, cursor: &Cursor, ch: char) {
-        self.contents[cursor.row()].insert(cursor.column(), ch);
+    // Buffer for the contents of a file decoded from `encoding`, which
+    // keeps to that and to the way lines end in it
+    pub fn with_file_contents(contents: String, encoding: Encoding) -> Buffer {
+        let line_ending = LineEnding::detect(&contents);
+        let mut buffer = Buffer::with_contents(line_ending.to_lf(contents));
+        buffer.line_ending = line_ending;
+        buffer.saved_line_ending = line_ending;
+        buffer.encoding = encoding;
+        buffer.saved_encoding = encoding;
+        buffer
     }
 
-    pub fn remove(&mut self, cursor: &Cursor) -> char {
-        self.contents[cursor.row()].remove(cursor.column())
+    // Buffer for a decoded file, which puts back any invalid bytes it had
+    // wherever they are still in the text on saving
+    pub fn with_decoded(decoded: Decoded) -> Buffer {
+        let line_ending = LineEnding::detect(&decoded.text);
+        let raw_bytes = decoded.raw_bytes.into_lf(&decoded.text, line_ending);
+        let mut buffer = Buffer::with_file_contents(decoded.text, decoded.encoding);
+        buffer.original_str.set_raw_bytes(raw_bytes);
+        buffer
     }
 
-    pub fn as_str(&self) -> String {
-        self.contents.iter().fold(String::new(), |mut acc, line| {
-            acc.push_str(&line.iter().cloned().collect::<String>());
-            acc.push_str("\n");
-            acc
-        })
+    // Bytes to be saved, with lines ending as they did in the file and in
+    // its encoding. Fails if the text has chars the encoding can't hold.
+    pub fn file_contents(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = self.encoding.bom().to_vec();
+        for node in self.node_list.iter() {
+            let start = node.index();
+            let runs = match node.from() {
+                BufferType::Original => self
+                    .original_str
+                    .raw_bytes_within(start, start + node.offset()),
+                BufferType::Added => Vec::new(),
+            };
+            let node_bytes = self.node_bytes(node);
+            let mut from = 0;
+            for run in runs {
+                self.encode_text(&node_bytes[from..run.offset - start], &mut bytes)?;
+                self.encoding.encode_raw_into(&run.bytes, &mut bytes);
+                from = run.offset - start + run.len;
+            }
+            self.encode_text(&node_bytes[from..], &mut bytes)?;
+        }
+        Ok(bytes)
+    }
+
+    fn encode_text(&self, text: &[u8], bytes: &mut Vec<u8>) -> Result<(), String> {
+        let text = String::from_utf8_lossy(text).into_owned();
+        self.encoding
+            .encode_into(&self.line_ending.apply_to(text), bytes)
+    }
+
+    // Puts the text back together from the pieces of the original
+    // contents and of `added` that made it up, as kept by `pieces`, while
+    // keeping the original contents the buffer already has. Returns false,
+    // leaving the buffer as it was, if any piece lies outside its store or
+    // splits a character. The result counts as modified.
+    pub fn restore_pieces(&mut self, added: String, pieces: &[(BufferType, usize, usize)]) -> bool {
+        let original = &self.original_str;
+        let store_len = |from| match from {
+            BufferType::Original => original.len(),
+            BufferType::Added => added.len(),
+        };
+        // Pieces of a mapped original can't run from one of its own pieces
+        // into another, and don't count their lines until they are needed
+        let bytes = |from, range: std::ops::Range<usize>| match from {
+            BufferType::Original => original.bytes(range),
+            BufferType::Added => &added.as_bytes()[range],
+        };
+        let mut nodes = Vec::new();
+        for &(from, start, len) in pieces {
+            let end = match start.checked_add(len) {
+                Some(end) if end <= store_len(from) => end,
+                _ => return false,
+            };
+            // UTF-8 continuation bytes are never the start of a character
+            let on_boundary =
+                |idx: usize| idx == store_len(from) || bytes(from, idx..idx + 1)[0] & 0xC0 != 0x80;
+            if !on_boundary(start) || !on_boundary(end) {
+                return false;
+            }
+            let mut node_start = start;
+            while node_start < end {
+                let node_end = match from {
+                    BufferType::Original => original.piece_end(node_start).min(end),
+                    BufferType::Added => end,
+                };
+                let len = node_end - node_start;
+                nodes.push(if from == BufferType::Original && original.is_mapped() {
+                    BufferNode::lazy(from, node_start, len)
+                } else {
+                    let newlines = bytes(from, node_start..node_end)
+                        .iter()
+                        .filter(|&&byte| byte == b'\n')
+                        .count();
+                    BufferNode::counted(from, node_start, len, newlines)
+                });
+                node_start = node_end;
+            }
+        }
+
+        self.node_list = if nodes.is_empty() {
+            NodeList::new()
+        } else {
+            NodeList::with_contents(nodes)
+        };
+        self.added_str = added.into_bytes();
+        self.history = History::new();
+        self.cursor = Cursor::new();
+        self.extra_cursors.clear();
+        self.current_line = 0;
+        true
+    }
+
+    // Offsets at which each line starts, relative to the start of `string`
+    pub fn get_offsets(string: &str) -> Vec<usize> {
+        nodelist::line_starts(string.as_bytes())
+    }
+
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
+    pub fn current_line(&self) -> usize {
+        self.current_line
     }
 
     pub fn num_lines(&self) -> usize {
-        self.contents.len()
+        1 + self
+            .node_list
+            .iter()
+            .map(|node| node.line_offsets_len(self.node_bytes(node)) - 1)
+            .sum::<usize>()
+    }
+
+    // Number of lines if every part of the text has counted its own, which
+    // a large file only does once it is gone through
+    pub fn num_lines_if_counted(&self) -> Option<usize> {
+        self.node_list
+            .iter()
+            .map(|node| node.newlines_if_counted())
+            .sum::<Option<usize>>()
+            .map(|newlines| newlines + 1)
+    }
+
+    // Number of lines, counting no more than `limit`, so that the rest of
+    // the text needn't be gone through
+    pub fn num_lines_up_to(&self, limit: usize) -> usize {
+        let mut lines = 1;
+        for node in self.node_list.iter() {
+            if lines >= limit {
+                break;
+            }
+            lines += node.line_offsets_len(self.node_bytes(node)) - 1;
+        }
+        lines.min(limit)
+    }
+
+    // Screen column of the cursor within its line
+    pub fn cursor_display_column(&self) -> usize {
+        self.display_column_at_cursor()
+    }
+
+    // Whether the text differs from when it was loaded or last saved.
+    // Undoing back to that point makes it unmodified again.
+    pub fn is_modified(&self) -> bool {
+        self.line_ending != self.saved_line_ending
+            || self.encoding != self.saved_encoding
+            || self.pieces().ne(self.saved_pieces.iter().copied())
+    }
+
+    pub fn set_unmodified(&mut self) {
+        self.saved_pieces = self.pieces().collect();
+        self.saved_line_ending = self.line_ending;
+        self.saved_encoding = self.encoding;
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    // Changes how lines end once saved, which counts as a change to the file
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
     }
 
-    pub fn len_at_col(&self, cursor: &Cursor) -> usize {
-        self.contents[cursor.row()].len()
+    // Changes the encoding the text is saved in, which also counts as a
+    // change to the file
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    // Store, start and length of each node, which together pin down the text
+    pub fn pieces(&self) -> impl Iterator<Item = (BufferType, usize, usize)> + '_ {
+        self.node_list
+            .iter()
+            .map(|node| (node.from(), node.index(), node.offset()))
+    }
+
+    // Contents the buffer was created with, which edits leave untouched
+    pub fn original(&self) -> &Original {
+        &self.original_str
+    }
+
+    pub fn original_checksum(&self) -> u64 {
+        self.original_str.checksum()
+    }
+
+    // Whether the original contents are mapped from the file with
+    // `metadata`, which writing to in place would change under the buffer
+    pub fn is_mapped_from(&self, metadata: &Metadata) -> bool {
+        self.original_str.is_mapped_from(metadata)
+    }
+
+    // Whether something is waiting to write to the file the original
+    // contents are mapped from, which it can do once they are read in
+    pub fn mapped_file_wanted(&self) -> bool {
+        self.original_str.lease_broken()
+    }
+
+    // Copies the original contents into memory, so that the file they were
+    // mapped from can be written to
+    pub fn read_in_original(&mut self) {
+        if self.original_str.is_mapped() {
+            self.original_str = self.original_str.read_in();
+        }
+    }
+
+    // Everything ever inserted, in the order it was typed
+    pub fn added(&self) -> &[u8] {
+        &self.added_str
+    }
+
+    pub fn tab_stop(&self) -> usize {
+        self.tab_stop
+    }
+
+    pub fn set_tab_stop(&mut self, tab_stop: usize) {
+        self.tab_stop = tab_stop.max(1);
+    }
+
+    pub fn insert(&mut self, ch: char) {
+        self.insert_str(ch.to_string());
+    }
+
+    pub fn insert_str(&mut self, string: String) {
+        if string.is_empty() {
+            return;
+        }
+
+        let kind = match string.chars().count() {
+            1 if string.starts_with(char::is_whitespace) => EditKind::InsertSpace,
+            1 => EditKind::InsertWord,
+            _ => EditKind::Other,
+        };
+        self.edit_at_cursors(kind, |buffer| vec![buffer.insert_at_cursor(&string)]);
+    }
+
+    // Removes the grapheme cluster right before the cursor
+    pub fn remove(&mut self) {
+        self.edit_at_cursors(EditKind::Remove, Buffer::remove_at_cursor);
+    }
+
+    // Reverts the last group of edits and puts the cursor back where it
+    // was before them. Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo(&mut self.node_list) {
+            Some(state) => {
+                self.restore_cursor_state(state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Reapplies the last undone group of edits
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo(&mut self.node_list) {
+            Some(state) => {
+                self.restore_cursor_state(state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Starts a selection at the cursor unless one is already under way.
+    // Moving the cursor afterwards extends it.
+    pub fn set_anchor(&mut self, inclusive: bool) {
+        if self.cursor.anchor.is_none() {
+            self.cursor.anchor = Some(self.cursor_position());
+        }
+        for cursor in self.extra_cursors.iter_mut() {
+            cursor.anchor.get_or_insert(cursor.position);
+        }
+        self.inclusive_selection = inclusive;
+    }
+
+    pub fn clear_anchor(&mut self) {
+        self.cursor.anchor = None;
+        for cursor in self.extra_cursors.iter_mut() {
+            cursor.anchor = None;
+        }
+    }
+
+    // Start and end offsets of the text between the anchor and the cursor,
+    // or None if nothing is selected
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        self.selection_between(self.cursor.anchor?, self.cursor_position())
+    }
+
+    // Selections of every cursor, in order
+    pub fn selections(&self) -> Vec<(usize, usize)> {
+        let mut selections: Vec<(usize, usize)> = self
+            .extra_cursors
+            .iter()
+            .filter_map(|cursor| self.selection_between(cursor.anchor?, cursor.position))
+            .chain(self.selection())
+            .collect();
+        selections.sort_unstable();
+        selections
+    }
+
+    // Text selected by every cursor, a line apiece, or None if nothing is
+    // selected
+    pub fn selected_text(&self) -> Option<String> {
+        let selections = self.selections();
+        if selections.is_empty() {
+            return None;
+        }
+
+        let texts: Vec<String> = selections
+            .into_iter()
+            .map(|(start, end)| self.text_between(start, end))
+            .collect();
+        Some(texts.join("\n"))
+    }
+
+    // Removes the text selected by every cursor as a single undo step,
+    // returning it as `selected_text` does
+    pub fn delete_selection(&mut self) -> Option<String> {
+        let text = self.selected_text()?;
+        self.history.commit();
+        self.edit_at_cursors(EditKind::Other, |buffer| match buffer.selection() {
+            Some((start, end)) => {
+                buffer.cursor.anchor = None;
+                let change = buffer.splice_range(start, end);
+                buffer.place_cursor(start);
+                vec![change]
+            }
+            None => Vec::new(),
+        });
+        self.history.commit();
+        Some(text)
+    }
+
+    pub fn extra_cursors(&self) -> &[ExtraCursor] {
+        &self.extra_cursors
+    }
+
+    pub fn clear_extra_cursors(&mut self) {
+        self.extra_cursors.clear();
+    }
+
+    // Absolute offsets of every cursor, in order
+    pub fn cursor_positions(&self) -> Vec<usize> {
+        let mut positions: Vec<usize> = self
+            .extra_cursors
+            .iter()
+            .map(|cursor| cursor.position)
+            .collect();
+        positions.push(self.cursor_position());
+        positions.sort_unstable();
+        positions
+    }
+
+    // Adds a cursor on the line below the last one, as close to the main
+    // cursor's screen column as the line allows. Returns false if the last
+    // cursor is already on the last line.
+    pub fn add_cursor_below(&mut self) -> bool {
+        self.history.commit();
+        let main = self.as_extra_cursor();
+        let last = self
+            .extra_cursors
+            .iter()
+            .copied()
+            .chain(Some(main))
+            .max_by_key(|cursor| cursor.position)
+            .unwrap();
+
+        self.load_cursor(ExtraCursor {
+            anchor: None,
+            original_line_offset: main.original_line_offset,
+            ..last
+        });
+        let line = self.current_line;
+        self.step_down();
+        let added = Some(self.as_extra_cursor()).filter(|_| self.current_line != line);
+        self.load_cursor(main);
+
+        match added {
+            Some(cursor) => {
+                self.extra_cursors.push(cursor);
+                self.merge_cursors();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Gives the next occurrence of the main cursor's selection after the
+    // last cursor a cursor of its own, selecting it the same way round and
+    // wrapping back to the start of the text. Returns false if nothing is
+    // selected or every occurrence already has a cursor.
+    pub fn add_cursor_at_next_match(&mut self) -> bool {
+        let (start, end) = match self.selection() {
+            Some(selection) => selection,
+            None => return false,
+        };
+        self.history.commit();
+
+        let needle = self.text_between(start, end);
+        let selections = self.selections();
+        let from = selections
+            .iter()
+            .map(|&(_, end)| end)
+            .chain(self.cursor_positions())
+            .max()
+            .unwrap_or(0);
+        let taken = |idx: usize| {
+            selections
+                .iter()
+                .any(|&(start, end)| idx < end && start < idx + needle.len())
+        };
+
+        let match_start = match self.find_text(needle.as_bytes(), from, |idx| !taken(idx)) {
+            Some(idx) => idx,
+            None => return false,
+        };
+
+        // In vi's Visual mode the selection ends on the last grapheme
+        // cluster rather than after it
+        let match_end = match_start + needle.len();
+        let last = if self.inclusive_selection {
+            match_end - self.grapheme_len_before(match_end)
+        } else {
+            match_end
+        };
+        let (position, anchor) = if self.cursor.anchor.unwrap() <= self.cursor_position() {
+            (last, match_start)
+        } else {
+            (match_start, last)
+        };
+        self.extra_cursors.push(ExtraCursor {
+            position,
+            anchor: Some(anchor),
+            original_line_offset: self.display_column_at(position),
+        });
+        self.merge_cursors();
+        true
+    }
+
+    pub fn move_cursor_left(&mut self) {
+        self.history.commit();
+        self.for_each_cursor(Buffer::step_left);
+    }
+
+    fn step_left(&mut self) {
+        if self.node_list.is_empty() {
+            return;
+        }
+
+        let len = self.grapheme_len_before(self.cursor_position());
+        if len == 0 {
+            return;
+        }
+
+        let mut passed_newline = false;
+        for _ in 0..len {
+            passed_newline |= self.move_back_one() == b'\n';
+        }
+        self.normalize_cursor();
+
+        self.update_line_idx();
+        if passed_newline {
+            self.current_line -= 1;
+            self.cursor.line_offset = self.column_at_cursor();
+        } else {
+            self.cursor.line_offset -= 1;
+        }
+        self.cursor.original_line_offset = self.display_column_at_cursor();
+    }
+
+    pub fn move_cursor_right(&mut self) {
+        self.history.commit();
+        self.for_each_cursor(Buffer::step_right);
+    }
+
+    fn step_right(&mut self) {
+        if self.node_list.is_empty() {
+            return;
+        }
+
+        let len = self.grapheme_len_after(self.cursor_position());
+        if len == 0 {
+            return;
+        }
+
+        let mut passed_newline = false;
+        for _ in 0..len {
+            passed_newline |= self.move_forward_one() == b'\n';
+        }
+
+        self.update_line_idx();
+        if passed_newline {
+            self.current_line += 1;
+            self.cursor.line_offset = 0;
+        } else {
+            self.cursor.line_offset += 1;
+        }
+        self.cursor.original_line_offset = self.display_column_at_cursor();
+    }
+
+    pub fn move_cursor_up(&mut self) {
+        self.history.commit();
+        self.for_each_cursor(Buffer::step_up);
+    }
+
+    fn step_up(&mut self) {
+        if self.node_list.is_empty() || self.current_line == 0 {
+            return;
+        }
+
+        self.move_to_line_start();
+        self.move_back_one();
+        self.move_to_line_start();
+        self.cursor.line_offset = self.advance_within_line(self.cursor.original_line_offset);
+        self.update_line_idx();
+        self.current_line -= 1;
+    }
+
+    pub fn move_cursor_down(&mut self) {
+        self.history.commit();
+        self.for_each_cursor(Buffer::step_down);
+    }
+
+    fn step_down(&mut self) {
+        if self.node_list.is_empty() {
+            return;
+        }
+
+        let index = self.node_list.index();
+        let node_offset = self.cursor.node_offset;
+        if !self.move_to_next_line_start() {
+            self.node_list.shift_to_index(index);
+            self.cursor.node_offset = node_offset;
+            return;
+        }
+
+        self.cursor.line_offset = self.advance_within_line(self.cursor.original_line_offset);
+        self.update_line_idx();
+        self.current_line += 1;
+    }
+
+    // Puts the cursor at the absolute offset `pos`, or at the end of the
+    // text if it is shorter than that
+    pub fn move_cursor_to(&mut self, pos: usize) {
+        self.history.commit();
+        self.place_cursor(pos);
+    }
+
+    // Removes the text between the absolute offsets `start` and `end` as a
+    // single undo step, leaving the cursor at `start`. Returns the text that
+    // was removed. Any extra cursors are dropped, since their offsets would
+    // no longer point where they did.
+    pub fn delete_range(&mut self, start: usize, end: usize) -> String {
+        let end = end.min(self.len());
+        if start >= end {
+            return String::new();
+        }
+
+        let removed = self.text_between(start, end);
+        self.cursor.anchor = None;
+        self.extra_cursors.clear();
+        self.history.commit();
+        let before = self.cursor_state();
+        let change = self.splice_range(start, end);
+        self.place_cursor(start);
+
+        self.history
+            .record(EditKind::Other, change, before, self.cursor_state());
+        self.history.commit();
+        removed
+    }
+
+    // Puts each `(start, end, text)` of `edits` in place of the text
+    // between `start` and `end` as a single undo step. The edits must be in
+    // order and must not overlap. The cursor is left at the start of the
+    // last one and any extra cursors are dropped.
+    pub fn replace_ranges(&mut self, edits: &[(usize, usize, String)]) {
+        let last = match edits.last() {
+            Some(last) => last,
+            None => return,
+        };
+
+        self.cursor.anchor = None;
+        self.extra_cursors.clear();
+        self.history.commit();
+        let before = self.cursor_state();
+
+        // Going from the last edit to the first keeps the offsets of the
+        // ones still to do valid
+        let mut changes = Vec::new();
+        for (start, end, text) in edits.iter().rev() {
+            if start < end {
+                changes.push(self.splice_range(*start, *end));
+            }
+            self.place_cursor(*start);
+            if !text.is_empty() {
+                changes.push(self.insert_at_cursor(text));
+            }
+        }
+
+        let grown: usize = edits[..edits.len() - 1]
+            .iter()
+            .map(|(_, _, text)| text.len())
+            .sum();
+        let shrunk: usize = edits[..edits.len() - 1]
+            .iter()
+            .map(|(start, end, _)| end - start)
+            .sum();
+        self.place_cursor(last.0 + grown - shrunk);
+
+        self.history
+            .record_all(EditKind::Other, changes, before, self.cursor_state());
+        self.history.commit();
+    }
+
+    // Length of the text in bytes
+    pub fn len(&self) -> usize {
+        self.node_list.iter().map(|node| node.offset()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Text between the absolute offsets `start` and `end`
+    pub fn text_between(&self, start: usize, end: usize) -> String {
+        String::from_utf8_lossy(&self.bytes_between(start, end)).into_owned()
+    }
+
+    pub fn as_str(&self) -> String {
+        let bytes: Vec<u8> = self
+            .node_list
+            .iter()
+            .flat_map(|node| self.node_bytes(node).iter().cloned())
+            .collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    #[cfg(test)]
+    pub fn as_str_split_by_cursors(&self) -> Vec<String> {
+        if self.node_list.is_empty() {
+            return vec![String::new()];
+        }
+
+        let text = self.as_str();
+        let mut pieces = Vec::with_capacity(self.extra_cursors.len() + 2);
+        let mut piece_start = 0;
+        for position in self.cursor_positions() {
+            pieces.push(String::from(&text[piece_start..position]));
+            piece_start = position;
+        }
+        pieces.push(String::from(&text[piece_start..]));
+        pieces
+    }
+
+    pub fn as_tagged_text(&self) -> TaggedText {
+        let mut tags: Vec<TextTag> = self
+            .cursor_positions()
+            .into_iter()
+            .map(|position| {
+                let len = self.grapheme_len_after(position).max(1);
+                TextTag::new(Tag::Cursor, position, position + len)
+            })
+            .collect();
+        for (start, end) in self.selections() {
+            tags.push(TextTag::new(Tag::Highlighted, start, end));
+        }
+        TaggedText::new(self.as_str(), tags)
+    }
+
+    // Tagged text for `num_lines` lines starting at `first_line`, so only
+    // what fits on screen needs to be copied out of the piece table
+    pub fn as_tagged_text_for_lines(&self, first_line: usize, num_lines: usize) -> TaggedText {
+        let start = self.offset_of_line(first_line);
+        let end = self.offset_of_line(first_line + num_lines);
+        let text = String::from_utf8_lossy(&self.bytes_between(start, end)).into_owned();
+
+        let mut tags = Vec::new();
+        if (first_line..first_line + num_lines).contains(&self.current_line) {
+            let position = self.cursor_position();
+            let len = self.grapheme_len_after(position).max(1);
+            tags.push(TextTag::new(
+                Tag::Cursor,
+                position - start,
+                position - start + len,
+            ));
+        }
+        // Extra cursors are found by offset rather than by line. One at the
+        // very end of the text is still on the last line.
+        let len = self.len();
+        for cursor in self.extra_cursors.iter() {
+            let position = cursor.position;
+            if position >= start && (position < end || end == len) {
+                let grapheme_len = self.grapheme_len_after(position).max(1);
+                tags.push(TextTag::new(
+                    Tag::Cursor,
+                    position - start,
+                    position - start + grapheme_len,
+                ));
+            }
+        }
+        for (selection_start, selection_end) in self.selections() {
+            let selection_start = selection_start.max(start);
+            let selection_end = selection_end.min(end);
+            if selection_start < selection_end {
+                tags.push(TextTag::new(
+                    Tag::Highlighted,
+                    selection_start - start,
+                    selection_end - start,
+                ));
+            }
+        }
+        TaggedText::new(text, tags)
+    }
+
+    // Absolute offset at which line `line` starts, or the end of the text
+    // if there are not that many lines
+    pub fn offset_of_line(&self, line: usize) -> usize {
+        let mut lines_left = line;
+        let mut node_start = 0;
+        for node in self.node_list.iter() {
+            let newlines = node.line_offsets_len(self.node_bytes(node)) - 1;
+            if lines_left <= newlines {
+                return node_start + node.line_offset_at(self.node_bytes(node), lines_left);
+            }
+            lines_left -= newlines;
+            node_start += node.offset();
+        }
+        node_start
+    }
+
+    // Absolute offset of the cursor from the start of the text
+    pub fn cursor_position(&self) -> usize {
+        if self.node_list.is_empty() {
+            return 0;
+        }
+
+        let before: usize = self
+            .node_list
+            .iter_until_curr()
+            .map(|node| node.offset())
+            .sum();
+        before - self.node_list.get_curr().offset() + self.cursor.node_offset
+    }
+
+    // Moves the cursor to `pos` without touching the undo history
+    fn place_cursor(&mut self, pos: usize) {
+        if self.node_list.is_empty() {
+            self.cursor = Cursor::new();
+            self.current_line = 0;
+            return;
+        }
+
+        let last_idx = self.node_list.len() - 1;
+        let mut index = last_idx;
+        let mut node_start = 0;
+        let mut lines_before = 0;
+        for (idx, node) in self.node_list.iter().enumerate() {
+            let node_end = node_start + node.offset();
+            if pos < node_end || idx == last_idx {
+                index = idx;
+                break;
+            }
+            lines_before += node.line_offsets_len(self.node_bytes(node)) - 1;
+            node_start = node_end;
+        }
+
+        self.node_list.shift_to_index(index);
+        let node_offset = pos - node_start;
+        self.cursor.node_offset = node_offset.min(self.node_list.get_curr().offset());
+        self.update_line_idx();
+        self.current_line = lines_before + self.cursor.line_idx;
+        self.cursor.line_offset = self.column_at_cursor();
+        self.cursor.original_line_offset = self.display_column_at_cursor();
+    }
+
+    fn cursor_state(&self) -> CursorState {
+        CursorState {
+            node_index: if self.node_list.is_empty() {
+                0
+            } else {
+                self.node_list.index()
+            },
+            cursor: self.cursor,
+            current_line: self.current_line,
+        }
+    }
+
+    fn restore_cursor_state(&mut self, state: CursorState) {
+        if !self.node_list.is_empty() {
+            self.node_list.shift_to_index(state.node_index);
+        }
+        self.cursor = state.cursor;
+        self.cursor.anchor = None;
+        self.extra_cursors.clear();
+        self.current_line = state.current_line;
+    }
+
+    // Makes `edit` at every cursor as a single undo step. Cursors are
+    // edited from last to first so the text before each one is as it was,
+    // and those already done are shifted by however much the text grew or
+    // shrank.
+    fn edit_at_cursors<F>(&mut self, kind: EditKind, mut edit: F)
+    where
+        F: FnMut(&mut Buffer) -> Vec<NodeChange>,
+    {
+        let before = self.cursor_state();
+        let changes = if self.extra_cursors.is_empty() {
+            edit(self)
+        } else {
+            let mut cursors: Vec<(bool, ExtraCursor)> = self
+                .extra_cursors
+                .drain(..)
+                .map(|cursor| (false, cursor))
+                .collect();
+            cursors.push((true, self.as_extra_cursor()));
+            cursors.sort_by_key(|&(_, cursor)| Reverse(cursor.position));
+
+            let mut changes = Vec::new();
+            let mut done: Vec<(bool, ExtraCursor)> = Vec::with_capacity(cursors.len());
+            for (is_main, cursor) in cursors {
+                let len_before = self.len();
+                self.load_cursor(cursor);
+                changes.extend(edit(self));
+
+                let len_after = self.len();
+                let shift = |offset: usize| (offset + len_after).saturating_sub(len_before);
+                for (_, cursor) in done.iter_mut() {
+                    cursor.position = shift(cursor.position);
+                    cursor.anchor = cursor.anchor.map(shift);
+                }
+                done.push((is_main, self.as_extra_cursor()));
+            }
+
+            let mut main = None;
+            for (is_main, mut cursor) in done {
+                if is_main {
+                    main = Some(cursor);
+                } else {
+                    cursor.original_line_offset = self.display_column_at(cursor.position);
+                    self.extra_cursors.push(cursor);
+                }
+            }
+            self.load_cursor(main.unwrap());
+            self.cursor.original_line_offset = self.display_column_at_cursor();
+            self.merge_cursors();
+            changes
+        };
+
+        self.history
+            .record_all(kind, changes, before, self.cursor_state());
+    }
+
+    // Runs `step` with each cursor in turn as the main one
+    fn for_each_cursor(&mut self, step: fn(&mut Buffer)) {
+        if !self.extra_cursors.is_empty() {
+            let main = self.as_extra_cursor();
+            for cursor in mem::take(&mut self.extra_cursors) {
+                self.load_cursor(cursor);
+                step(self);
+                self.extra_cursors.push(self.as_extra_cursor());
+            }
+            self.load_cursor(main);
+        }
+        step(self);
+        self.merge_cursors();
+    }
+
+    fn as_extra_cursor(&self) -> ExtraCursor {
+        ExtraCursor {
+            position: self.cursor_position(),
+            anchor: self.cursor.anchor,
+            original_line_offset: self.cursor.original_line_offset,
+        }
+    }
+
+    // Makes `cursor` the main one without touching the undo history
+    fn load_cursor(&mut self, cursor: ExtraCursor) {
+        self.place_cursor(cursor.position);
+        self.cursor.anchor = cursor.anchor;
+        self.cursor.original_line_offset = cursor.original_line_offset;
+    }
+
+    // Drops cursors that ran into the main cursor or each other
+    fn merge_cursors(&mut self) {
+        let main = self.cursor_position();
+        self.extra_cursors.retain(|cursor| cursor.position != main);
+        self.extra_cursors.sort_by_key(|cursor| cursor.position);
+        self.extra_cursors.dedup_by_key(|cursor| cursor.position);
+    }
+
+    // Start and end offsets of the text between `anchor` and `position`
+    fn selection_between(&self, anchor: usize, position: usize) -> Option<(usize, usize)> {
+        let start = anchor.min(position);
+        let mut end = anchor.max(position);
+        if self.inclusive_selection {
+            end += self.grapheme_len_after(end);
+        }
+        Some((start, end)).filter(|(start, end)| start < end)
+    }
+
+    // Inserts `string` at the cursor, leaving the cursor after it
+    fn insert_at_cursor(&mut self, string: &str) -> NodeChange {
+        self.cursor.anchor = None;
+        let offsets = Buffer::get_offsets(string);
+        let num_newlines = offsets.len() - 1;
+        let node = BufferNode::new(
+            BufferType::Added,
+            self.added_str.len(),
+            string.len(),
+            offsets,
+        );
+        self.added_str.extend_from_slice(string.as_bytes());
+        self.normalize_cursor();
+
+        let change;
+        if self.node_list.is_empty() {
+            change = NodeChange::new(0, vec![], vec![node.clone()]);
+            self.node_list.insert_curr(node);
+            self.cursor.node_offset = self.node_list.get_curr().offset();
+            self.cursor.line_idx = num_newlines;
+        } else if self.cursor.node_offset == 0 {
+            change = NodeChange::new(self.node_list.index(), vec![], vec![node.clone()]);
+            self.node_list.insert_prev(node);
+        } else if self.cursor.node_offset == self.node_list.get_curr().offset() {
+            change = NodeChange::new(self.node_list.index() + 1, vec![], vec![node.clone()]);
+            self.node_list.insert_next(node);
+            self.node_list.move_right();
+            self.cursor.node_offset = self.node_list.get_curr().offset();
+            self.cursor.line_idx = num_newlines;
+        } else {
+            let curr = self.node_list.get_curr().clone();
+            let (left, right) = curr.split_at(self.node_bytes(&curr), self.cursor.node_offset);
+            change = NodeChange::new(
+                self.node_list.index(),
+                vec![curr],
+                vec![left.clone(), node.clone(), right.clone()],
+            );
+            *self.node_list.get_curr_mut() = left;
+            self.node_list.insert_next(right);
+            self.node_list.insert_next(node);
+            self.node_list.move_right();
+            self.node_list.move_right();
+            self.cursor.node_offset = 0;
+            self.cursor.line_idx = 0;
+        }
+
+        self.cursor.line_offset = self.column_at_cursor();
+        self.cursor.original_line_offset = self.display_column_at_cursor();
+        self.current_line += num_newlines;
+        change
+    }
+
+    // Removes the grapheme cluster right before the cursor, if there is one
+    fn remove_at_cursor(&mut self) -> Vec<NodeChange> {
+        if self.node_list.is_empty() {
+            return Vec::new();
+        }
+
+        let len = self.grapheme_len_before(self.cursor_position());
+        if len == 0 {
+            return Vec::new();
+        }
+
+        self.cursor.anchor = None;
+        let mut changes = Vec::with_capacity(len);
+        let mut removed_newline = false;
+        for _ in 0..len {
+            let (removed, change) = self.remove_back_one();
+            removed_newline |= removed == b'\n';
+            changes.push(change);
+        }
+
+        self.update_line_idx();
+        if removed_newline {
+            self.current_line -= 1;
+        }
+        self.cursor.line_offset = self.column_at_cursor();
+        self.cursor.original_line_offset = self.display_column_at_cursor();
+        changes
+    }
+
+    // Replaces the nodes covering `start` to `end` with what is left of
+    // them once the text in between is gone. The cursor has to be placed
+    // again afterwards.
+    fn splice_range(&mut self, start: usize, end: usize) -> NodeChange {
+        // Only the nodes at either end of the range need splitting; the
+        // ones in between are dropped whole
+        let mut first = None;
+        let mut last = (0, 0);
+        let mut node_start = 0;
+        for (idx, node) in self.node_list.iter().enumerate() {
+            let node_end = node_start + node.offset();
+            if first.is_none() && start < node_end {
+                first = Some((idx, node_start));
+            }
+            if end <= node_end {
+                last = (idx, node_start);
+                break;
+            }
+            node_start = node_end;
+        }
+        let (first_idx, first_start) = first.unwrap();
+        let (last_idx, last_start) = last;
+
+        let old: Vec<BufferNode> = (first_idx..=last_idx)
+            .map(|idx| self.node_list.get(idx).clone())
+            .collect();
+        let first = &old[0];
+        let last = &old[old.len() - 1];
+        let (left, _) = first.split_at(self.node_bytes(first), start - first_start);
+        let (_, right) = last.split_at(self.node_bytes(last), end - last_start);
+        let change = NodeChange::new(
+            first_idx,
+            old,
+            vec![left, right]
+                .into_iter()
+                .filter(|node| node.offset() > 0)
+                .collect(),
+        );
+        change.apply(&mut self.node_list);
+        change
+    }
+
+    // Removes the byte right before the cursor, which must not be at the
+    // very start of the text
+    fn remove_back_one(&mut self) -> (u8, NodeChange) {
+        self.normalize_cursor();
+
+        let removed;
+        let change;
+        if self.cursor.node_offset == 0 {
+            let prev = self.node_list.get_prev();
+            removed = self.byte_at(prev, prev.offset() - 1);
+            let (left, _) = prev.split_at(self.node_bytes(prev), prev.offset() - 1);
+            change = NodeChange::new(
+                self.node_list.index() - 1,
+                vec![prev.clone()],
+                Some(left.clone())
+                    .filter(|node| node.offset() > 0)
+                    .into_iter()
+                    .collect(),
+            );
+            if left.offset() == 0 {
+                self.node_list.remove_prev();
+            } else {
+                *self.node_list.get_prev_mut() = left;
+            }
+        } else {
+            let curr = self.node_list.get_curr();
+            removed = self.byte_at(curr, self.cursor.node_offset - 1);
+            let (left, rest) = curr.split_at(self.node_bytes(curr), self.cursor.node_offset - 1);
+            let (_, right) = rest.split_at(self.node_bytes(&rest), 1);
+            change = NodeChange::new(
+                self.node_list.index(),
+                vec![curr.clone()],
+                vec![left.clone(), right.clone()]
+                    .into_iter()
+                    .filter(|node| node.offset() > 0)
+                    .collect(),
+            );
+
+            match (left.offset(), right.offset()) {
+                (0, 0) => {
+                    let was_head = self.node_list.at_head();
+                    self.node_list.remove_curr();
+                    self.cursor.node_offset = if was_head || self.node_list.is_empty() {
+                        0
+                    } else {
+                        self.node_list.get_curr().offset()
+                    };
+                }
+                (0, _) => {
+                    *self.node_list.get_curr_mut() = right;
+                    self.cursor.node_offset = 0;
+                }
+                (_, 0) => {
+                    self.cursor.node_offset = left.offset();
+                    *self.node_list.get_curr_mut() = left;
+                }
+                _ => {
+                    *self.node_list.get_curr_mut() = left;
+                    self.node_list.insert_next(right);
+                    self.node_list.move_right();
+                    self.cursor.node_offset = 0;
+                }
+            }
+            self.normalize_cursor();
+        }
+        (removed, change)
+    }
+
+    // Bytes of the text between the absolute offsets `start` and `end`
+    fn bytes_between(&self, start: usize, end: usize) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(end - start);
+        let mut node_start = 0;
+        for node in self.node_list.iter() {
+            let node_end = node_start + node.offset();
+            if node_end > start && node_start < end {
+                let from = start.max(node_start) - node_start;
+                let to = end.min(node_end) - node_start;
+                bytes.extend_from_slice(&self.node_bytes(node)[from..to]);
+            }
+            if node_end >= end {
+                break;
+            }
+            node_start = node_end;
+        }
+        bytes
+    }
+
+    // Offset of the first occurrence of `needle` from `from` on, wrapping
+    // back to the start of the text, that `wanted` accepts. The text is
+    // read FIND_BLOCK_LEN at a time, so a nearby match is found without
+    // copying the rest.
+    fn find_text(
+        &self,
+        needle: &[u8],
+        from: usize,
+        wanted: impl Fn(usize) -> bool,
+    ) -> Option<usize> {
+        let len = self.len();
+        let overlap = needle.len().saturating_sub(1);
+        for &(first, last) in &[(from, len), (0, from.min(len))] {
+            let mut start = first;
+            while start < last {
+                let end = (start + FIND_BLOCK_LEN).min(last);
+                // Matches may start right up to the end of the block
+                let bytes = self.bytes_between(start, (end + overlap).min(len));
+                let mut at = 0;
+                while let Some(idx) = bytes[at..]
+                    .windows(needle.len())
+                    .position(|window| window == needle)
+                {
+                    let found = start + at + idx;
+                    if found >= end {
+                        break;
+                    }
+                    if wanted(found) {
+                        return Some(found);
+                    }
+                    at += idx + needle.len();
+                }
+                start = end.max(start + at);
+            }
+        }
+        None
+    }
+
+    // Absolute offsets of the start of the line containing `pos` and of
+    // the start of the line after it (or the end of the text)
+    pub fn line_bounds(&self, pos: usize) -> (usize, usize) {
+        // Only the nodes next to `pos` are looked into, up to the newlines
+        // on either side of it
+        let mut node_starts = Vec::with_capacity(self.node_list.len());
+        let mut node_start = 0;
+        for node in self.node_list.iter() {
+            node_starts.push(node_start);
+            node_start += node.offset();
+        }
+        let len = node_start;
+        let idx = node_starts.partition_point(|&node_start| node_start <= pos);
+
+        let mut line_start = 0;
+        for idx in (0..idx).rev() {
+            let node = self.node_list.get(idx);
+            let bytes = self.node_bytes(node);
+            let line_idx = node.line_idx_at(bytes, (pos - node_starts[idx]).min(node.offset()));
+            if line_idx > 0 {
+                line_start = node_starts[idx] + node.line_offset_at(bytes, line_idx);
+                break;
+            }
+        }
+
+        let mut line_end = len;
+        let first = idx.saturating_sub(1);
+        for (node, &node_start) in self.node_list.iter().skip(first).zip(&node_starts[first..]) {
+            let from = pos.saturating_sub(node_start);
+            if let Some(line_offset) = node
+                .line_offsets(self.node_bytes(node))
+                .iter()
+                .find(|&&line_offset| line_offset > from)
+            {
+                line_end = node_start + line_offset;
+                break;
+            }
+        }
+        (line_start, line_end)
+    }
+
+    // Length in bytes of the grapheme cluster ending at `pos`. Clusters
+    // never span lines, except for "\r\n" which the window still covers.
+    pub fn grapheme_len_before(&self, pos: usize) -> usize {
+        if pos == 0 {
+            return 0;
+        }
+
+        let (line_start, _) = self.line_bounds(pos - 1);
+        let bytes = self.bytes_between(line_start, pos);
+        String::from_utf8_lossy(&bytes)
+            .graphemes(true)
+            .next_back()
+            .map_or(0, |grapheme| grapheme.len().min(bytes.len()))
+    }
+
+    // Length in bytes of the grapheme cluster starting at `pos`
+    pub fn grapheme_len_after(&self, pos: usize) -> usize {
+        let (_, line_end) = self.line_bounds(pos);
+        let bytes = self.bytes_between(pos, line_end);
+        String::from_utf8_lossy(&bytes)
+            .graphemes(true)
+            .next()
+            .map_or(0, |grapheme| grapheme.len().min(bytes.len()))
+    }
+
+    fn node_bytes(&self, node: &BufferNode) -> &[u8] {
+        let range = node.index()..node.index() + node.offset();
+        match node.from() {
+            BufferType::Original => self.original_str.bytes(range),
+            BufferType::Added => &self.added_str[range],
+        }
+    }
+
+    fn byte_at(&self, node: &BufferNode, offset: usize) -> u8 {
+        self.node_bytes(node)[offset]
+    }
+
+    // The cursor only sits at the end of a node if it is the last one,
+    // otherwise it is moved to the start of the next node.
+    fn normalize_cursor(&mut self) {
+        if self.node_list.is_empty() {
+            return;
+        }
+
+        if self.cursor.node_offset == self.node_list.get_curr().offset()
+            && !self.node_list.at_tail()
+        {
+            self.node_list.move_right();
+            self.cursor.node_offset = 0;
+        }
+    }
+
+    fn update_line_idx(&mut self) {
+        self.cursor.line_idx = if self.node_list.is_empty() {
+            0
+        } else {
+            self.node_list.get_curr().line_idx_at(
+                self.node_bytes(self.node_list.get_curr()),
+                self.cursor.node_offset,
+            )
+        };
+    }
+
+    // Text of the current line up to the cursor
+    fn line_before_cursor(&self) -> String {
+        if self.node_list.is_empty() {
+            return String::new();
+        }
+
+        let position = self.cursor_position();
+        let (line_start, _) = self.line_bounds(position);
+        String::from_utf8_lossy(&self.bytes_between(line_start, position)).into_owned()
+    }
+
+    // Column of the cursor in grapheme clusters from the start of its line
+    fn column_at_cursor(&self) -> usize {
+        self.line_before_cursor().graphemes(true).count()
+    }
+
+    // Screen column of the cursor, which differs from `column_at_cursor`
+    // once the line has tabs or wide chars in it
+    fn display_column_at_cursor(&self) -> usize {
+        width::display_width(&self.line_before_cursor(), self.tab_stop)
+    }
+
+    // Screen column of the absolute offset `pos` within its line
+    fn display_column_at(&self, pos: usize) -> usize {
+        let (line_start, _) = self.line_bounds(pos);
+        width::display_width(&self.text_between(line_start, pos), self.tab_stop)
+    }
+
+    fn move_to_line_start(&mut self) {
+        let curr = self.node_list.get_curr();
+        let bytes = self.node_bytes(curr);
+        let line_idx = curr.line_idx_at(bytes, self.cursor.node_offset);
+        if line_idx > 0 {
+            self.cursor.node_offset = curr.line_offset_at(bytes, line_idx);
+        } else {
+            self.cursor.node_offset = 0;
+            while !self.node_list.at_head() {
+                self.node_list.move_left();
+                let curr = self.node_list.get_curr();
+                let bytes = self.node_bytes(curr);
+                if curr.has_newline(bytes) {
+                    self.cursor.node_offset = curr.last_line_offset(bytes);
+                    break;
+                }
+            }
+        }
+        self.normalize_cursor();
+    }
+
+    // Moves the cursor back by a single byte, returning the byte passed
+    fn move_back_one(&mut self) -> u8 {
+        if self.cursor.node_offset == 0 {
+            self.node_list.move_left();
+            self.cursor.node_offset = self.node_list.get_curr().offset();
+        }
+        self.cursor.node_offset -= 1;
+        self.byte_at(self.node_list.get_curr(), self.cursor.node_offset)
+    }
+
+    // Moves the cursor forward by a single byte, returning the byte passed
+    fn move_forward_one(&mut self) -> u8 {
+        self.normalize_cursor();
+        let passed = self.byte_at(self.node_list.get_curr(), self.cursor.node_offset);
+        self.cursor.node_offset += 1;
+        self.normalize_cursor();
+        passed
+    }
+
+    // Returns false if the cursor is already on the last line
+    fn move_to_next_line_start(&mut self) -> bool {
+        let curr = self.node_list.get_curr();
+        if let Some(line_offset) =
+            curr.next_line_offset(self.node_bytes(curr), self.cursor.node_offset)
+        {
+            self.cursor.node_offset = line_offset;
+            self.normalize_cursor();
+            return true;
+        }
+
+        while !self.node_list.at_tail() {
+            self.node_list.move_right();
+            let curr = self.node_list.get_curr();
+            let bytes = self.node_bytes(curr);
+            if curr.has_newline(bytes) {
+                self.cursor.node_offset = curr.line_offset_at(bytes, 1);
+                self.normalize_cursor();
+                return true;
+            }
+        }
+        false
+    }
+
+    // Moves from the start of a line towards screen column
+    // `display_column` without crossing a newline or splitting a wide char,
+    // returning the number of grapheme clusters moved
+    fn advance_within_line(&mut self, display_column: usize) -> usize {
+        let position = self.cursor_position();
+        let (_, line_end) = self.line_bounds(position);
+        let bytes = self.bytes_between(position, line_end);
+        let text = String::from_utf8_lossy(&bytes);
+        let line = text.strip_suffix('\n').unwrap_or(&text);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        let moved = width::column_at_display(line, display_column, self.tab_stop);
+        let len: usize = line.graphemes(true).take(moved).map(str::len).sum();
+        for _ in 0..len.min(bytes.len()) {
+            self.move_forward_one();
+        }
+        self.normalize_cursor();
+        moved
     }
 }
+
+#[cfg(test)]
+#[path = "tests/buffer_tests.rs"]
+mod tests;
@@ -0,0 +1,267 @@
+// Commands that can be entered on the command line, e.g. `:w notes.txt`
+#[derive(PartialEq, Debug)]
+pub enum Command {
+    Write(Option<String>),
+    WriteQuit(Option<String>),
+    // `:e file`, or `:e! file` to throw away unsaved changes
+    Edit {
+        file_path: String,
+        force: bool,
+    },
+    // `:e ++enc=name [file]`, reading the file, or else the current one,
+    // as the given encoding
+    EditWithEncoding {
+        encoding: String,
+        file_path: Option<String>,
+        force: bool,
+    },
+    Quit {
+        force: bool,
+    },
+    // `:set option=value`, or `:set option` for on/off options
+    Set {
+        option: String,
+        value: Option<String>,
+    },
+    // `:noh`, which stops highlighting matches until the next search
+    NoHighlight,
+    Substitute(Substitute),
+}
+
+// A line given as part of a range
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Address {
+    // Counted from 1, as typed
+    Line(usize),
+    // `.`, the line the cursor is on
+    Current,
+    // `$`
+    Last,
+}
+
+// Lines from `start` to `end`, both included. `%` is every line.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct LineRange {
+    pub start: Address,
+    pub end: Address,
+}
+
+impl LineRange {
+    pub fn current_line() -> LineRange {
+        LineRange {
+            start: Address::Current,
+            end: Address::Current,
+        }
+    }
+
+    pub fn whole_file() -> LineRange {
+        LineRange {
+            start: Address::Line(1),
+            end: Address::Last,
+        }
+    }
+}
+
+// `:[range]s/pattern/replacement/[flags]`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Substitute {
+    pub range: LineRange,
+    // Empty to use the last search pattern
+    pub pattern: String,
+    pub replacement: String,
+    // `g`: every match on a line rather than just the first
+    pub global: bool,
+    // `c`: ask before each replacement
+    pub confirm: bool,
+    // `i`
+    pub ignore_case: bool,
+}
+
+impl Command {
+    pub fn parse(input: &str) -> Result<Command, String> {
+        let input = input.trim();
+        let input = input.strip_prefix(':').unwrap_or(input).trim_start();
+        if let Some(substitute) = parse_substitute(input) {
+            return substitute.map(Command::Substitute);
+        }
+
+        let mut parts = input.splitn(2, char::is_whitespace);
+        let name = parts.next().unwrap_or("");
+        let arg = parts
+            .next()
+            .map(str::trim)
+            .filter(|arg| !arg.is_empty())
+            .map(String::from);
+
+        match (name, arg) {
+            ("w", arg) | ("write", arg) => Ok(Command::Write(arg)),
+            ("wq", arg) | ("x", arg) => Ok(Command::WriteQuit(arg)),
+            ("e", Some(arg)) | ("edit", Some(arg)) => parse_edit(arg, false),
+            ("e!", Some(arg)) | ("edit!", Some(arg)) => parse_edit(arg, true),
+            ("e", None) | ("edit", None) | ("e!", None) | ("edit!", None) => {
+                Err(String::from("No file name"))
+            }
+            ("q", None) | ("quit", None) => Ok(Command::Quit { force: false }),
+            ("q!", None) | ("quit!", None) => Ok(Command::Quit { force: true }),
+            ("q", Some(_)) | ("quit", Some(_)) | ("q!", Some(_)) | ("quit!", Some(_)) => {
+                Err(String::from("Trailing characters"))
+            }
+            ("set", Some(arg)) | ("se", Some(arg)) => match arg.split_once('=') {
+                Some((option, value)) => Ok(Command::Set {
+                    option: String::from(option.trim()),
+                    value: Some(String::from(value.trim())),
+                }),
+                None => Ok(Command::Set {
+                    option: arg,
+                    value: None,
+                }),
+            },
+            ("set", None) | ("se", None) => Err(String::from("Argument required")),
+            ("noh", None) | ("nohlsearch", None) => Ok(Command::NoHighlight),
+            ("", _) => Err(String::from("No command given")),
+            _ => Err(format!("Not an editor command: {}", input)),
+        }
+    }
+}
+
+// A file name, or `++enc=name` followed by an optional file name
+fn parse_edit(arg: String, force: bool) -> Result<Command, String> {
+    let rest = match arg
+        .strip_prefix("++enc=")
+        .or_else(|| arg.strip_prefix("++encoding="))
+    {
+        Some(rest) => rest,
+        None if arg.starts_with("++") => return Err(format!("Invalid argument: {}", arg)),
+        None => {
+            return Ok(Command::Edit {
+                file_path: arg,
+                force,
+            })
+        }
+    };
+
+    let mut parts = rest.splitn(2, char::is_whitespace);
+    let encoding = parts.next().unwrap_or("");
+    if encoding.is_empty() {
+        return Err(format!("Invalid argument: {}", arg));
+    }
+    let file_path = parts
+        .next()
+        .map(str::trim)
+        .filter(|file_path| !file_path.is_empty())
+        .map(String::from);
+    Ok(Command::EditWithEncoding {
+        encoding: String::from(encoding),
+        file_path,
+        force,
+    })
+}
+
+// Parses `input` as a substitution, or returns None if it isn't one. As in
+// vi, any punctuation can stand in for the `/`s.
+fn parse_substitute(input: &str) -> Option<Result<Substitute, String>> {
+    let (range, rest) = parse_range(input)?;
+    let rest = rest
+        .strip_prefix("substitute")
+        .or_else(|| rest.strip_prefix('s'))?;
+    let delimiter = rest
+        .chars()
+        .next()
+        .filter(|c| c.is_ascii_punctuation() && !matches!(c, '\\' | '"' | '|'))?;
+
+    let mut rest = &rest[delimiter.len_utf8()..];
+    let pattern = take_delimited(&mut rest, delimiter);
+    let replacement = take_delimited(&mut rest, delimiter);
+    let range = match range {
+        Ok(range) => range,
+        Err(e) => return Some(Err(e)),
+    };
+
+    let mut substitute = Substitute {
+        range,
+        pattern,
+        replacement,
+        global: false,
+        confirm: false,
+        ignore_case: false,
+    };
+    for flag in rest.trim().chars() {
+        match flag {
+            'g' => substitute.global = true,
+            'c' => substitute.confirm = true,
+            'i' => substitute.ignore_case = true,
+            'I' => substitute.ignore_case = false,
+            _ => return Some(Err(String::from("Trailing characters"))),
+        }
+    }
+    Some(Ok(substitute))
+}
+
+// Splits a leading range such as `%`, `.`, `3` or `1,$` off `input`.
+// Returns None if what follows can't be a command, and an error for a
+// range with a line 0 in it.
+fn parse_range(input: &str) -> Option<(Result<LineRange, String>, &str)> {
+    if let Some(rest) = input.strip_prefix('%') {
+        return Some((Ok(LineRange::whole_file()), rest));
+    }
+
+    let (start, rest) = match parse_address(input) {
+        Some((start, rest)) => (start, rest),
+        None => return Some((Ok(LineRange::current_line()), input)),
+    };
+    let (end, rest) = match rest.strip_prefix(',') {
+        Some(rest) => parse_address(rest)?,
+        None => (start, rest),
+    };
+
+    let range = match (start, end) {
+        (Address::Line(0), _) | (_, Address::Line(0)) => Err(String::from("Invalid range")),
+        _ => Ok(LineRange { start, end }),
+    };
+    Some((range, rest))
+}
+
+fn parse_address(input: &str) -> Option<(Address, &str)> {
+    if let Some(rest) = input.strip_prefix('.') {
+        return Some((Address::Current, rest));
+    }
+    if let Some(rest) = input.strip_prefix('$') {
+        return Some((Address::Last, rest));
+    }
+
+    let digits = input.len() - input.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let line = input[..digits].parse().ok()?;
+    Some((Address::Line(line), &input[digits..]))
+}
+
+// Takes everything up to the next `delimiter` not escaped with a backslash
+// off the front of `input`, dropping the backslashes that escaped it. Other
+// escapes are left for the regex or the replacement to deal with.
+fn take_delimited(input: &mut &str, delimiter: char) -> String {
+    let mut taken = String::new();
+    let mut chars = input.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        if c == delimiter {
+            *input = &input[idx + c.len_utf8()..];
+            return taken;
+        }
+        if c == '\\' {
+            match chars.next() {
+                Some((_, escaped)) if escaped == delimiter => taken.push(escaped),
+                Some((_, escaped)) => {
+                    taken.push(c);
+                    taken.push(escaped);
+                }
+                None => taken.push(c),
+            }
+            continue;
+        }
+        taken.push(c);
+    }
+    *input = "";
+    taken
+}
+
+#[cfg(test)]
+#[path = "tests/command_tests.rs"]
+mod tests;
@@ -1,27 +1,35 @@
-#[derive(Debug)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct Cursor {
-    column: usize,
-    row: usize,
+    // Offset into the node the cursor is currently on
+    pub node_offset: usize,
+    // Index into the line offsets of the current node
+    pub line_idx: usize,
+    // Column of the cursor within the line it is on, in grapheme clusters
+    pub line_offset: usize,
+    // Screen column we try to return to when moving up and down
+    pub original_line_offset: usize,
+    // Absolute offset the selection was started from, if there is one
+    pub anchor: Option<usize>,
 }
 
 impl Cursor {
     pub fn new() -> Cursor {
-        Cursor { column: 0, row: 0 }
-    }
-
-    pub fn column(&self) -> usize {
-        self.column
-    }
-
-    pub fn row(&self) -> usize {
-        self.row
-    }
-
-    pub fn move_right(&mut self) {
-        self.column += 1;
+        Cursor {
+            node_offset: 0,
+            line_idx: 0,
+            line_offset: 0,
+            original_line_offset: 0,
+            anchor: None,
+        }
     }
+}
 
-    pub fn move_left(&mut self) {
-        self.column -= 1;
-    }
+// A cursor besides the main one. Only absolute offsets are kept, since it
+// is turned back into a full `Cursor` whenever it is moved or edited at.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ExtraCursor {
+    pub position: usize,
+    pub anchor: Option<usize>,
+    // Screen column we try to return to when moving up and down
+    pub original_line_offset: usize,
 }
@@ -0,0 +1,178 @@
+// Line by line differences between two texts, shown as `diff -u` would
+
+// Lines of context kept around each change
+pub const CONTEXT_LINES: usize = 3;
+
+// Most cells the table of common lines is allowed to have. Anything bigger
+// left after the common start and end are trimmed is shown as replaced
+// outright, rather than taking up memory in proportion to both lengths.
+const MAX_TABLE_CELLS: usize = 1 << 22;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LineDiff<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+// The lines of `old` and `new` in order, each marked as common to both or
+// as only in one of them. Common lines are found with a longest common
+// subsequence, so as few lines as possible are marked as changed.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<LineDiff<'a>> {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut diff: Vec<LineDiff> = old[..prefix]
+        .iter()
+        .map(|&line| LineDiff::Same(line))
+        .collect();
+    if (old_middle.len() + 1).saturating_mul(new_middle.len() + 1) <= MAX_TABLE_CELLS {
+        diff.extend(diff_middle(old_middle, new_middle));
+    } else {
+        diff.extend(old_middle.iter().map(|&line| LineDiff::Removed(line)));
+        diff.extend(new_middle.iter().map(|&line| LineDiff::Added(line)));
+    }
+    diff.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|&line| LineDiff::Same(line)),
+    );
+    diff
+}
+
+fn diff_middle<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<LineDiff<'a>> {
+    // common[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..]
+    let width = new.len() + 1;
+    let mut common = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i * width + j] = if old[i] == new[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(LineDiff::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if common[(i + 1) * width + j] >= common[i * width + j + 1] {
+            diff.push(LineDiff::Removed(old[i]));
+            i += 1;
+        } else {
+            diff.push(LineDiff::Added(new[j]));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|&line| LineDiff::Removed(line)));
+    diff.extend(new[j..].iter().map(|&line| LineDiff::Added(line)));
+    diff
+}
+
+// Unified diff from `old` to `new`, headed with their names. Empty if the
+// two are the same.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let diff = diff_lines(old, new);
+    let changed: Vec<usize> = diff
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, LineDiff::Same(_)))
+        .map(|(idx, _)| idx)
+        .collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    // Changes close enough for their context to meet share a hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &idx in changed.iter() {
+        let start = idx.saturating_sub(CONTEXT_LINES);
+        let end = (idx + 1 + CONTEXT_LINES).min(diff.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.1 => hunk.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut output = format!("--- {}\n+++ {}\n", old_name, new_name);
+    // Lines of `old` and `new` before each entry of `diff`
+    let (mut old_line, mut new_line, mut idx) = (0, 0, 0);
+    for (start, end) in hunks {
+        for line in &diff[idx..start] {
+            advance(line, &mut old_line, &mut new_line);
+        }
+        let lines = &diff[start..end];
+        let old_len = lines
+            .iter()
+            .filter(|line| !matches!(line, LineDiff::Added(_)))
+            .count();
+        let new_len = lines
+            .iter()
+            .filter(|line| !matches!(line, LineDiff::Removed(_)))
+            .count();
+        output.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_line, old_len),
+            hunk_range(new_line, new_len)
+        ));
+
+        for line in lines {
+            let (sign, text) = match line {
+                LineDiff::Same(text) => (' ', text),
+                LineDiff::Removed(text) => ('-', text),
+                LineDiff::Added(text) => ('+', text),
+            };
+            output.push(sign);
+            output.push_str(text.strip_suffix('\n').unwrap_or(text));
+            output.push('\n');
+            advance(line, &mut old_line, &mut new_line);
+        }
+        idx = end;
+    }
+    output
+}
+
+fn advance(line: &LineDiff, old_line: &mut usize, new_line: &mut usize) {
+    match line {
+        LineDiff::Same(_) => {
+            *old_line += 1;
+            *new_line += 1;
+        }
+        LineDiff::Removed(_) => *old_line += 1,
+        LineDiff::Added(_) => *new_line += 1,
+    }
+}
+
+// "start,len" with 1-based lines, where an empty range is named by the
+// line before it
+fn hunk_range(before: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", before),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{}", before + 1, len),
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/diff_tests.rs"]
+mod tests;
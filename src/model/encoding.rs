@@ -0,0 +1,327 @@
+use std::convert::TryFrom;
+
+use super::lineending::LineEnding;
+
+// Text encodings files can be read and written in. The buffer always
+// holds UTF-8; files are decoded on the way in and encoded on the way out.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Encoding {
+    Utf8,
+    // UTF-8 starting with a byte order mark
+    Utf8Bom,
+    // UTF-16 is always written with a byte order mark, as that is how it
+    // is told apart when read
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    // Windows' take on Latin-1, with printable characters in place of most
+    // of the control characters at 0x80 to 0x9F
+    Cp1252,
+}
+
+// Text decoded from a file, and whether any of it was invalid
+#[derive(PartialEq, Debug)]
+pub struct Decoded {
+    pub text: String,
+    pub encoding: Encoding,
+    pub invalid: bool,
+    // What the replacement characters put in for invalid UTF-8 stand for
+    pub raw_bytes: RawBytes,
+}
+
+// Runs of bytes that weren't valid UTF-8, each shown in the text as a
+// replacement character, or as "?" where that wouldn't fit. They are kept
+// apart from the text, by the offset of what stands in for them, so that
+// saving puts the very same bytes back while the text itself, replacement
+// characters that really were in the file included, is always saved as it
+// is.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct RawBytes {
+    // In order of offset
+    runs: Vec<RawRun>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RawRun {
+    pub offset: usize,
+    // Length of the text standing in for the bytes
+    pub len: usize,
+    pub bytes: Vec<u8>,
+}
+
+// What the bytes 0x80 to 0x9F are in cp1252. The five it leaves undefined
+// are read as the control characters they are in Latin-1, as Windows does,
+// so that any file can be read and saved again unchanged.
+const CP1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+// Bytes cp1252 leaves undefined, which are unlikely to be in a file that
+// really is in it
+const CP1252_UNDEFINED: [u8; 5] = [0x81, 0x8D, 0x8F, 0x90, 0x9D];
+
+impl Encoding {
+    // Names as in vi's `fileencoding` option
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+            Encoding::Cp1252 => "cp1252",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-8-bom" | "utf8-bom" => Some(Encoding::Utf8Bom),
+            "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Encoding::Latin1),
+            "cp1252" | "windows-1252" => Some(Encoding::Cp1252),
+            _ => None,
+        }
+    }
+
+    pub fn bom(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8Bom => b"\xEF\xBB\xBF",
+            Encoding::Utf16Le => b"\xFF\xFE",
+            Encoding::Utf16Be => b"\xFE\xFF",
+            Encoding::Utf8 | Encoding::Latin1 | Encoding::Cp1252 => b"",
+        }
+    }
+
+    // Guesses the encoding of `bytes` from its byte order mark. Without
+    // one, it is UTF-8 if valid, or if it has any UTF-8 characters beyond
+    // ASCII at all, in which case it is only broken here and there.
+    // Otherwise it is Latin-1, unless it has bytes that are control
+    // characters there, in which case it is cp1252 if that has them all.
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        let with_bom = [Encoding::Utf8Bom, Encoding::Utf16Le, Encoding::Utf16Be];
+        if let Some(&encoding) = with_bom
+            .iter()
+            .find(|encoding| bytes.starts_with(encoding.bom()))
+        {
+            return encoding;
+        }
+        if bytes.is_ascii() || bytes.utf8_chunks().any(|chunk| !chunk.valid().is_ascii()) {
+            Encoding::Utf8
+        } else if !bytes.iter().any(|byte| (0x80..0xA0).contains(byte)) {
+            Encoding::Latin1
+        } else if bytes.iter().any(|byte| CP1252_UNDEFINED.contains(byte)) {
+            Encoding::Utf8
+        } else {
+            Encoding::Cp1252
+        }
+    }
+
+    // Reads `bytes` as this encoding, skipping its byte order mark if there
+    // is one. Invalid UTF-8 is kept as raw bytes, while invalid UTF-16
+    // becomes replacement characters.
+    pub fn decode(self, bytes: &[u8]) -> Decoded {
+        let bytes = bytes.strip_prefix(self.bom()).unwrap_or(bytes);
+        let mut raw_bytes = RawBytes::default();
+        let (text, invalid) = match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => {
+                raw_bytes = decode_utf8(bytes);
+                let text = String::from_utf8_lossy(bytes).into_owned();
+                (text, !raw_bytes.is_empty())
+            }
+            Encoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+            Encoding::Utf16Be => decode_utf16(bytes, u16::from_be_bytes),
+            Encoding::Latin1 => (bytes.iter().map(|&byte| char::from(byte)).collect(), false),
+            Encoding::Cp1252 => (bytes.iter().map(|&byte| cp1252_char(byte)).collect(), false),
+        };
+        Decoded {
+            text,
+            encoding: self,
+            invalid,
+            raw_bytes,
+        }
+    }
+
+    // `text` in this encoding, byte order mark included
+    #[cfg(test)]
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, String> {
+        let mut bytes = self.bom().to_vec();
+        self.encode_into(text, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    // Adds `text` in this encoding to `bytes`, without a byte order mark
+    pub fn encode_into(self, text: &str, bytes: &mut Vec<u8>) -> Result<(), String> {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => bytes.extend_from_slice(text.as_bytes()),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = text
+                    .chars()
+                    .flat_map(|c| c.encode_utf16(&mut [0; 2]).to_vec());
+                for unit in units {
+                    if self == Encoding::Utf16Le {
+                        bytes.extend_from_slice(&unit.to_le_bytes());
+                    } else {
+                        bytes.extend_from_slice(&unit.to_be_bytes());
+                    }
+                }
+            }
+            Encoding::Latin1 | Encoding::Cp1252 => {
+                for c in text.chars() {
+                    match self.byte_for(c) {
+                        Some(byte) => bytes.push(byte),
+                        None => return Err(format!("Can't write '{}' as {}", c, self.name())),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Adds bytes that weren't valid UTF-8 to `bytes` as they were, or as a
+    // replacement character in UTF-16, which has no way to hold them
+    pub fn encode_raw_into(self, raw: &[u8], bytes: &mut Vec<u8>) {
+        match self {
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let replacement = char::REPLACEMENT_CHARACTER.to_string();
+                self.encode_into(&replacement, bytes).unwrap();
+            }
+            _ => bytes.extend_from_slice(raw),
+        }
+    }
+
+    // The single byte `c` is written as in Latin-1 or cp1252
+    fn byte_for(self, c: char) -> Option<u8> {
+        let code = c as u32;
+        if self == Encoding::Cp1252 {
+            if let Some(idx) = CP1252_HIGH.iter().position(|&high| high == c) {
+                return Some(0x80 + idx as u8);
+            }
+            if (0x80..0xA0).contains(&code) {
+                return None;
+            }
+        }
+        u8::try_from(code).ok()
+    }
+}
+
+impl RawBytes {
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    // Runs whose stand-ins are at offsets from `start` up to `end`, in order
+    pub fn within(&self, start: usize, end: usize) -> &[RawRun] {
+        let first = self.runs.partition_point(|run| run.offset < start);
+        let last = self.runs.partition_point(|run| run.offset < end);
+        &self.runs[first..last]
+    }
+
+    // Adds a run that comes after these
+    pub fn push(&mut self, run: RawRun) {
+        self.runs.push(run);
+    }
+
+    // The same runs once `text`, which they were found in, has its lines
+    // changed to end in "\n" rather than in `line_ending`. Only "\r\n"
+    // shortens the text, and no run is ever in the middle of one.
+    pub fn into_lf(mut self, text: &str, line_ending: LineEnding) -> RawBytes {
+        if line_ending != LineEnding::CrLf {
+            return self;
+        }
+        let mut removed = 0;
+        let mut counted_to = 0;
+        for run in self.runs.iter_mut() {
+            removed += text[counted_to..run.offset].matches("\r\n").count();
+            counted_to = run.offset;
+            run.offset -= removed;
+        }
+        self
+    }
+}
+
+// Decodes `bytes` as whatever encoding it seems to be in, unless one is given
+pub fn decode(bytes: &[u8], encoding: Option<Encoding>) -> Decoded {
+    encoding
+        .unwrap_or_else(|| Encoding::detect(bytes))
+        .decode(bytes)
+}
+
+fn cp1252_char(byte: u8) -> char {
+    match byte {
+        0x80..=0x9F => CP1252_HIGH[usize::from(byte - 0x80)],
+        _ => char::from(byte),
+    }
+}
+
+// The runs of invalid bytes in `bytes`, at the offsets of the replacement
+// characters `String::from_utf8_lossy` puts in for them
+fn decode_utf8(bytes: &[u8]) -> RawBytes {
+    let mut runs = Vec::new();
+    let mut offset = 0;
+    for chunk in bytes.utf8_chunks() {
+        offset += chunk.valid().len();
+        if !chunk.invalid().is_empty() {
+            let len = char::REPLACEMENT_CHARACTER.len_utf8();
+            runs.push(RawRun {
+                offset,
+                len,
+                bytes: chunk.invalid().to_vec(),
+            });
+            offset += len;
+        }
+    }
+    RawBytes { runs }
+}
+
+// `bytes`, found at `start`, with a "?" for each byte that isn't valid
+// UTF-8, so that nothing moves, and the bytes replaced. Each "?" can be
+// edited on its own, so each gets a run of its own. None if they are all
+// valid.
+pub fn mark_invalid_utf8(bytes: &[u8], start: usize) -> Option<(Vec<u8>, RawBytes)> {
+    if std::str::from_utf8(bytes).is_ok() {
+        return None;
+    }
+    let mut marked = Vec::with_capacity(bytes.len());
+    let mut runs = Vec::new();
+    for chunk in bytes.utf8_chunks() {
+        marked.extend_from_slice(chunk.valid().as_bytes());
+        for &byte in chunk.invalid() {
+            runs.push(RawRun {
+                offset: start + marked.len(),
+                len: 1,
+                bytes: vec![byte],
+            });
+            marked.push(b'?');
+        }
+    }
+    Some((marked, RawBytes { runs }))
+}
+
+fn decode_utf16(bytes: &[u8], to_unit: fn([u8; 2]) -> u16) -> (String, bool) {
+    let pairs = bytes.chunks_exact(2);
+    // An odd byte out at the end
+    let odd_byte = !pairs.remainder().is_empty();
+    let units = pairs.map(|pair| to_unit([pair[0], pair[1]]));
+    let mut invalid = odd_byte;
+    let mut text: String = char::decode_utf16(units)
+        .map(|c| {
+            c.unwrap_or_else(|_| {
+                invalid = true;
+                char::REPLACEMENT_CHARACTER
+            })
+        })
+        .collect();
+    if odd_byte {
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+    (text, invalid)
+}
+
+#[cfg(test)]
+#[path = "tests/encoding_tests.rs"]
+mod tests;
@@ -0,0 +1,166 @@
+use super::cursor::Cursor;
+use super::nodelist::{BufferNode, NodeList};
+
+// A contiguous run of nodes starting at `index` that was replaced by
+// another run of nodes. Since the added store is append-only, this is
+// all that is needed to undo or redo an edit.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NodeChange {
+    index: usize,
+    removed: Vec<BufferNode>,
+    inserted: Vec<BufferNode>,
+}
+
+impl NodeChange {
+    pub fn new(index: usize, removed: Vec<BufferNode>, inserted: Vec<BufferNode>) -> NodeChange {
+        NodeChange {
+            index,
+            removed,
+            inserted,
+        }
+    }
+
+    pub fn apply(&self, node_list: &mut NodeList) {
+        node_list.splice(self.index, self.removed.len(), self.inserted.clone());
+    }
+
+    pub fn revert(&self, node_list: &mut NodeList) {
+        node_list.splice(self.index, self.inserted.len(), self.removed.clone());
+    }
+}
+
+// Everything needed to put the cursor back where it was
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct CursorState {
+    pub node_index: usize,
+    pub cursor: Cursor,
+    pub current_line: usize,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EditKind {
+    InsertWord,
+    InsertSpace,
+    Remove,
+    Other,
+}
+
+impl EditKind {
+    // Whether an edit of kind `next` belongs to the same undo step
+    fn continues_with(self, next: EditKind) -> bool {
+        matches!(
+            (self, next),
+            (EditKind::InsertWord, EditKind::InsertWord)
+                | (EditKind::InsertWord, EditKind::InsertSpace)
+                | (EditKind::InsertSpace, EditKind::InsertSpace)
+                | (EditKind::Remove, EditKind::Remove)
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Transaction {
+    changes: Vec<NodeChange>,
+    before: CursorState,
+    after: CursorState,
+}
+
+#[derive(Debug)]
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    current: Option<Transaction>,
+    last_kind: Option<EditKind>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            current: None,
+            last_kind: None,
+        }
+    }
+
+    // Records an edit, grouping it with the previous one if it continues
+    // the same word or run of deletions
+    pub fn record(
+        &mut self,
+        kind: EditKind,
+        change: NodeChange,
+        before: CursorState,
+        after: CursorState,
+    ) {
+        self.record_all(kind, vec![change], before, after);
+    }
+
+    // Records several edits made at once, such as one at each cursor, as
+    // part of the same undo step
+    pub fn record_all(
+        &mut self,
+        kind: EditKind,
+        changes: Vec<NodeChange>,
+        before: CursorState,
+        after: CursorState,
+    ) {
+        if changes.is_empty() {
+            return;
+        }
+
+        let continues = match self.last_kind {
+            Some(last_kind) => self.current.is_some() && last_kind.continues_with(kind),
+            None => false,
+        };
+        if !continues {
+            self.commit();
+            self.current = Some(Transaction {
+                changes: Vec::new(),
+                before,
+                after,
+            });
+        }
+
+        let transaction = self.current.as_mut().unwrap();
+        transaction.changes.extend(changes);
+        transaction.after = after;
+        self.last_kind = Some(kind);
+        self.redo_stack.clear();
+    }
+
+    // Closes the current undo step so the next edit starts a new one
+    pub fn commit(&mut self) {
+        if let Some(transaction) = self.current.take() {
+            if !transaction.changes.is_empty() {
+                self.undo_stack.push(transaction);
+            }
+        }
+        self.last_kind = None;
+    }
+
+    // Reverts the last undo step, returning where the cursor was before it
+    pub fn undo(&mut self, node_list: &mut NodeList) -> Option<CursorState> {
+        self.commit();
+        let transaction = self.undo_stack.pop()?;
+        for change in transaction.changes.iter().rev() {
+            change.revert(node_list);
+        }
+
+        let before = transaction.before;
+        self.redo_stack.push(transaction);
+        Some(before)
+    }
+
+    // Reapplies the last undone step, returning where the cursor was after it
+    pub fn redo(&mut self, node_list: &mut NodeList) -> Option<CursorState> {
+        self.commit();
+        let transaction = self.redo_stack.pop()?;
+        for change in transaction.changes.iter() {
+            change.apply(node_list);
+        }
+
+        let after = transaction.after;
+        self.undo_stack.push(transaction);
+        Some(after)
+    }
+}
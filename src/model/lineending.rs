@@ -0,0 +1,81 @@
+// How lines end in a file. The buffer always works with "\n"; files that
+// end lines otherwise are converted on the way in and back on the way out,
+// so they are saved just as they were read.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    // Lines end in "\r\n" only if every one of them does, and in "\r" only
+    // if none has a "\n" at all, so that nothing is lost going back and
+    // forth. Anything else, mixed endings included, is taken as it is.
+    pub fn detect(text: &str) -> LineEnding {
+        let bytes = text.as_bytes();
+        let mut newlines = bytes
+            .iter()
+            .enumerate()
+            .filter(|&(_, &byte)| byte == b'\n')
+            .peekable();
+        if newlines.peek().is_none() {
+            return if bytes.contains(&b'\r') {
+                LineEnding::Cr
+            } else {
+                LineEnding::Lf
+            };
+        }
+        if newlines.all(|(idx, _)| idx > 0 && bytes[idx - 1] == b'\r') {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    // Names as in vi's `fileformat` option
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::CrLf => "dos",
+            LineEnding::Cr => "mac",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LineEnding> {
+        match name {
+            "unix" => Some(LineEnding::Lf),
+            "dos" => Some(LineEnding::CrLf),
+            "mac" => Some(LineEnding::Cr),
+            _ => None,
+        }
+    }
+
+    // `text` with lines ending in "\n" rather than in this
+    pub fn to_lf(self, text: String) -> String {
+        match self {
+            LineEnding::Lf => text,
+            _ => text.replace(self.as_str(), "\n"),
+        }
+    }
+
+    // `text` with lines ending in this rather than in "\n"
+    pub fn apply_to(self, text: String) -> String {
+        match self {
+            LineEnding::Lf => text,
+            _ => text.replace('\n', self.as_str()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/lineending_tests.rs"]
+mod tests;
@@ -1,3 +1,16 @@
 pub mod app;
 pub mod buffer;
+pub mod command;
 pub mod cursor;
+pub mod diff;
+pub mod encoding;
+pub mod history;
+pub mod lineending;
+pub mod nodelist;
+pub mod original;
+pub mod registers;
+pub mod search;
+pub mod swap;
+pub mod taggedtext;
+pub mod texttag;
+pub mod vi;
@@ -1,3 +1,4 @@
+use std::cell::OnceCell;
 use std::collections::VecDeque;
 
 #[allow(dead_code)]
@@ -12,9 +13,36 @@ pub struct BufferNode {
     from: BufferType,
     index: usize,
     offset: usize,
-    line_offsets: Vec<usize>,
+    line_offsets: LineOffsets,
 }
 
+// Where the lines in a node start. Nodes over a mapped file count their
+// lines, and then look for where those start, only the first time either
+// is needed, so that a large file isn't gone through all at once.
+#[derive(Clone, PartialEq, Debug)]
+enum LineOffsets {
+    Known(Vec<usize>),
+    Lazy {
+        newlines: OnceCell<usize>,
+        found: OnceCell<Vec<usize>>,
+    },
+}
+
+// Offsets at which each line starts, relative to the start of `bytes`
+pub fn line_starts(bytes: &[u8]) -> Vec<usize> {
+    let mut offsets = vec![0];
+    offsets.extend(
+        bytes
+            .iter()
+            .enumerate()
+            .filter(|&(_, &byte)| byte == b'\n')
+            .map(|(idx, _)| idx + 1),
+    );
+    offsets
+}
+
+// Methods that need to know where lines start are given the node's own
+// bytes, for nodes that haven't looked yet.
 impl BufferNode {
     pub fn new(
         from: BufferType,
@@ -26,7 +54,34 @@ impl BufferNode {
             from,
             index,
             offset,
-            line_offsets,
+            line_offsets: LineOffsets::Known(line_offsets),
+        }
+    }
+
+    // Node with `newlines` lines breaks in it, which are only found once
+    // they are needed
+    pub fn counted(from: BufferType, index: usize, offset: usize, newlines: usize) -> BufferNode {
+        BufferNode {
+            from,
+            index,
+            offset,
+            line_offsets: LineOffsets::Lazy {
+                newlines: OnceCell::from(newlines),
+                found: OnceCell::new(),
+            },
+        }
+    }
+
+    // Node whose line breaks aren't even counted until they are needed
+    pub fn lazy(from: BufferType, index: usize, offset: usize) -> BufferNode {
+        BufferNode {
+            from,
+            index,
+            offset,
+            line_offsets: LineOffsets::Lazy {
+                newlines: OnceCell::new(),
+                found: OnceCell::new(),
+            },
         }
     }
 
@@ -42,24 +97,83 @@ impl BufferNode {
         self.from
     }
 
-    pub fn line_offsets(&mut self) -> &mut Vec<usize> {
-        &mut self.line_offsets
+    pub fn line_offsets(&self, bytes: &[u8]) -> &[usize] {
+        match &self.line_offsets {
+            LineOffsets::Known(offsets) => offsets,
+            LineOffsets::Lazy { found, .. } => found.get_or_init(|| line_starts(bytes)),
+        }
+    }
+
+    pub fn line_offset_at(&self, bytes: &[u8], idx: usize) -> usize {
+        self.line_offsets(bytes)[idx]
+    }
+
+    pub fn line_offsets_len(&self, bytes: &[u8]) -> usize {
+        let newlines = match &self.line_offsets {
+            LineOffsets::Known(offsets) => return offsets.len(),
+            LineOffsets::Lazy { newlines, found } => newlines.get_or_init(|| match found.get() {
+                Some(offsets) => offsets.len() - 1,
+                None => bytes.iter().filter(|&&byte| byte == b'\n').count(),
+            }),
+        };
+        newlines + 1
+    }
+
+    // Number of line breaks, if they have been counted yet
+    pub fn newlines_if_counted(&self) -> Option<usize> {
+        match &self.line_offsets {
+            LineOffsets::Known(offsets) => Some(offsets.len() - 1),
+            LineOffsets::Lazy { newlines, found } => newlines
+                .get()
+                .copied()
+                .or_else(|| found.get().map(|offsets| offsets.len() - 1)),
+        }
     }
 
-    pub fn line_offset_at(&self, idx: usize) -> usize {
-        self.line_offsets[idx]
+    pub fn last_line_offset(&self, bytes: &[u8]) -> usize {
+        *self.line_offsets(bytes).last().unwrap()
     }
 
-    pub fn line_offsets_len(&self) -> usize {
-        self.line_offsets.len()
+    pub fn has_newline(&self, bytes: &[u8]) -> bool {
+        self.line_offsets_len(bytes) > 1
     }
 
-    pub fn last_line_offset(&self) -> usize {
-        *self.line_offsets.last().unwrap()
+    // Index of the line within this node that `offset` falls on
+    pub fn line_idx_at(&self, bytes: &[u8], offset: usize) -> usize {
+        match self.line_offsets(bytes).binary_search(&offset) {
+            Ok(idx) => idx,
+            Err(idx) => idx - 1,
+        }
     }
 
-    pub fn has_newline(&self) -> bool {
-        self.line_offsets_len() > 1
+    // Start of the first line in this node that begins after `offset`
+    pub fn next_line_offset(&self, bytes: &[u8], offset: usize) -> Option<usize> {
+        self.line_offsets(bytes)
+            .get(self.line_idx_at(bytes, offset) + 1)
+            .copied()
+    }
+
+    // Splits the node into [0, at) and [at, offset)
+    pub fn split_at(&self, bytes: &[u8], at: usize) -> (BufferNode, BufferNode) {
+        assert!(at <= self.offset);
+        let line_offsets = self.line_offsets(bytes);
+        let left_offsets = line_offsets
+            .iter()
+            .cloned()
+            .filter(|&line_offset| line_offset <= at)
+            .collect();
+        let right_offsets = std::iter::once(0)
+            .chain(
+                line_offsets
+                    .iter()
+                    .filter(|&&line_offset| line_offset > at)
+                    .map(|line_offset| line_offset - at),
+            )
+            .collect();
+
+        let left = BufferNode::new(self.from, self.index, at, left_offsets);
+        let right = BufferNode::new(self.from, self.index + at, self.offset - at, right_offsets);
+        (left, right)
     }
 }
 
@@ -82,8 +196,8 @@ impl NodeList {
         let left_list: VecDeque<_> = contents.drain(..=0).collect();
         let right_list: VecDeque<_> = contents.drain(..).collect();
         NodeList {
-            left_list: left_list,
-            right_list: right_list,
+            left_list,
+            right_list,
         }
     }
 
@@ -182,12 +296,34 @@ impl NodeList {
         self.right_list.pop_front();
     }
 
+    // Replaces `count` nodes starting at `index` with `nodes`, leaving the
+    // current node just before the replaced run (or at the head)
+    pub fn splice(&mut self, index: usize, count: usize, nodes: Vec<BufferNode>) {
+        assert!(index + count <= self.len());
+        while self.left_list.len() > index {
+            let element = self.left_list.pop_back().unwrap();
+            self.right_list.push_front(element);
+        }
+        while self.left_list.len() < index {
+            self.move_right();
+        }
+
+        self.right_list.drain(..count);
+        for node in nodes.into_iter().rev() {
+            self.right_list.push_front(node);
+        }
+
+        if self.left_list.is_empty() {
+            self.move_right();
+        }
+    }
+
     pub fn at_head(&self) -> bool {
-        return self.left_list.len() == 1;
+        self.left_list.len() == 1
     }
 
     pub fn at_tail(&self) -> bool {
-        return self.right_list.len() == 0 && !self.is_empty();
+        self.right_list.is_empty() && !self.is_empty()
     }
 
     pub fn move_left(&mut self) {
@@ -200,7 +336,7 @@ impl NodeList {
     }
 
     pub fn move_right(&mut self) {
-        if self.right_list.len() < 1 {
+        if self.right_list.is_empty() {
             return;
         }
 
@@ -220,7 +356,7 @@ impl NodeList {
         }
     }
 
-    pub fn get_mut(&mut self, index: usize) -> &BufferNode {
+    pub fn get_mut(&mut self, index: usize) -> &mut BufferNode {
         assert!(!self.is_empty());
         if index < self.left_list.len() {
             self.left_list.get_mut(index).unwrap()
@@ -236,24 +372,26 @@ impl NodeList {
         self.left_list.iter().chain(self.right_list.iter())
     }
 
-    pub fn iter_until_curr(&self) -> impl Iterator<Item = &BufferNode> {
+    pub fn iter_until_curr(&self) -> impl DoubleEndedIterator<Item = &BufferNode> {
         self.left_list.iter()
     }
 
-    pub fn iter_from_after_curr(&self) -> impl Iterator<Item = &BufferNode> {
+    pub fn iter_from_after_curr(&self) -> impl DoubleEndedIterator<Item = &BufferNode> {
         self.right_list.iter()
     }
 
-    pub fn move_to_prev_newline(&mut self) {
+    // These take the bytes of each node, for nodes that haven't counted
+    // their lines yet
+    pub fn move_to_prev_newline<'a>(&mut self, bytes: impl Fn(&BufferNode) -> &'a [u8]) {
         self.move_left();
-        while !self.get_curr().has_newline() && !self.at_head() {
+        while !self.get_curr().has_newline(bytes(self.get_curr())) && !self.at_head() {
             self.move_left();
         }
     }
 
-    pub fn move_to_next_newline(&mut self) {
+    pub fn move_to_next_newline<'a>(&mut self, bytes: impl Fn(&BufferNode) -> &'a [u8]) {
         self.move_right();
-        while !self.get_curr().has_newline() && !self.at_tail() {
+        while !self.get_curr().has_newline(bytes(self.get_curr())) && !self.at_tail() {
             self.move_right();
         }
     }
@@ -277,4 +415,4 @@ impl PartialEq<Vec<BufferNode>> for NodeList {
 
 #[cfg(test)]
 #[path = "tests/nodelist_tests.rs"]
-mod node_list_tests;
+mod tests;
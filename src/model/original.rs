@@ -0,0 +1,360 @@
+use std::cell::OnceCell;
+use std::fmt;
+use std::fs::{File, Metadata};
+use std::io;
+use std::ops::Range;
+use std::os::unix::fs::MetadataExt;
+use std::str;
+
+use memmap2::Mmap;
+
+use super::encoding::{self, Encoding, RawBytes, RawRun};
+use crate::utils::stamp::checksum;
+
+// Files at least this long are mapped rather than read, where they can be
+pub const MAP_MIN_LEN: u64 = 1 << 24;
+
+// Length the contents are split into pieces at, give or take a line
+pub const PIECE_LEN: usize = 1 << 20;
+
+// How far past PIECE_LEN a piece may go to end with its line
+const PIECE_SLACK: usize = 1 << 12;
+
+// The original contents of a buffer, which the piece table never changes.
+// A large file that looks to need no converting is mapped straight from
+// disk, so opening it reads next to nothing up front and only the parts
+// looked at take up memory. Anything else is read in.
+//
+// Only the first piece of a file is looked at to decide whether it can be
+// mapped: it has to be UTF-8 without a byte order mark or any "\r". Each
+// of the other pieces is checked the first time it is looked at. Bytes in
+// them that aren't UTF-8 are shown as "?" in a copy of the piece, as a
+// replacement character wouldn't fit in their place, and are saved as they
+// were. A "\r" further on is kept as it is, as in a file with mixed line
+// endings.
+//
+// Cutting short a file that is mapped kills the editor with SIGBUS the
+// next time it looks past the new end. So a file is only mapped under a
+// lease, which keeps anything that opens it for writing or truncates it
+// waiting until the buffer has read it in (see `lease_broken`). A file that
+// is already open for writing, or can't be leased for any other reason, is
+// read instead, as are small files, where mapping gains little.
+pub struct Original {
+    store: Store,
+    // Worked out once, as swap files keep needing it
+    checksum: OnceCell<u64>,
+}
+
+enum Store {
+    // Along with the invalid bytes the contents were decoded from
+    Read {
+        bytes: Vec<u8>,
+        raw_bytes: RawBytes,
+    },
+    // Mapped from `file`, which is kept open for the lease on it
+    Mapped {
+        map: Mmap,
+        file: File,
+        dev: u64,
+        ino: u64,
+        pieces: Vec<Piece>,
+    },
+}
+
+// A piece of a mapped file, up to the start of the next one
+struct Piece {
+    start: usize,
+    checked: OnceCell<Checked>,
+}
+
+// What checking a piece of a mapped file for invalid UTF-8 found
+struct Checked {
+    // A copy of the piece with the invalid bytes marked, if it had any
+    marked: Option<Vec<u8>>,
+    raw_bytes: RawBytes,
+}
+
+impl Original {
+    pub fn new(bytes: Vec<u8>) -> Original {
+        Original {
+            store: Store::Read {
+                bytes,
+                raw_bytes: RawBytes::default(),
+            },
+            checksum: OnceCell::new(),
+        }
+    }
+
+    // Maps `file` if it is a regular file at least `min_len` long that
+    // looks to be usable as it is, and can be leased. Returns None for
+    // anything else, pipes included, which has to be read instead.
+    pub fn map(file: &File, min_len: u64) -> io::Result<Option<Original>> {
+        let metadata = file.metadata()?;
+        if !metadata.is_file() || metadata.len() == 0 || metadata.len() < min_len {
+            return Ok(None);
+        }
+        if !take_lease(file) {
+            return Ok(None);
+        }
+
+        // Safe as long as the file isn't cut short while mapped, which the
+        // lease sees to
+        let map = unsafe { Mmap::map(file)? };
+        let starts = piece_starts(&map);
+        let first = &map[..starts.get(1).copied().unwrap_or(map.len())];
+        let as_is = !first.starts_with(Encoding::Utf8Bom.bom())
+            && !first.contains(&b'\r')
+            && str::from_utf8(first).is_ok();
+        if !as_is {
+            return Ok(None);
+        }
+        let pieces = starts
+            .into_iter()
+            .map(|start| Piece {
+                start,
+                checked: OnceCell::new(),
+            })
+            .collect();
+        Ok(Some(Original {
+            store: Store::Mapped {
+                map,
+                file: file.try_clone()?,
+                dev: metadata.dev(),
+                ino: metadata.ino(),
+                pieces,
+            },
+            checksum: OnceCell::new(),
+        }))
+    }
+
+    // Keeps the invalid bytes that read contents were decoded from
+    pub fn set_raw_bytes(&mut self, raw_bytes: RawBytes) {
+        if let Store::Read {
+            raw_bytes: kept, ..
+        } = &mut self.store
+        {
+            *kept = raw_bytes;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.store {
+            Store::Read { bytes, .. } => bytes.len(),
+            Store::Mapped { map, .. } => map.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.store, Store::Mapped { .. })
+    }
+
+    // Whether this is mapped from the file with `metadata`
+    pub fn is_mapped_from(&self, metadata: &Metadata) -> bool {
+        match self.store {
+            Store::Mapped { dev, ino, .. } => (dev, ino) == (metadata.dev(), metadata.ino()),
+            Store::Read { .. } => false,
+        }
+    }
+
+    // Whether something is waiting to write to the file this is mapped
+    // from, which it can do once this is read in
+    pub fn lease_broken(&self) -> bool {
+        match &self.store {
+            Store::Mapped { file, .. } => !holds_lease(file),
+            Store::Read { .. } => false,
+        }
+    }
+
+    // The bytes in `range`, which mustn't run from one piece into another
+    pub fn bytes(&self, range: Range<usize>) -> &[u8] {
+        match &self.store {
+            Store::Read { bytes, .. } => &bytes[range],
+            Store::Mapped { map, pieces, .. } => {
+                let idx = pieces.partition_point(|piece| piece.start <= range.start) - 1;
+                let (start, end) = piece_bounds(pieces, idx, map.len());
+                assert!(range.end <= end, "range runs past the end of a piece");
+                match &self.checked(idx).marked {
+                    Some(marked) => &marked[range.start - start..range.end - start],
+                    None => &map[range],
+                }
+            }
+        }
+    }
+
+    // Where the piece that `offset` is in ends
+    pub fn piece_end(&self, offset: usize) -> usize {
+        match &self.store {
+            Store::Read { bytes, .. } => bytes.len(),
+            Store::Mapped { map, pieces, .. } => {
+                let idx = pieces.partition_point(|piece| piece.start <= offset) - 1;
+                piece_bounds(pieces, idx, map.len()).1
+            }
+        }
+    }
+
+    // Where each piece is, which for contents that were read are only
+    // worked out now
+    pub fn pieces(&self) -> Vec<Range<usize>> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let starts = match &self.store {
+            Store::Read { bytes, .. } => piece_starts(bytes),
+            Store::Mapped { pieces, .. } => pieces.iter().map(|piece| piece.start).collect(),
+        };
+        let ends = starts.iter().skip(1).copied().chain(Some(self.len()));
+        starts
+            .iter()
+            .zip(ends)
+            .map(|(&start, end)| start..end)
+            .collect()
+    }
+
+    // Invalid bytes from the file with stand-ins from `start` up to `end`
+    pub fn raw_bytes_within(&self, start: usize, end: usize) -> Vec<&RawRun> {
+        match &self.store {
+            Store::Read { raw_bytes, .. } => raw_bytes.within(start, end).iter().collect(),
+            Store::Mapped { pieces, .. } => {
+                let first = pieces.partition_point(|piece| piece.start <= start) - 1;
+                let last = pieces.partition_point(|piece| piece.start < end);
+                (first..last)
+                    .flat_map(|idx| self.checked(idx).raw_bytes.within(start, end))
+                    .collect()
+            }
+        }
+    }
+
+    // A copy held in memory, for when the file is about to be written over
+    pub fn read_in(&self) -> Original {
+        let mut bytes = Vec::with_capacity(self.len());
+        let mut raw_bytes = RawBytes::default();
+        for range in self.pieces() {
+            bytes.extend_from_slice(self.bytes(range.clone()));
+            for run in self.raw_bytes_within(range.start, range.end) {
+                raw_bytes.push(run.clone());
+            }
+        }
+        // Of the file's own bytes, so that swap files still match it
+        let checksum = OnceCell::from(self.checksum());
+        Original {
+            store: Store::Read { bytes, raw_bytes },
+            checksum,
+        }
+    }
+
+    pub fn checksum(&self) -> u64 {
+        *self.checksum.get_or_init(|| match &self.store {
+            Store::Read { bytes, .. } => checksum(bytes),
+            Store::Mapped { map, .. } => checksum(map),
+        })
+    }
+
+    // Piece `idx` of a mapped file, checked the first time it is needed
+    fn checked(&self, idx: usize) -> &Checked {
+        let (map, pieces) = match &self.store {
+            Store::Mapped { map, pieces, .. } => (map, pieces),
+            Store::Read { .. } => unreachable!("only mapped files are checked"),
+        };
+        pieces[idx].checked.get_or_init(|| {
+            let (start, end) = piece_bounds(pieces, idx, map.len());
+            match encoding::mark_invalid_utf8(&map[start..end], start) {
+                Some((marked, raw_bytes)) => Checked {
+                    marked: Some(marked),
+                    raw_bytes,
+                },
+                None => Checked {
+                    marked: None,
+                    raw_bytes: RawBytes::default(),
+                },
+            }
+        })
+    }
+}
+
+impl PartialEq<Vec<u8>> for Original {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        self.len() == other.len()
+            && self
+                .pieces()
+                .into_iter()
+                .all(|range| self.bytes(range.clone()) == &other[range])
+    }
+}
+
+// The contents can be gigabytes long, so only their length is shown
+impl fmt::Debug for Original {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Original")
+            .field("len", &self.len())
+            .field("mapped", &self.is_mapped())
+            .finish()
+    }
+}
+
+fn piece_bounds(pieces: &[Piece], idx: usize, len: usize) -> (usize, usize) {
+    let end = pieces.get(idx + 1).map_or(len, |next| next.start);
+    (pieces[idx].start, end)
+}
+
+// Where each piece of `bytes` starts: every PIECE_LEN or so, at the start
+// of the next line, or at the start of the next character in a line that
+// goes on for too long
+fn piece_starts(bytes: &[u8]) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut start = 0;
+    while bytes.len() - start > PIECE_LEN {
+        let from = start + PIECE_LEN;
+        let slack = &bytes[from..bytes.len().min(from + PIECE_SLACK)];
+        start = match slack.iter().position(|&byte| byte == b'\n') {
+            Some(idx) => from + idx + 1,
+            // UTF-8 continuation bytes are never the start of a character
+            None => (from..bytes.len())
+                .find(|&idx| bytes[idx] & 0xC0 != 0x80)
+                .unwrap_or(bytes.len()),
+        };
+        if start < bytes.len() {
+            starts.push(start);
+        }
+    }
+    starts
+}
+
+// Takes a read lease on `file`, which can't be had while anything has it
+// open for writing. Anything that then opens it for writing has to wait
+// until the lease is given up. The signal sent to ask for that is ignored,
+// as `lease_broken` is asked on every tick instead.
+#[cfg(target_os = "linux")]
+fn take_lease(file: &File) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    unsafe {
+        libc::signal(libc::SIGIO, libc::SIG_IGN);
+        libc::fcntl(file.as_raw_fd(), libc::F_SETLEASE, libc::F_RDLCK) == 0
+    }
+}
+
+// Leases are only to be had on Linux, so files are always read elsewhere
+#[cfg(not(target_os = "linux"))]
+fn take_lease(_file: &File) -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn holds_lease(file: &File) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLEASE) == libc::F_RDLCK }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn holds_lease(_file: &File) -> bool {
+    false
+}
+
+#[cfg(test)]
+#[path = "tests/original_tests.rs"]
+mod tests;
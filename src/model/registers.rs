@@ -0,0 +1,90 @@
+use std::collections::{HashMap, VecDeque};
+
+// Number of yanks and cuts kept around, reachable as registers `0` to `9`
+pub const RING_SIZE: usize = 10;
+
+// Text held by a register. Whole lines, as taken by `dd` or `yy`, are
+// pasted onto lines of their own.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Register {
+    pub text: String,
+    pub linewise: bool,
+}
+
+impl Register {
+    pub fn new(text: String, linewise: bool) -> Register {
+        Register { text, linewise }
+    }
+}
+
+// Vi style registers: an unnamed one that every yank and cut goes to,
+// lettered ones `a` to `z` that are only written when asked for, and a
+// ring of recent yanks and cuts with the latest as `0`
+#[derive(Default, Debug)]
+pub struct Registers {
+    unnamed: Register,
+    named: HashMap<char, Register>,
+    ring: VecDeque<Register>,
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        Registers::default()
+    }
+
+    // Whether `name` can be written to or read from. `"` is the unnamed
+    // register and an uppercase letter appends to its lowercase register.
+    pub fn is_valid_name(name: char) -> bool {
+        name == '"' || name.is_ascii_alphanumeric()
+    }
+
+    // Stores yanked or cut text in the unnamed register, the ring and the
+    // register `name` if one is given. Appending to a register puts all of
+    // it in the unnamed one, but only the text just taken on the ring.
+    pub fn store(&mut self, name: Option<char>, register: Register) {
+        if register.text.is_empty() {
+            return;
+        }
+
+        self.ring.push_front(register.clone());
+        self.ring.truncate(RING_SIZE);
+        self.unnamed = match name {
+            Some(name) if name.is_ascii_uppercase() => {
+                let named = self.named.entry(name.to_ascii_lowercase()).or_default();
+                named.text.push_str(&register.text);
+                named.linewise |= register.linewise;
+                named.clone()
+            }
+            Some(name) if name.is_ascii_lowercase() => {
+                self.named.insert(name, register.clone());
+                register
+            }
+            _ => register,
+        };
+    }
+
+    // Contents of register `name`, or of the unnamed one if none is given
+    pub fn get(&self, name: Option<char>) -> Option<&Register> {
+        let register = match name {
+            None | Some('"') => Some(&self.unnamed),
+            Some(name) if name.is_ascii_digit() => {
+                self.ring.get(name.to_digit(10).unwrap() as usize)
+            }
+            Some(name) => self.named.get(&name.to_ascii_lowercase()),
+        };
+        register.filter(|register| !register.text.is_empty())
+    }
+
+    // Entry `index` of the ring, counting round from the latest again
+    // past the oldest
+    pub fn ring_entry(&self, index: usize) -> Option<&Register> {
+        if self.ring.is_empty() {
+            return None;
+        }
+        self.ring.get(index % self.ring.len())
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/registers_tests.rs"]
+mod tests;
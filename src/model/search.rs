@@ -0,0 +1,225 @@
+use regex::{Captures, Regex, RegexBuilder};
+
+use super::buffer::Buffer;
+
+// Regex search, as typed after `/` or `?` on the command line, and the
+// matching and replacing behind `:s`. `^` and `$` match at every line.
+
+// Length of text searched at a time, give or take a line
+const BLOCK_LEN: usize = 1 << 16;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+impl SearchDirection {
+    pub fn reversed(self) -> SearchDirection {
+        match self {
+            SearchDirection::Forward => SearchDirection::Backward,
+            SearchDirection::Backward => SearchDirection::Forward,
+        }
+    }
+}
+
+// A search that `n` and `N` repeat
+#[derive(Clone, PartialEq, Debug)]
+pub struct Search {
+    pub pattern: String,
+    pub direction: SearchDirection,
+}
+
+impl Search {
+    pub fn new(pattern: String, direction: SearchDirection) -> Search {
+        Search { pattern, direction }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    // Whether the search went past the end of the text (or the start,
+    // searching backward) to find it
+    pub wrapped: bool,
+}
+
+// Text to put in place of the match between `start` and `end`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Replacement {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    // Line the match starts on
+    pub line: usize,
+}
+
+pub fn compile(pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .multi_line(true)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|_| format!("Invalid pattern: {}", pattern))
+}
+
+// Start and end offsets of every match of `regex` in `text`, including
+// ones that overlap
+pub fn find_matches(text: &str, regex: &Regex) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    let mut from = 0;
+    while let Some(found) = regex.find_at(text, from) {
+        matches.push((found.start(), found.end()));
+        // Move on by a whole char so the next search starts on a boundary
+        match text[found.start()..].chars().next() {
+            Some(c) => from = found.start() + c.len_utf8(),
+            None => break,
+        }
+    }
+    matches
+}
+
+// The first match of `regex` after the offset `from` in `direction`,
+// wrapping around the end of the buffer if there is none before it. A
+// match at `from` itself is only found once every other one has been
+// passed. The buffer is read in blocks of whole lines outwards from the
+// line `from` is on, so a match nearby is found without copying the rest,
+// but a match can't run from one block into the next.
+pub fn find_next(
+    buffer: &Buffer,
+    regex: &Regex,
+    from: usize,
+    direction: SearchDirection,
+) -> Option<Match> {
+    let len = buffer.len();
+    let (line_start, line_end) = buffer.line_bounds(from);
+    let mut wrapped = false;
+    let (mut start, mut end) = match direction {
+        SearchDirection::Forward => (line_start, block_end(buffer, line_start)),
+        SearchDirection::Backward => (block_start(buffer, line_end), line_end),
+    };
+    loop {
+        let matches = block_matches(buffer, regex, start, end);
+        let found = match direction {
+            SearchDirection::Forward => matches
+                .into_iter()
+                .find(|&(match_start, _)| wrapped || match_start > from),
+            SearchDirection::Backward => matches
+                .into_iter()
+                .rev()
+                .find(|&(match_start, _)| wrapped || match_start < from),
+        };
+        if let Some((start, end)) = found {
+            return Some(Match {
+                start,
+                end,
+                wrapped,
+            });
+        }
+
+        // Back round as far as the line the search started on
+        match direction {
+            SearchDirection::Forward if wrapped && end >= line_end => return None,
+            SearchDirection::Backward if wrapped && start <= line_start => return None,
+            SearchDirection::Forward if end == len => {
+                wrapped = true;
+                start = 0;
+            }
+            SearchDirection::Backward if start == 0 => {
+                wrapped = true;
+                end = len;
+            }
+            SearchDirection::Forward => start = end,
+            SearchDirection::Backward => end = start,
+        }
+        match direction {
+            SearchDirection::Forward => end = block_end(buffer, start),
+            SearchDirection::Backward => start = block_start(buffer, end),
+        }
+    }
+}
+
+// End of the line BLOCK_LEN on from `start`
+fn block_end(buffer: &Buffer, start: usize) -> usize {
+    buffer.line_bounds((start + BLOCK_LEN).min(buffer.len())).1
+}
+
+// Start of the line BLOCK_LEN back from `end`
+fn block_start(buffer: &Buffer, end: usize) -> usize {
+    buffer.line_bounds(end.saturating_sub(BLOCK_LEN)).0
+}
+
+// Matches between `start` and `end`, which are line starts. The block
+// ends after a line break, where `^` and `$` would match even though the
+// next line may not be empty, so nothing starting there counts unless
+// it is the end of the text.
+fn block_matches(buffer: &Buffer, regex: &Regex, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let text = buffer.text_between(start, end);
+    find_matches(&text, regex)
+        .into_iter()
+        .filter(|&(match_start, _)| match_start < text.len() || end == buffer.len())
+        .map(|(match_start, match_end)| (start + match_start, start + match_end))
+        .collect()
+}
+
+// Replacements for the matches of `regex` on `lines` of `text`, each of
+// which starts at the offset given with it. Only the first match on each
+// line is replaced unless `global` is set.
+pub fn replacements(
+    text: &str,
+    lines: &[(usize, usize)],
+    regex: &Regex,
+    template: &str,
+    global: bool,
+) -> Vec<Replacement> {
+    let mut replacements = Vec::new();
+    for (line, &(line_start, line_end)) in lines.iter().enumerate() {
+        let content = &text[line_start..line_end];
+        let content = content.strip_suffix('\n').unwrap_or(content);
+        let content = content.strip_suffix('\r').unwrap_or(content);
+
+        for captures in regex.captures_iter(content) {
+            let found = captures.get(0).unwrap();
+            replacements.push(Replacement {
+                start: line_start + found.start(),
+                end: line_start + found.end(),
+                text: expand_replacement(template, &captures),
+                line,
+            });
+            if !global {
+                break;
+            }
+        }
+    }
+    replacements
+}
+
+// Fills in a vi style replacement: `&` or `\0` is the whole match, `\1`
+// to `\9` are capture groups and `\r` or `\n` starts a new line. Any
+// other char after a backslash stands for itself.
+pub fn expand_replacement(template: &str, captures: &Captures) -> String {
+    let group = |idx: usize| captures.get(idx).map_or("", |found| found.as_str());
+
+    let mut expanded = String::with_capacity(template.len());
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => expanded.push_str(group(0)),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => {
+                    expanded.push_str(group(digit.to_digit(10).unwrap() as usize))
+                }
+                Some('r') | Some('n') => expanded.push('\n'),
+                Some('t') => expanded.push('\t'),
+                Some(escaped) => expanded.push(escaped),
+                None => expanded.push('\\'),
+            },
+            _ => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+#[path = "tests/search_tests.rs"]
+mod tests;
@@ -0,0 +1,156 @@
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use serde::{Deserialize, Serialize};
+use tempfile::Builder;
+
+use super::buffer::Buffer;
+use super::nodelist::BufferType;
+
+// Unsaved edits kept next to the file being edited, so they can be
+// recovered if the editor dies before they are saved. The original
+// contents are still in the file itself, so only the added store and the
+// pieces that make up the text are kept, along with enough to tell if the
+// file has changed since.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Swap {
+    // Process that wrote the swap file
+    pub pid: u32,
+    // Length and checksum of the original contents the pieces refer to
+    pub original_len: usize,
+    pub original_checksum: u64,
+    pub added: String,
+    pub pieces: Vec<Piece>,
+    pub cursor: usize,
+}
+
+// A run of text from one of the two stores
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+pub struct Piece {
+    pub added: bool,
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Swap {
+    pub fn from_buffer(buffer: &Buffer) -> Swap {
+        let pieces = buffer
+            .pieces()
+            .map(|(from, start, len)| Piece {
+                added: from == BufferType::Added,
+                start,
+                len,
+            })
+            .collect();
+        Swap {
+            pid: process::id(),
+            original_len: buffer.original().len(),
+            original_checksum: buffer.original_checksum(),
+            added: String::from_utf8_lossy(buffer.added()).into_owned(),
+            pieces,
+            cursor: buffer.cursor_position(),
+        }
+    }
+
+    // Puts the edits back into `buffer`, freshly opened from the file the
+    // swap file was written for. The original contents it was opened with
+    // are kept as they are, mapped or not, rather than copied.
+    pub fn restore(&self, buffer: &mut Buffer) -> Result<(), String> {
+        if buffer.original().len() != self.original_len
+            || buffer.original_checksum() != self.original_checksum
+        {
+            return Err(String::from("File changed since the swap file was written"));
+        }
+
+        let pieces: Vec<_> = self
+            .pieces
+            .iter()
+            .map(|piece| {
+                let from = if piece.added {
+                    BufferType::Added
+                } else {
+                    BufferType::Original
+                };
+                (from, piece.start, piece.len)
+            })
+            .collect();
+        if !buffer.restore_pieces(self.added.clone(), &pieces) {
+            return Err(String::from("Swap file is corrupt"));
+        }
+        buffer.move_cursor_to(self.cursor.min(buffer.len()));
+        Ok(())
+    }
+
+    // Whether the process that wrote the swap file is still running, in
+    // which case the file is most likely open in another editor
+    pub fn owner_is_running(&self) -> bool {
+        let pid = match libc::pid_t::try_from(self.pid) {
+            Ok(pid) if pid > 0 && self.pid != process::id() => pid,
+            _ => return false,
+        };
+        // Signal 0 only checks that the process is there. Being refused
+        // means it belongs to someone else, but is there all the same.
+        let signalled = unsafe { libc::kill(pid, 0) } == 0;
+        signalled || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+
+    pub fn read(path: &Path) -> io::Result<Swap> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Makes a new swap file at `path`. Anything already there, a symlink
+    // included, is left alone and the write fails, as it could be another
+    // editor's swap file or a link planted to get some other file written
+    // over. It holds the text being edited, so only its owner may read it,
+    // whoever else can read the file.
+    pub fn create(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string(self)?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(json.as_bytes())?;
+        file.sync_all()
+    }
+
+    // Replaces the swap file at `path`, made earlier by `create`, so that a
+    // crash while writing leaves the last one whole. The new one is written
+    // next to it, where temporary files are readable by their owner only,
+    // then renamed over it. Renaming replaces a symlink put in its place
+    // rather than the file it points to.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string(self)?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut temp = Builder::new()
+            .prefix(path.file_name().unwrap_or_default())
+            .suffix(".tmp")
+            .tempfile_in(dir)?;
+        temp.write_all(json.as_bytes())?;
+        temp.as_file().sync_all()?;
+        temp.persist(path).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
+// `dir/.name.swp` for `dir/name`, as vi names them
+pub fn swap_path(file_path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(file_path.file_name().unwrap_or_default());
+    name.push(".swp");
+    file_path.with_file_name(name)
+}
+
+#[cfg(test)]
+#[path = "tests/swap_tests.rs"]
+mod tests;
@@ -12,6 +12,7 @@ impl TaggedText {
         TaggedText { text, tags }
     }
 
+    #[cfg(test)]
     pub fn text(&self) -> &String {
         &self.text
     }
@@ -24,11 +25,9 @@ impl TaggedText {
         &self.tags
     }
 
-    pub fn text_mut(&mut self) -> &mut String {
-        &mut self.text
-    }
-
-    pub fn tags_mut(&mut self) -> &mut Vec<TextTag> {
-        &mut self.tags
+    // Adds `tags`, keeping them all in order
+    pub fn add_tags(&mut self, tags: impl IntoIterator<Item = TextTag>) {
+        self.tags.extend(tags);
+        self.tags.sort_unstable();
     }
 }
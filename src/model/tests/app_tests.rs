@@ -1,10 +1,14 @@
 #[cfg(test)]
 mod app_tests {
     use super::super::*;
+    use std::cell::RefCell;
     use std::fs::{self, File};
     use std::io::{self, Write};
     use std::os::unix::fs::PermissionsExt;
     use std::path::Path;
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Instant;
     use tempfile::tempdir;
 
     #[test]
@@ -15,7 +19,7 @@ mod app_tests {
         let file_path_string = file_path.to_string_lossy().into_owned();
 
         // Prepare the application
-        App::init_new_file(file_path_string.clone());
+        App::init_new_file(file_path_string.clone())?;
 
         assert!(Path::new(&file_path_string).exists());
 
@@ -80,9 +84,12 @@ mod app_tests {
     }
 
     #[test]
-    #[should_panic(expected = "Permission denied")]
     fn open_writeonly_file_fail() {
-        // Doesn't return io::Result<()> like other tests because should_panic must return unit
+        // Root can read the file whatever its permissions
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+
         // Prepare the files
         let dir = tempdir().expect("Failed to create tempdir");
         let file_path = dir.path().join("temp.txt");
@@ -105,6 +112,101 @@ mod app_tests {
         app.command_buffer = Buffer::with_contents(file_path_string.clone());
 
         app.open_file();
+
+        let message = app.message().expect("No error message was shown");
+        assert!(message.contains("Permission denied"));
+        assert_eq!(app.mode(), AppMode::Edit);
+    }
+
+    #[test]
+    fn open_file_under_regular_file_fail() {
+        // Nobody, root included, can make a file inside a regular file
+        let dir = tempdir().expect("Failed to create tempdir");
+        let parent = dir.path().join("temp.txt");
+        fs::write(&parent, "").expect("Failed to create file");
+        let file_path_string = parent.join("temp.txt").to_string_lossy().into_owned();
+
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.set_mode(AppMode::Command(CommandMode::Read));
+        app.command_buffer = Buffer::with_contents(file_path_string);
+
+        app.open_file();
+
+        let message = app.message().expect("No error message was shown");
+        assert!(message.contains("Unable to open file"));
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.file_path, None);
+    }
+
+    #[test]
+    fn new_with_file_argument() -> io::Result<()> {
+        // Prepare the files
+        let dir = tempdir()?;
+        let file_path = dir.path().join("temp.txt");
+        let file_path_string = file_path.to_string_lossy().into_owned();
+
+        let mut file = File::create(file_path.clone())?;
+        write!(file, "Opened from args!")?;
+
+        drop(file);
+
+        // Prepare the application
+        let args = vec![String::from("raccoon-editor"), file_path_string.clone()];
+        let app = App::new(&args);
+
+        assert_eq!(app.get_buffer_text(), "Opened from args!");
+        assert_eq!(app.file_path(), Some(&file_path_string));
+        assert_eq!(app.mode(), AppMode::Edit);
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn new_with_file_argument_not_created_yet() -> io::Result<()> {
+        // Prepare the files
+        let dir = tempdir()?;
+        let file_path = dir.path().join("temp.txt");
+        let file_path_string = file_path.to_string_lossy().into_owned();
+
+        // Prepare the application
+        let args = vec![String::from("raccoon-editor"), file_path_string.clone()];
+        let app = App::new(&args);
+
+        assert!(
+            Path::new(&file_path_string).exists(),
+            "A new file is not created"
+        );
+        assert_eq!(app.get_buffer_text(), "");
+        assert_eq!(app.file_path(), Some(&file_path_string));
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn handle_regular_save_with_an_opened_file() -> io::Result<()> {
+        // Prepare the files
+        let dir = tempdir()?;
+        let file_path = dir.path().join("temp.txt");
+        let file_path_string = file_path.to_string_lossy().into_owned();
+
+        // Prepare the application
+        let args = vec![String::from("raccoon-editor"), file_path_string];
+        let mut app = App::new(&args);
+        app.add_char('a');
+        app.add_char('\n');
+        app.add_char('b');
+
+        app.handle_regular_save();
+        assert_eq!(app.mode(), AppMode::Edit);
+
+        let saved_text = fs::read_to_string(file_path)?;
+        assert_eq!(saved_text, "a\nb");
+
+        dir.close()?;
+        Ok(())
     }
 
     #[test]
@@ -134,9 +236,12 @@ mod app_tests {
     }
 
     #[test]
-    #[should_panic(expected = "Unable to write file")]
     fn save_readonly_file_fail() {
-        // Doesn't return io::Result<()> like other tests because should_panic must return unit
+        // Root can write to the file whatever its permissions
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+
         // Prepare the files
         let dir = tempdir().expect("Failed to create tempdir");
         let file_path = dir.path().join("temp.txt");
@@ -157,6 +262,30 @@ mod app_tests {
         app.buffer = Buffer::with_contents(String::from("Testing Write, this should fail!"));
 
         app.save_file();
+
+        let message = app.message().expect("No error message was shown");
+        assert!(message.contains("Unable to write file"));
+    }
+
+    #[test]
+    fn save_file_under_regular_file_fail() {
+        // Nobody, root included, can write a file inside a regular file
+        let dir = tempdir().expect("Failed to create tempdir");
+        let parent = dir.path().join("temp.txt");
+        fs::write(&parent, "").expect("Failed to create file");
+        let file_path_string = parent.join("temp.txt").to_string_lossy().into_owned();
+
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.set_mode(AppMode::Command(CommandMode::Write));
+        app.command_buffer = Buffer::with_contents(file_path_string);
+        app.buffer = Buffer::with_contents(String::from("Testing Write, this should fail!"));
+
+        assert!(!app.save_file());
+
+        let message = app.message().expect("No error message was shown");
+        assert!(message.contains("Unable to write file"));
+        assert_eq!(fs::read_to_string(&parent).unwrap(), "");
     }
 
     #[test]
@@ -262,6 +391,154 @@ mod app_tests {
         assert_eq!(app.mode(), AppMode::Command(CommandMode::Write));
     }
 
+    #[test]
+    fn execute_write_command_with_file_name() -> io::Result<()> {
+        // Prepare the files
+        let dir = tempdir()?;
+        let file_path = dir.path().join("temp.txt");
+        let file_path_string = file_path.to_string_lossy().into_owned();
+
+        // Prepare the application
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("Testing :w!"));
+        app.handle_command_line();
+        app.command_buffer = Buffer::with_contents(format!("w {}", file_path_string));
+
+        assert_eq!(app.submit_command(), QuitOption::NotQuitting);
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.file_path(), Some(&file_path_string));
+
+        let saved_text = fs::read_to_string(file_path)?;
+        assert_eq!(saved_text, "Testing :w!");
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn execute_write_command_with_no_filepath() {
+        // Prepare the application
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.handle_command_line();
+        app.command_buffer = Buffer::with_contents(String::from("w"));
+
+        assert_eq!(app.submit_command(), QuitOption::NotQuitting);
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.message(), Some(&String::from("No file name")));
+    }
+
+    #[test]
+    fn execute_edit_command() -> io::Result<()> {
+        // Prepare the files
+        let dir = tempdir()?;
+        let file_path = dir.path().join("temp.txt");
+        let file_path_string = file_path.to_string_lossy().into_owned();
+
+        let mut file = File::create(file_path)?;
+        write!(file, "Testing :e!")?;
+
+        drop(file);
+
+        // Prepare the application
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.handle_command_line();
+        app.command_buffer = Buffer::with_contents(format!("e {}", file_path_string));
+
+        assert_eq!(app.submit_command(), QuitOption::NotQuitting);
+        assert_eq!(app.get_buffer_text(), "Testing :e!");
+        assert_eq!(app.file_path(), Some(&file_path_string));
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn edit_command_keeps_unsaved_changes() -> io::Result<()> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("temp.txt");
+        fs::write(&file_path, "other file")?;
+
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.add_char('x');
+        run_command(&mut app, &format!("e {}", file_path.to_string_lossy()));
+
+        assert_eq!(app.get_buffer_text(), "x");
+        assert_eq!(app.file_path(), None);
+        assert_eq!(
+            app.message(),
+            Some(&String::from(
+                "No write since last change (add ! to override)"
+            ))
+        );
+
+        // Nor with an encoding given
+        run_command(
+            &mut app,
+            &format!("e ++enc=latin1 {}", file_path.to_string_lossy()),
+        );
+        assert_eq!(app.get_buffer_text(), "x");
+        assert_eq!(app.file_path(), None);
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn forced_edit_command_throws_away_changes() -> io::Result<()> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("temp.txt");
+        let file_path_string = file_path.to_string_lossy().into_owned();
+        fs::write(&file_path, "other file")?;
+
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.add_char('x');
+        run_command(&mut app, &format!("e! {}", file_path_string));
+
+        assert_eq!(app.get_buffer_text(), "other file");
+        assert_eq!(app.file_path(), Some(&file_path_string));
+        assert!(!app.buffer().is_modified());
+
+        // And reloads the current file with `++enc`
+        app.add_char('x');
+        run_command(&mut app, "e! ++enc=latin1");
+        assert_eq!(app.get_buffer_text(), "other file");
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn execute_quit_command() {
+        // Prepare the application
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.handle_command_line();
+        app.command_buffer = Buffer::with_contents(String::from("q"));
+
+        assert_eq!(app.submit_command(), QuitOption::Quitting);
+    }
+
+    #[test]
+    fn execute_unknown_command() {
+        // Prepare the application
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.handle_command_line();
+        app.command_buffer = Buffer::with_contents(String::from("frobnicate"));
+
+        assert_eq!(app.submit_command(), QuitOption::NotQuitting);
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(
+            app.message(),
+            Some(&String::from("Not an editor command: frobnicate"))
+        );
+    }
+
     #[test]
     fn add_char_in_edit_mode() {
         // Prepare the application
@@ -305,4 +582,1499 @@ mod app_tests {
         assert_eq!("", app.buffer.as_str()); // Buffer should still be the same
         assert_eq!("ab", app.command_buffer.as_str());
     }
+
+    #[test]
+    fn undo_and_redo_in_edit_mode() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+
+        app.add_char('a');
+        app.add_char(' ');
+        app.add_char('b');
+        app.undo();
+        assert_eq!("a ", app.buffer.as_str());
+        app.redo();
+        assert_eq!("a b", app.buffer.as_str());
+
+        app.redo();
+        assert_eq!(
+            Some(&String::from("Already at newest change")),
+            app.message()
+        );
+    }
+
+    #[test]
+    fn execute_set_tab_stop_command() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+
+        app.handle_command_line();
+        for c in "set ts=4".chars() {
+            app.add_char(c);
+        }
+        app.submit_command();
+        assert_eq!(4, app.buffer().tab_stop());
+
+        app.handle_command_line();
+        for c in "set ts=0".chars() {
+            app.add_char(c);
+        }
+        app.submit_command();
+        assert_eq!(4, app.buffer().tab_stop());
+        assert_eq!(Some(&String::from("Invalid argument: ts=0")), app.message());
+
+        app.handle_command_line();
+        for c in "set wrap=1".chars() {
+            app.add_char(c);
+        }
+        app.submit_command();
+        assert_eq!(Some(&String::from("Unknown option: wrap")), app.message());
+    }
+
+    #[test]
+    fn page_down_and_up() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        for _ in 0..10 {
+            app.add_char('\n');
+        }
+        app.page_up(4);
+        assert_eq!(6, app.buffer().current_line());
+        app.page_up(20);
+        assert_eq!(0, app.buffer().current_line());
+        app.page_down(4);
+        assert_eq!(4, app.buffer().current_line());
+    }
+
+    #[test]
+    fn execute_line_number_commands() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        assert_eq!(LineNumbers::Absolute, app.line_numbers());
+
+        for (command, line_numbers) in [
+            ("set rnu", LineNumbers::Relative),
+            ("set nonumber", LineNumbers::Off),
+            ("set number", LineNumbers::Absolute),
+        ] {
+            app.handle_command_line();
+            for c in command.chars() {
+                app.add_char(c);
+            }
+            app.submit_command();
+            assert_eq!(line_numbers, app.line_numbers());
+        }
+    }
+
+    fn type_vi_keys(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            app.handle_vi_key(Key::Char(c));
+        }
+    }
+
+    fn modal_app_with_contents(contents: &str) -> App {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from(contents));
+        app.set_modal(true);
+        app
+    }
+
+    #[test]
+    fn set_vi_command_toggles_modal_editing() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        assert!(!app.is_modal());
+
+        app.handle_command_line();
+        for c in "set vi".chars() {
+            app.add_char(c);
+        }
+        app.submit_command();
+        assert!(app.is_modal());
+        assert_eq!(AppMode::Normal, app.mode());
+
+        // Commands hand back to Normal mode rather than Edit mode
+        app.handle_command_line();
+        app.cancel_command();
+        assert_eq!(AppMode::Normal, app.mode());
+
+        app.set_modal(false);
+        assert_eq!(AppMode::Edit, app.mode());
+    }
+
+    #[test]
+    fn vi_delete_word_with_count() {
+        let mut app = modal_app_with_contents("one two three four");
+        type_vi_keys(&mut app, "w2dw");
+        assert_eq!("one four", app.get_buffer_text());
+        assert_eq!("two three ", app.registers.get(None).unwrap().text);
+
+        type_vi_keys(&mut app, "u");
+        assert_eq!("one two three four", app.get_buffer_text());
+    }
+
+    #[test]
+    fn vi_change_word_enters_edit_mode() {
+        let mut app = modal_app_with_contents("one two");
+        type_vi_keys(&mut app, "cw");
+        assert_eq!(AppMode::Edit, app.mode());
+        for c in "six".chars() {
+            app.add_char(c);
+        }
+        assert_eq!("six two", app.get_buffer_text());
+
+        app.enter_normal_mode();
+        assert_eq!(AppMode::Normal, app.mode());
+    }
+
+    #[test]
+    fn vi_delete_lines() {
+        let mut app = modal_app_with_contents("a\nb\nc\nd");
+        type_vi_keys(&mut app, "j2dd");
+        assert_eq!("a\nd", app.get_buffer_text());
+        assert_eq!(1, app.buffer().current_line());
+
+        type_vi_keys(&mut app, "dgg");
+        assert_eq!("", app.get_buffer_text());
+    }
+
+    #[test]
+    fn vi_delete_to_last_line() {
+        let mut app = modal_app_with_contents("a\nb\nc");
+        type_vi_keys(&mut app, "jdG");
+        assert_eq!("a", app.get_buffer_text());
+    }
+
+    #[test]
+    fn vi_yank_and_line_motions() {
+        let mut app = modal_app_with_contents("abc def\nghi");
+        type_vi_keys(&mut app, "$y0");
+        assert_eq!("abc def", app.registers.get(None).unwrap().text);
+        assert_eq!(0, app.buffer().cursor_position());
+        assert_eq!("abc def\nghi", app.get_buffer_text());
+
+        type_vi_keys(&mut app, "Gx");
+        assert_eq!("abc def\nhi", app.get_buffer_text());
+        type_vi_keys(&mut app, "ggA");
+        assert_eq!(AppMode::Edit, app.mode());
+        assert_eq!(7, app.buffer().cursor_position());
+    }
+
+    #[test]
+    fn vi_open_lines() {
+        let mut app = modal_app_with_contents("a\nb");
+        type_vi_keys(&mut app, "o");
+        app.add_char('x');
+        app.enter_normal_mode();
+        type_vi_keys(&mut app, "GO");
+        app.add_char('y');
+        assert_eq!("a\nx\ny\nb", app.get_buffer_text());
+    }
+
+    #[test]
+    fn vi_visual_mode_delete() {
+        let mut app = modal_app_with_contents("hello world");
+        type_vi_keys(&mut app, "lve");
+        assert_eq!(AppMode::Visual, app.mode());
+        assert_eq!(Some((1, 5)), app.visual_range());
+
+        type_vi_keys(&mut app, "d");
+        assert_eq!(AppMode::Normal, app.mode());
+        assert_eq!("h world", app.get_buffer_text());
+        assert_eq!("ello", app.registers.get(None).unwrap().text);
+        assert_eq!(None, app.visual_range());
+    }
+
+    #[test]
+    fn vi_visual_mode_escape() {
+        let mut app = modal_app_with_contents("hello");
+        type_vi_keys(&mut app, "vl");
+        app.handle_vi_key(Key::Esc);
+        assert_eq!(AppMode::Normal, app.mode());
+        assert_eq!("hello", app.get_buffer_text());
+    }
+
+    #[test]
+    fn typing_replaces_selection() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("hello world"));
+
+        app.select_right();
+        app.select_right();
+        app.select_right();
+        app.select_right();
+        app.select_right();
+        app.add_char('H');
+        assert_eq!("H world", app.get_buffer_text());
+
+        app.select_right();
+        app.select_right();
+        app.remove_char();
+        assert_eq!("Horld", app.get_buffer_text());
+
+        // Without shift the selection is dropped
+        app.select_right();
+        app.move_cursor_right();
+        assert_eq!(None, app.buffer().selection());
+        app.remove_char();
+        assert_eq!("Hold", app.get_buffer_text());
+    }
+
+    #[test]
+    fn leaving_visual_mode_drops_selection() {
+        let mut app = modal_app_with_contents("hello");
+        type_vi_keys(&mut app, "vl");
+        assert_eq!(Some((0, 2)), app.buffer().selection());
+
+        type_vi_keys(&mut app, ":");
+        assert_eq!(None, app.buffer().selection());
+        app.cancel_command();
+        assert_eq!(AppMode::Normal, app.mode());
+    }
+
+    #[test]
+    fn vi_paste_characters() {
+        let mut app = modal_app_with_contents("abc");
+        type_vi_keys(&mut app, "xp");
+        assert_eq!("bac", app.get_buffer_text());
+        assert_eq!(1, app.buffer().cursor_position());
+
+        type_vi_keys(&mut app, "0y$$2P");
+        assert_eq!("bacbacbac", app.get_buffer_text());
+        assert_eq!(8, app.buffer().cursor_position());
+    }
+
+    #[test]
+    fn vi_paste_lines() {
+        let mut app = modal_app_with_contents("a\nb\nc");
+        type_vi_keys(&mut app, "yyjp");
+        assert_eq!("a\nb\na\nc", app.get_buffer_text());
+        assert_eq!(2, app.buffer().current_line());
+
+        type_vi_keys(&mut app, "GP");
+        assert_eq!("a\nb\na\na\nc", app.get_buffer_text());
+        assert_eq!(3, app.buffer().current_line());
+
+        // Below the last line, which has no line break of its own
+        type_vi_keys(&mut app, "Gp");
+        assert_eq!("a\nb\na\na\nc\na", app.get_buffer_text());
+        assert_eq!(5, app.buffer().current_line());
+        assert_eq!(0, app.buffer().cursor().line_offset);
+    }
+
+    #[test]
+    fn vi_paste_deleted_last_line() {
+        let mut app = modal_app_with_contents("a\nb");
+        type_vi_keys(&mut app, "jddP");
+        assert_eq!("b\na", app.get_buffer_text());
+        assert_eq!(0, app.buffer().current_line());
+    }
+
+    #[test]
+    fn vi_paste_is_one_undo_step() {
+        let mut app = modal_app_with_contents("one\ntwo\n");
+        type_vi_keys(&mut app, "2yyG3p");
+        assert_eq!(
+            "one\ntwo\none\ntwo\none\ntwo\none\ntwo\n",
+            app.get_buffer_text()
+        );
+        type_vi_keys(&mut app, "u");
+        assert_eq!("one\ntwo\n", app.get_buffer_text());
+    }
+
+    #[test]
+    fn vi_named_registers_and_ring() {
+        let mut app = modal_app_with_contents("one two three");
+        type_vi_keys(&mut app, "\"ayw");
+        type_vi_keys(&mut app, "wdw");
+        type_vi_keys(&mut app, "dw");
+        assert_eq!("one ", app.get_buffer_text());
+
+        type_vi_keys(&mut app, "\"aP");
+        assert_eq!("one one ", app.get_buffer_text());
+        assert_eq!("three", app.registers.get(None).unwrap().text);
+
+        type_vi_keys(&mut app, "$\"1p");
+        assert_eq!("one one two ", app.get_buffer_text());
+
+        type_vi_keys(&mut app, "\"zp");
+        assert_eq!(Some(&String::from("Nothing in register")), app.message());
+    }
+
+    #[test]
+    fn vi_visual_paste_replaces_selection() {
+        let mut app = modal_app_with_contents("abc def");
+        type_vi_keys(&mut app, "yewvep");
+        assert_eq!("abc abc", app.get_buffer_text());
+    }
+
+    #[test]
+    fn cut_copy_and_paste() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("ab\ncd"));
+
+        app.cut();
+        assert_eq!(Some(&String::from("Nothing selected")), app.message());
+
+        app.select_down();
+        app.copy();
+        assert_eq!(None, app.buffer().selection());
+        assert_eq!("ab\n", app.registers.get(None).unwrap().text);
+
+        app.select_right();
+        app.cut();
+        assert_eq!("ab\nd", app.get_buffer_text());
+
+        app.paste();
+        app.paste();
+        assert_eq!("ab\nccd", app.get_buffer_text());
+
+        // Pasting over a selection replaces it
+        app.select_right();
+        app.paste();
+        assert_eq!("ab\nccc", app.get_buffer_text());
+    }
+
+    #[test]
+    fn paste_previous_goes_back_through_the_ring() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("one two "));
+
+        app.select_right();
+        app.select_right();
+        app.select_right();
+        app.copy();
+        app.move_cursor_right();
+        app.select_right();
+        app.select_right();
+        app.select_right();
+        app.copy();
+        app.buffer.move_cursor_to(8);
+
+        // Nothing pasted yet, so this pastes the latest
+        app.paste_previous();
+        assert_eq!("one two two", app.get_buffer_text());
+        app.paste_previous();
+        assert_eq!("one two one", app.get_buffer_text());
+        // Round to the latest again
+        app.paste_previous();
+        assert_eq!("one two two", app.get_buffer_text());
+
+        // Moving away starts a fresh paste
+        app.move_cursor_left();
+        app.paste_previous();
+        assert_eq!("one two twtwoo", app.get_buffer_text());
+    }
+
+    type Shared<T> = Rc<RefCell<T>>;
+
+    // Records what was copied and pastes whatever it is told to
+    struct FakeClipboard {
+        copied: Shared<Vec<String>>,
+        contents: Shared<Option<String>>,
+    }
+
+    impl Clipboard for FakeClipboard {
+        fn copy(&mut self, text: &str) -> io::Result<()> {
+            self.copied.borrow_mut().push(String::from(text));
+            Ok(())
+        }
+
+        fn paste(&mut self) -> io::Result<Option<String>> {
+            Ok(self.contents.borrow().clone())
+        }
+    }
+
+    fn app_with_fake_clipboard(
+        contents: &str,
+    ) -> (App, Shared<Vec<String>>, Shared<Option<String>>) {
+        let mut app = modal_app_with_contents(contents);
+        let copied = Rc::new(RefCell::new(Vec::new()));
+        let clipboard_contents = Rc::new(RefCell::new(None));
+        app.clipboard = Some(Box::new(FakeClipboard {
+            copied: copied.clone(),
+            contents: clipboard_contents.clone(),
+        }));
+        (app, copied, clipboard_contents)
+    }
+
+    #[test]
+    fn osc52_copies_wait_for_the_view() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("abc"));
+        assert_eq!(app.take_terminal_output(), "");
+
+        app.set_clipboard_option("osc52");
+        app.select_right();
+        app.copy();
+        assert_eq!(app.take_terminal_output(), "\x1b]52;c;YQ==\x07");
+        assert_eq!(app.take_terminal_output(), "");
+    }
+
+    #[test]
+    fn yanks_and_cuts_go_to_clipboard() {
+        let (mut app, copied, _) = app_with_fake_clipboard("one two\nthree");
+        type_vi_keys(&mut app, "yw\"add");
+        assert_eq!(
+            *copied.borrow(),
+            vec![String::from("one "), String::from("one two\n")]
+        );
+
+        app.set_modal(false);
+        app.select_right();
+        app.cut();
+        assert_eq!(copied.borrow().last(), Some(&String::from("t")));
+    }
+
+    #[test]
+    fn paste_from_clipboard() {
+        let (mut app, _, contents) = app_with_fake_clipboard("a\nb");
+        type_vi_keys(&mut app, "yy");
+
+        // What was yanked comes back as whole lines
+        *contents.borrow_mut() = Some(String::from("a\n"));
+        type_vi_keys(&mut app, "p");
+        assert_eq!("a\na\nb", app.get_buffer_text());
+
+        // Something copied outside the editor takes over
+        *contents.borrow_mut() = Some(String::from("xyz"));
+        type_vi_keys(&mut app, "P");
+        assert_eq!("a\nxyza\nb", app.get_buffer_text());
+
+        // Named registers ignore the clipboard
+        type_vi_keys(&mut app, "\"0p");
+        assert_eq!("a\nxyza\na\nb", app.get_buffer_text());
+    }
+
+    #[test]
+    fn set_clipboard_option() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("abc"));
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("clipboard");
+        let command = format!("set clipboard=tee {0},cat {0}", path.display());
+        app.handle_command_line();
+        for c in command.chars() {
+            app.add_char(c);
+        }
+        app.submit_command();
+
+        app.select_right();
+        app.copy();
+        assert_eq!("a", fs::read_to_string(&path).unwrap());
+
+        fs::write(&path, "xy").unwrap();
+        app.paste();
+        assert_eq!("axybc", app.get_buffer_text());
+    }
+
+    #[test]
+    fn type_at_every_match() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("let a = a + a;"));
+        app.add_cursor_at_next_match();
+        assert_eq!(app.message(), Some(&String::from("Nothing selected")));
+
+        app.move_cursor_right();
+        app.move_cursor_right();
+        app.move_cursor_right();
+        app.move_cursor_right();
+        app.select_right();
+        app.add_cursor_at_next_match();
+        app.add_cursor_at_next_match();
+        for c in "bc".chars() {
+            app.add_char(c);
+        }
+        assert_eq!(app.get_buffer_text(), "let bc = bc + bc;");
+
+        app.copy();
+        app.undo();
+        app.undo();
+        assert_eq!(app.get_buffer_text(), "let a = a + a;");
+    }
+
+    #[test]
+    fn escape_drops_extra_cursors_first() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("a\nb"));
+        app.add_cursor_below();
+        app.add_cursor_below();
+        assert_eq!(
+            app.message(),
+            Some(&String::from("No line below the last cursor"))
+        );
+
+        app.handle_command_line();
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.buffer().cursor_positions(), vec![0]);
+        app.handle_command_line();
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Execute));
+    }
+
+    #[test]
+    fn vi_modes_use_a_single_cursor() {
+        let mut app = modal_app_with_contents("ab\ncd");
+        type_vi_keys(&mut app, "i");
+        app.add_cursor_below();
+        app.add_char('x');
+        assert_eq!(app.get_buffer_text(), "xab\nxcd");
+
+        app.enter_normal_mode();
+        assert!(app.buffer().extra_cursors().is_empty());
+    }
+
+    fn type_search(app: &mut App, direction: SearchDirection, pattern: &str) {
+        app.start_search(direction);
+        for c in pattern.chars() {
+            app.add_char(c);
+        }
+    }
+
+    #[test]
+    fn search_moves_cursor_as_pattern_is_typed() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("one two\nthree two"));
+
+        type_search(&mut app, SearchDirection::Forward, "t");
+        assert_eq!(app.buffer().cursor_position(), 4);
+        assert_eq!(app.search_highlight(), Some(String::from("t")));
+        app.add_char('h');
+        assert_eq!(app.buffer().cursor_position(), 8);
+        app.add_char('x');
+        assert_eq!(app.buffer().cursor_position(), 0);
+        app.remove_char();
+        assert_eq!(app.buffer().cursor_position(), 8);
+
+        app.submit_command();
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.buffer().cursor_position(), 8);
+        assert_eq!(app.message(), None);
+        assert_eq!(app.search_highlight(), Some(String::from("th")));
+    }
+
+    #[test]
+    fn cancelled_search_goes_back() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("abc abc"));
+        app.move_cursor_right();
+
+        type_search(&mut app, SearchDirection::Forward, "abc");
+        assert_eq!(app.buffer().cursor_position(), 4);
+        app.cancel_command();
+        assert_eq!(app.buffer().cursor_position(), 1);
+        assert_eq!(app.search_highlight(), None);
+    }
+
+    #[test]
+    fn search_wraps_around() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("ab\nab\nab"));
+
+        type_search(&mut app, SearchDirection::Backward, "ab");
+        app.submit_command();
+        assert_eq!(app.buffer().cursor_position(), 6);
+        assert_eq!(
+            app.message(),
+            Some(&String::from("Search hit TOP, continuing at BOTTOM"))
+        );
+
+        app.search_next(true, 1);
+        assert_eq!(app.buffer().cursor_position(), 0);
+        assert_eq!(
+            app.message(),
+            Some(&String::from("Search hit BOTTOM, continuing at TOP"))
+        );
+        app.search_next(false, 2);
+        assert_eq!(app.buffer().cursor_position(), 3);
+        assert_eq!(
+            app.message(),
+            Some(&String::from("Search hit TOP, continuing at BOTTOM"))
+        );
+        app.search_next(false, 1);
+        assert_eq!(app.buffer().cursor_position(), 0);
+        assert_eq!(app.message(), None);
+    }
+
+    #[test]
+    fn search_not_found() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("abc"));
+        app.search_next(false, 1);
+        assert_eq!(
+            app.message(),
+            Some(&String::from("No previous search pattern"))
+        );
+
+        app.move_cursor_right();
+        type_search(&mut app, SearchDirection::Forward, "x");
+        app.submit_command();
+        assert_eq!(app.buffer().cursor_position(), 1);
+        assert_eq!(app.message(), Some(&String::from("Pattern not found: x")));
+    }
+
+    #[test]
+    fn search_in_normal_mode() {
+        let mut app = modal_app_with_contents("a b a b a");
+        type_vi_keys(&mut app, "/");
+        app.add_char('b');
+        assert_eq!(
+            app.mode(),
+            AppMode::Command(CommandMode::Search(SearchDirection::Forward))
+        );
+        app.submit_command();
+        assert_eq!(app.mode(), AppMode::Normal);
+        assert_eq!(app.buffer().cursor_position(), 2);
+
+        type_vi_keys(&mut app, "nN");
+        assert_eq!(app.buffer().cursor_position(), 2);
+
+        // An empty pattern searches for the last one again
+        type_vi_keys(&mut app, "?");
+        app.submit_command();
+        assert_eq!(app.buffer().cursor_position(), 6);
+        assert_eq!(app.search_highlight(), Some(String::from("b")));
+
+        type_vi_keys(&mut app, ":");
+        "noh".chars().for_each(|c| app.add_char(c));
+        app.submit_command();
+        assert_eq!(app.search_highlight(), None);
+        // `?` made the search go backward
+        type_vi_keys(&mut app, "n");
+        assert_eq!(app.buffer().cursor_position(), 2);
+        assert_eq!(app.search_highlight(), Some(String::from("b")));
+    }
+
+    fn run_command(app: &mut App, command: &str) {
+        app.handle_command_line();
+        for c in command.chars() {
+            app.add_char(c);
+        }
+        app.submit_command();
+    }
+
+    #[test]
+    fn substitute_on_current_line() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("a a\na a"));
+
+        run_command(&mut app, "s/a/b/");
+        assert_eq!(app.get_buffer_text(), "b a\na a");
+        assert_eq!(
+            app.message(),
+            Some(&String::from("1 substitution on 1 line"))
+        );
+
+        run_command(&mut app, "s/x/y/");
+        assert_eq!(app.message(), Some(&String::from("Pattern not found: x")));
+        run_command(&mut app, "s/(/y/");
+        assert_eq!(app.message(), Some(&String::from("Invalid pattern: (")));
+        run_command(&mut app, "5s/a/y/");
+        assert_eq!(app.message(), Some(&String::from("Invalid range")));
+    }
+
+    #[test]
+    fn substitute_whole_file_is_one_undo_step() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("x=1, y=2\nz\nw=3"));
+
+        run_command(&mut app, r"%s/(\w)=(\d)/\2:\1/g");
+        assert_eq!(app.get_buffer_text(), "1:x, 2:y\nz\n3:w");
+        assert_eq!(
+            app.message(),
+            Some(&String::from("3 substitutions on 2 lines"))
+        );
+        // The cursor ends up at the start of the last line changed
+        assert_eq!(app.buffer().current_line(), 2);
+        assert_eq!(app.buffer().cursor().line_offset, 0);
+
+        app.undo();
+        assert_eq!(app.get_buffer_text(), "x=1, y=2\nz\nw=3");
+        assert!(!app.buffer.undo());
+    }
+
+    #[test]
+    fn substitute_uses_and_sets_last_search() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("Ab ab\nAB"));
+
+        run_command(&mut app, "%s//x/");
+        assert_eq!(
+            app.message(),
+            Some(&String::from("No previous search pattern"))
+        );
+
+        run_command(&mut app, "1s/ab/[&]/gi");
+        assert_eq!(app.get_buffer_text(), "[Ab] [ab]\nAB");
+        assert_eq!(app.search_highlight(), Some(String::from("(?i)ab")));
+
+        run_command(&mut app, "2s///");
+        assert_eq!(app.get_buffer_text(), "[Ab] [ab]\n");
+    }
+
+    #[test]
+    fn substitute_with_confirm() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.buffer = Buffer::with_contents(String::from("a b a b a"));
+
+        run_command(&mut app, "s/a/x/gc");
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Confirm));
+        assert_eq!(app.buffer().selection(), Some((0, 1)));
+        assert_eq!(
+            app.message(),
+            Some(&String::from("Replace with x (y/n/a/q/l)?"))
+        );
+
+        app.confirm_replace(Key::Char('y'));
+        app.confirm_replace(Key::Char('?'));
+        assert_eq!(app.buffer().selection(), Some((4, 5)));
+        // Nothing changes until every match has been answered for
+        assert_eq!(app.get_buffer_text(), "a b a b a");
+        app.confirm_replace(Key::Char('n'));
+        app.confirm_replace(Key::Char('y'));
+
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.get_buffer_text(), "x b a b x");
+        assert_eq!(app.buffer().selection(), None);
+        assert_eq!(
+            app.message(),
+            Some(&String::from("2 substitutions on 1 line"))
+        );
+        app.undo();
+        assert_eq!(app.get_buffer_text(), "a b a b a");
+    }
+
+    #[test]
+    fn substitute_with_confirm_all_or_quit() {
+        let mut app = modal_app_with_contents("a\na\na\na");
+        type_vi_keys(&mut app, "j");
+
+        run_command(&mut app, "%s/a/b/c");
+        app.confirm_replace(Key::Char('n'));
+        app.confirm_replace(Key::Char('a'));
+        assert_eq!(app.mode(), AppMode::Normal);
+        assert_eq!(app.get_buffer_text(), "a\nb\nb\nb");
+
+        run_command(&mut app, "%s/b/c/c");
+        app.confirm_replace(Key::Char('l'));
+        assert_eq!(app.get_buffer_text(), "a\nc\nb\nb");
+
+        run_command(&mut app, "%s/b/c/c");
+        app.confirm_replace(Key::Char('n'));
+        app.confirm_replace(Key::Esc);
+        assert_eq!(app.get_buffer_text(), "a\nc\nb\nb");
+        assert_eq!(app.buffer().current_line(), 1);
+        assert_eq!(app.message(), None);
+    }
+
+    #[test]
+    fn quit_without_changes() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        assert_eq!(app.quit(false), QuitOption::Quitting);
+
+        app.add_char('a');
+        app.undo();
+        assert_eq!(app.quit(false), QuitOption::Quitting);
+    }
+
+    #[test]
+    fn quit_with_changes_asks_first() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.add_char('a');
+
+        assert_eq!(app.quit(false), QuitOption::NotQuitting);
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::SaveBeforeQuit));
+        assert_eq!(
+            app.message(),
+            Some(&String::from(
+                "Save changes to \"[No Name]\"? (y)es, (n)o, (c)ancel"
+            ))
+        );
+
+        assert_eq!(app.confirm_quit(Key::Char('x')), QuitOption::NotQuitting);
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::SaveBeforeQuit));
+        assert_eq!(app.confirm_quit(Key::Char('c')), QuitOption::NotQuitting);
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.message(), None);
+        assert_eq!(app.get_buffer_text(), "a");
+
+        app.quit(false);
+        assert_eq!(app.confirm_quit(Key::Char('n')), QuitOption::Quitting);
+        assert_eq!(app.quit(true), QuitOption::Quitting);
+    }
+
+    #[test]
+    fn quit_commands() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.add_char('a');
+
+        app.handle_command_line();
+        for c in "q".chars() {
+            app.add_char(c);
+        }
+        assert_eq!(app.submit_command(), QuitOption::NotQuitting);
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::SaveBeforeQuit));
+        app.confirm_quit(Key::Esc);
+
+        app.handle_command_line();
+        for c in "q!".chars() {
+            app.add_char(c);
+        }
+        assert_eq!(app.submit_command(), QuitOption::Quitting);
+    }
+
+    #[test]
+    fn quit_saving_changes() -> io::Result<()> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("temp.txt");
+        let file_path_string = file_path.to_string_lossy().into_owned();
+        fs::write(&file_path, "b")?;
+
+        let args = vec![String::from("raccoon"), file_path_string];
+        let mut app = App::new(&args);
+        app.add_char('a');
+        assert!(app.buffer().is_modified());
+
+        app.quit(false);
+        assert_eq!(app.confirm_quit(Key::Char('y')), QuitOption::Quitting);
+        assert_eq!(fs::read_to_string(&file_path)?, "ab");
+        assert!(!app.buffer().is_modified());
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn quit_saving_changes_without_a_name() -> io::Result<()> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("temp.txt");
+        let file_path_string = file_path.to_string_lossy().into_owned();
+
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.add_char('a');
+        app.quit(false);
+        assert_eq!(app.confirm_quit(Key::Char('y')), QuitOption::NotQuitting);
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Write));
+
+        // Cancelling the file name prompt doesn't quit after a later save
+        app.cancel_command();
+        app.handle_save_as_new_file();
+        for c in file_path_string.chars() {
+            app.add_char(c);
+        }
+        assert_eq!(app.submit_command(), QuitOption::NotQuitting);
+
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(fs::read_to_string(&file_path)?, "a");
+
+        app.file_path = None;
+        app.add_char('b');
+        app.quit(false);
+        app.confirm_quit(Key::Char('y'));
+        for c in file_path_string.chars() {
+            app.add_char(c);
+        }
+        assert_eq!(app.submit_command(), QuitOption::Quitting);
+        assert_eq!(fs::read_to_string(&file_path)?, "ab");
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn write_keeps_backup_when_set() -> io::Result<()> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("temp.txt");
+        let backup_path = dir.path().join("temp.txt~");
+        fs::write(&file_path, "old")?;
+
+        let args = vec![
+            String::from("raccoon"),
+            file_path.to_string_lossy().into_owned(),
+        ];
+        let mut app = App::new(&args);
+        app.add_char('a');
+        run_command(&mut app, "w");
+        assert_eq!(fs::read_to_string(&file_path)?, "aold");
+        assert!(!backup_path.exists());
+
+        run_command(&mut app, "set bk");
+        app.add_char('b');
+        run_command(&mut app, "w");
+        assert_eq!(fs::read_to_string(&file_path)?, "abold");
+        assert_eq!(fs::read_to_string(&backup_path)?, "aold");
+
+        run_command(&mut app, "set nobackup");
+        assert!(!app.backup);
+
+        dir.close()?;
+        Ok(())
+    }
+
+    fn app_with_file(dir: &Path, contents: impl AsRef<[u8]>) -> (App, PathBuf) {
+        let file_path = dir.join("temp.txt");
+        fs::write(&file_path, contents).unwrap();
+        let args = vec![
+            String::from("raccoon"),
+            file_path.to_string_lossy().into_owned(),
+        ];
+        (App::new(&args), file_path)
+    }
+
+    #[test]
+    fn tick_writes_swap_file() -> io::Result<()> {
+        let dir = tempdir()?;
+        let (mut app, _) = app_with_file(dir.path(), "old");
+        let swap_path = dir.path().join(".temp.txt.swp");
+        let start = SystemTime::now();
+
+        app.tick(start);
+        assert!(!swap_path.exists());
+
+        app.add_char('a');
+        app.tick(start + Duration::from_secs(1));
+        assert!(!swap_path.exists());
+        app.tick(start + SWAP_INTERVAL);
+        let swap = Swap::read(&swap_path)?;
+        assert_eq!(swap.added, "a");
+
+        // Nothing is written until the interval is up again
+        app.add_char('b');
+        app.tick(start + SWAP_INTERVAL + Duration::from_secs(1));
+        assert_eq!(Swap::read(&swap_path)?.added, "a");
+        app.tick(start + SWAP_INTERVAL * 2);
+        assert_eq!(Swap::read(&swap_path)?.added, "ab");
+
+        // Saving leaves nothing to recover
+        app.save_file();
+        app.tick(start + SWAP_INTERVAL * 3);
+        assert!(!swap_path.exists());
+
+        app.add_char('c');
+        app.update_swap();
+        assert!(swap_path.exists());
+        app.remove_swap();
+        assert!(!swap_path.exists());
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn no_swap_file_without_a_name() {
+        let args: Vec<String> = Vec::new();
+        let mut app = App::new(&args);
+        app.add_char('a');
+        app.update_swap();
+        assert_eq!(app.swap_file, None);
+    }
+
+    #[test]
+    fn recover_from_swap_file() -> io::Result<()> {
+        let dir = tempdir()?;
+        let (mut app, file_path) = app_with_file(dir.path(), "old\n");
+        for c in "new ".chars() {
+            app.add_char(c);
+        }
+        app.update_swap();
+        // As if the editor had crashed
+        app.swap_file = None;
+
+        let (mut app, _) = app_with_file(dir.path(), "old\n");
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Recover));
+        assert!(app.message().unwrap().starts_with("Found swap file"));
+        // The swap file is left alone while the question is open
+        app.update_swap();
+        assert!(dir.path().join(".temp.txt.swp").exists());
+
+        assert_eq!(app.confirm_recover(Key::Char('x')), QuitOption::NotQuitting);
+        assert_eq!(app.confirm_recover(Key::Char('r')), QuitOption::NotQuitting);
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.get_buffer_text(), "new old\n");
+        assert!(app.buffer().is_modified());
+        assert_eq!(fs::read_to_string(&file_path)?, "old\n");
+
+        app.save_file();
+        app.update_swap();
+        assert!(!dir.path().join(".temp.txt.swp").exists());
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn delete_or_quit_on_swap_file() -> io::Result<()> {
+        let dir = tempdir()?;
+        let swap_path = dir.path().join(".temp.txt.swp");
+        let (mut app, _) = app_with_file(dir.path(), "old");
+        app.add_char('a');
+        app.update_swap();
+        app.swap_file = None;
+
+        let (mut app, _) = app_with_file(dir.path(), "old");
+        assert_eq!(app.confirm_recover(Key::Char('q')), QuitOption::Quitting);
+        app.remove_swap();
+        assert!(swap_path.exists());
+
+        // A file changed since can't be recovered, only deleted
+        let (mut app, _) = app_with_file(dir.path(), "new");
+        app.confirm_recover(Key::Char('r'));
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Recover));
+        assert_eq!(
+            app.message(),
+            Some(&String::from(
+                "File changed since the swap file was written: (d)elete it, (q)uit"
+            ))
+        );
+        app.confirm_recover(Key::Char('d'));
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.get_buffer_text(), "new");
+        assert!(!swap_path.exists());
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn swap_file_in_use_is_left_alone() -> io::Result<()> {
+        let dir = tempdir()?;
+        let swap_path = dir.path().join(".temp.txt.swp");
+        let (mut app, _) = app_with_file(dir.path(), "old");
+        app.add_char('a');
+        app.update_swap();
+        app.swap_file = None;
+        // As if written by an editor that is still running
+        let mut swap = Swap::read(&swap_path)?;
+        swap.pid = std::os::unix::process::parent_id();
+        swap.write(&swap_path)?;
+
+        let (mut app, _) = app_with_file(dir.path(), "old");
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Recover));
+        let message = app.message().unwrap();
+        assert!(message.contains("in use by process"));
+        assert!(message.ends_with("(r)ecover, (o)pen anyway, (q)uit"));
+
+        // Not offered, so not taken
+        app.confirm_recover(Key::Char('d'));
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Recover));
+        assert!(swap_path.exists());
+
+        app.confirm_recover(Key::Char('o'));
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.get_buffer_text(), "old");
+        app.add_char('b');
+        app.update_swap();
+        app.remove_swap();
+        assert_eq!(Swap::read(&swap_path)?, swap);
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn unreadable_swap_file_is_left_alone() -> io::Result<()> {
+        let dir = tempdir()?;
+        let swap_path = dir.path().join(".temp.txt.swp");
+        fs::write(&swap_path, "{")?;
+
+        let (mut app, _) = app_with_file(dir.path(), "old");
+        assert_eq!(app.mode(), AppMode::Edit);
+        let message = app.message().unwrap();
+        assert!(message.starts_with("Unable to read swap file"));
+        assert!(message.ends_with("editing without one"));
+
+        app.add_char('a');
+        app.update_swap();
+        app.remove_swap();
+        assert_eq!(fs::read_to_string(&swap_path)?, "{");
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn planted_swap_symlink_is_not_followed() -> io::Result<()> {
+        let dir = tempdir()?;
+        let swap_path = dir.path().join(".temp.txt.swp");
+        let victim = dir.path().join("victim.txt");
+        fs::write(&victim, "victim")?;
+
+        // Planted once the file is open
+        let (mut app, _) = app_with_file(dir.path(), "old");
+        std::os::unix::fs::symlink(&victim, &swap_path)?;
+        app.add_char('a');
+        app.update_swap();
+        assert!(app
+            .message()
+            .unwrap()
+            .starts_with("Unable to write swap file"));
+        assert_eq!(fs::read_to_string(&victim)?, "victim");
+
+        // Or before, dangling or not
+        for target in [victim.clone(), dir.path().join("missing.txt")] {
+            fs::remove_file(&swap_path)?;
+            std::os::unix::fs::symlink(&target, &swap_path)?;
+            let (mut app, _) = app_with_file(dir.path(), "old");
+            assert!(app
+                .message()
+                .unwrap()
+                .starts_with("Unable to read swap file"));
+            app.add_char('a');
+            app.update_swap();
+            assert_eq!(fs::read_to_string(&victim)?, "victim");
+        }
+        assert!(!dir.path().join("missing.txt").exists());
+
+        dir.close()?;
+        Ok(())
+    }
+
+    // Rewrites the file as another program would, a while later
+    fn change_on_disk(file_path: &Path, contents: &str) {
+        let modified = fs::metadata(file_path).unwrap().modified().unwrap();
+        fs::write(file_path, contents).unwrap();
+        File::options()
+            .write(true)
+            .open(file_path)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(5))
+            .unwrap();
+    }
+
+    #[test]
+    fn tick_notices_change_on_disk() -> io::Result<()> {
+        let dir = tempdir()?;
+        let (mut app, file_path) = app_with_file(dir.path(), "old\n");
+        app.tick(SystemTime::now());
+        assert_eq!(app.mode(), AppMode::Edit);
+
+        // Touching the file changes nothing worth asking about
+        change_on_disk(&file_path, "old\n");
+        app.tick(SystemTime::now());
+        assert_eq!(app.mode(), AppMode::Edit);
+
+        change_on_disk(&file_path, "new\n");
+        app.tick(SystemTime::now());
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Reload));
+        assert!(app.changed_on_disk());
+        assert_eq!(
+            app.message(),
+            Some(&format!(
+                "\"{}\" changed on disk: (r)eload, (k)eep ours, (d)iff",
+                file_path.to_string_lossy()
+            ))
+        );
+
+        app.confirm_reload(Key::Char('r'));
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert!(!app.changed_on_disk());
+        assert_eq!(app.get_buffer_text(), "new\n");
+        assert!(app.message().unwrap().ends_with("reloaded"));
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn change_on_disk_waits_for_command_line() -> io::Result<()> {
+        let dir = tempdir()?;
+        let (mut app, file_path) = app_with_file(dir.path(), "old");
+        app.handle_command_line();
+        change_on_disk(&file_path, "new");
+        app.tick(SystemTime::now());
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Execute));
+
+        app.cancel_command();
+        app.tick(SystemTime::now());
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Reload));
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn keep_ours_after_change_on_disk() -> io::Result<()> {
+        let dir = tempdir()?;
+        let (mut app, file_path) = app_with_file(dir.path(), "old\n");
+        app.add_char('a');
+        change_on_disk(&file_path, "new\n");
+
+        // Saving asks first rather than writing over the change
+        app.save_file();
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Reload));
+        assert_eq!(fs::read_to_string(&file_path)?, "new\n");
+
+        app.confirm_reload(Key::Char('d'));
+        assert_eq!(
+            app.shown_buffer().as_str(),
+            format!(
+                "--- buffer\n+++ {} (on disk)\n@@ -1 +1 @@\n-aold\n+new\n",
+                file_path.to_string_lossy()
+            )
+        );
+        app.confirm_reload(Key::Char('d'));
+        assert_eq!(app.shown_buffer().as_str(), "aold\n");
+        app.confirm_reload(Key::Char('d'));
+
+        app.confirm_reload(Key::Char('k'));
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.shown_buffer().as_str(), "aold\n");
+        app.tick(SystemTime::now());
+        assert_eq!(app.mode(), AppMode::Edit);
+
+        app.save_file();
+        assert_eq!(fs::read_to_string(&file_path)?, "aold\n");
+        app.tick(SystemTime::now());
+        assert_eq!(app.mode(), AppMode::Edit);
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn keep_ours_after_change_in_length() -> io::Result<()> {
+        let dir = tempdir()?;
+        let (mut app, file_path) = app_with_file(dir.path(), "old\n");
+        change_on_disk(&file_path, "newer\n");
+        app.tick(SystemTime::now());
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Reload));
+
+        // Kept without reading the file, and not asked about again
+        app.confirm_reload(Key::Char('k'));
+        app.tick(SystemTime::now());
+        assert_eq!(app.mode(), AppMode::Edit);
+        assert_eq!(app.shown_buffer().as_str(), "old\n");
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn save_keeps_line_endings() -> io::Result<()> {
+        let dir = tempdir()?;
+        let (mut app, file_path) = app_with_file(dir.path(), "one\r\ntwo\r\n");
+        assert_eq!(app.get_buffer_text(), "one\ntwo\n");
+        app.move_cursor_down();
+        app.add_char('2');
+        run_command(&mut app, "w");
+        assert_eq!(fs::read_to_string(&file_path)?, "one\r\n2two\r\n");
+
+        run_command(&mut app, "set ff");
+        assert_eq!(app.message(), Some(&String::from("fileformat=dos")));
+        run_command(&mut app, "set ff=unix");
+        assert!(app.buffer().is_modified());
+        run_command(&mut app, "w");
+        assert_eq!(fs::read_to_string(&file_path)?, "one\n2two\n");
+
+        run_command(&mut app, "set fileformat=mac");
+        run_command(&mut app, "w");
+        assert_eq!(fs::read_to_string(&file_path)?, "one\r2two\r");
+
+        run_command(&mut app, "set ff=windows");
+        assert_eq!(
+            app.message(),
+            Some(&String::from("Invalid argument: ff=windows"))
+        );
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn diff_with_disk_ignores_line_endings() -> io::Result<()> {
+        let dir = tempdir()?;
+        let (mut app, file_path) = app_with_file(dir.path(), "a\r\nb\r\n");
+        change_on_disk(&file_path, "a\r\nc\r\n");
+        app.tick(SystemTime::now());
+        app.confirm_reload(Key::Char('d'));
+        assert!(app
+            .shown_buffer()
+            .as_str()
+            .ends_with("@@ -1,2 +1,2 @@\n a\n-b\n+c\n"));
+
+        app.confirm_reload(Key::Char('r'));
+        assert_eq!(app.get_buffer_text(), "a\nc\n");
+        assert_eq!(app.buffer().line_ending(), LineEnding::CrLf);
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn save_keeps_encoding() -> io::Result<()> {
+        let dir = tempdir()?;
+        let (mut app, file_path) = app_with_file(dir.path(), b"caf\xE9\n");
+        assert_eq!(app.get_buffer_text(), "caf\u{e9}\n");
+        assert_eq!(app.buffer().encoding(), Encoding::Latin1);
+        app.add_char('x');
+        run_command(&mut app, "w");
+        assert_eq!(fs::read(&file_path)?, b"xcaf\xE9\n");
+
+        run_command(&mut app, "set fenc");
+        assert_eq!(app.message(), Some(&String::from("fileencoding=latin1")));
+        run_command(&mut app, "set fenc=utf-16le");
+        assert!(app.buffer().is_modified());
+        run_command(&mut app, "w");
+        assert_eq!(fs::read(&file_path)?, b"\xFF\xFEx\0c\0a\0f\0\xE9\0\n\0");
+
+        app.buffer = Buffer::with_file_contents(String::from("\u{20ac}"), Encoding::Latin1);
+        run_command(&mut app, "w");
+        assert_eq!(
+            app.message(),
+            Some(&String::from(
+                "Unable to write file: Can't write '\u{20ac}' as latin1"
+            ))
+        );
+
+        run_command(&mut app, "set fenc=koi8-r");
+        assert_eq!(
+            app.message(),
+            Some(&String::from("Invalid argument: fenc=koi8-r"))
+        );
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_utf8_is_saved_as_it_was() -> io::Result<()> {
+        let dir = tempdir()?;
+        // A replacement character that really is in the file stays one
+        let contents = b"caf\xC3\xA9 \x80\r\n\xEF\xBF\xBD\r\n";
+        let (mut app, file_path) = app_with_file(dir.path(), contents);
+        assert_eq!(app.buffer().encoding(), Encoding::Utf8);
+        assert_eq!(app.get_buffer_text(), "caf\u{e9} \u{fffd}\n\u{fffd}\n");
+        assert_eq!(
+            app.message(),
+            Some(&format!(
+                "\"{}\" isn't all valid utf-8, shown as \u{fffd} where it isn't",
+                file_path.to_string_lossy()
+            ))
+        );
+        app.move_cursor_down();
+        app.add_char('b');
+        run_command(&mut app, "w");
+        assert_eq!(
+            fs::read(&file_path)?,
+            b"caf\xC3\xA9 \x80\r\nb\xEF\xBF\xBD\r\n"
+        );
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn reopen_with_encoding() -> io::Result<()> {
+        let dir = tempdir()?;
+        let (mut app, file_path) = app_with_file(dir.path(), "caf\u{e9}");
+        assert_eq!(app.buffer().encoding(), Encoding::Utf8);
+        run_command(&mut app, "e ++enc=latin1");
+        assert_eq!(app.get_buffer_text(), "caf\u{c3}\u{a9}");
+        assert_eq!(app.buffer().encoding(), Encoding::Latin1);
+        assert!(!app.buffer().is_modified());
+
+        // Reloading after a change on disk keeps the chosen encoding
+        change_on_disk(&file_path, "caf\u{e9}!");
+        app.tick(SystemTime::now());
+        app.confirm_reload(Key::Char('r'));
+        assert_eq!(app.get_buffer_text(), "caf\u{c3}\u{a9}!");
+
+        run_command(&mut app, "e ++enc=koi8-r");
+        assert_eq!(
+            app.message(),
+            Some(&String::from("Invalid encoding: koi8-r"))
+        );
+
+        let mut app = App::new(&Vec::new());
+        run_command(&mut app, "e ++enc=latin1");
+        assert_eq!(app.message(), Some(&String::from("No file name")));
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn large_file_is_mapped_and_saved() -> io::Result<()> {
+        let dir = tempdir()?;
+        let line = "a mapped line\n";
+        let count = original::MAP_MIN_LEN as usize / line.len() + 1;
+        let (mut app, file_path) = app_with_file(dir.path(), line.repeat(count));
+        assert!(app.buffer().is_mapped_from(&fs::metadata(&file_path)?));
+        assert_eq!(app.buffer().num_lines(), count + 1);
+        assert!(!app.buffer().is_modified());
+
+        // Another link to the file makes saving write over it in place
+        fs::hard_link(&file_path, dir.path().join("link.txt"))?;
+        app.add_char('!');
+        run_command(&mut app, "w");
+        assert!(!app.buffer().is_mapped_from(&fs::metadata(&file_path)?));
+        let saved = fs::read(&file_path)?;
+        assert_eq!(saved.len(), line.len() * count + 1);
+        assert!(saved.starts_with(b"!a mapped line\na mapped line\n"));
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn mapped_file_cut_short_while_open() -> io::Result<()> {
+        let dir = tempdir()?;
+        let line = "a mapped line\n";
+        let count = original::MAP_MIN_LEN as usize / line.len() + 1;
+        let (mut app, file_path) = app_with_file(dir.path(), line.repeat(count));
+        assert!(app.buffer().is_mapped_from(&fs::metadata(&file_path)?));
+        // Stamped without going through the file
+        assert_eq!(app.disk_stamp.unwrap().checksum, None);
+
+        // Whatever cuts the file short waits for a tick to read it in
+        let cutting = file_path.clone();
+        let cut = thread::spawn(move || fs::write(cutting, "cut\n"));
+        let start = Instant::now();
+        while app.buffer().is_mapped_from(&fs::metadata(&file_path)?) {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+            app.tick(SystemTime::now());
+        }
+        cut.join().unwrap()?;
+        assert_eq!(app.buffer().len(), line.len() * count);
+        assert_eq!(app.buffer().text_between(0, line.len()), line);
+
+        app.tick(SystemTime::now());
+        assert_eq!(app.mode(), AppMode::Command(CommandMode::Reload));
+        app.confirm_reload(Key::Char('r'));
+        assert_eq!(app.get_buffer_text(), "cut\n");
+
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn small_or_converted_files_are_read() -> io::Result<()> {
+        let dir = tempdir()?;
+        let (app, file_path) = app_with_file(dir.path(), "small\n");
+        assert!(!app.buffer().is_mapped_from(&fs::metadata(&file_path)?));
+        assert_eq!(app.get_buffer_text(), "small\n");
+        dir.close()?;
+        Ok(())
+    }
 }
@@ -670,7 +670,7 @@ mod buffer_tests {
 
     #[test]
     fn as_str_on_empty_buffer() {
-        let mut buffer = Buffer::new();
+        let buffer = Buffer::new();
         let string = buffer.as_str();
         assert_eq!(string, "");
     }
@@ -1982,4 +1982,985 @@ mod buffer_tests {
 
         assert_eq!(buffer.current_line, 0, "buffer.current_line mismatch!");
     }
+
+    #[test]
+    fn insert_newline_then_remove_joins_lines() {
+        let mut buffer = Buffer::with_contents(String::from("abcd"));
+        buffer.current_line = 0;
+        buffer.cursor.node_offset = 2;
+        buffer.cursor.line_offset = 2;
+        buffer.cursor.line_idx = 0;
+        buffer.cursor.original_line_offset = buffer.cursor.line_offset;
+
+        buffer.insert('\n');
+
+        assert_eq!(buffer.as_str(), "ab\ncd");
+        let cursor = buffer.cursor;
+        assert_eq!(cursor.node_offset, 0, "cursor.node_offset mismatch!");
+        assert_eq!(cursor.line_offset, 0, "cursor.line_offset mismatch!");
+        assert_eq!(cursor.line_idx, 0, "cursor.line_idx mismatch!");
+        assert_eq!(buffer.node_list.index(), 2, "node_list index mismatch!");
+        assert_eq!(buffer.current_line, 1, "buffer.current_line mismatch!");
+
+        buffer.remove();
+
+        assert_eq!(buffer.as_str(), "abcd");
+        let cursor = buffer.cursor;
+        assert_eq!(cursor.node_offset, 0, "cursor.node_offset mismatch!");
+        assert_eq!(cursor.line_offset, 2, "cursor.line_offset mismatch!");
+        assert_eq!(cursor.line_idx, 0, "cursor.line_idx mismatch!");
+        assert_eq!(
+            cursor.original_line_offset, cursor.line_offset,
+            "cursor.line_offset mismatch!"
+        );
+        assert_eq!(buffer.node_list.index(), 1, "node_list index mismatch!");
+        assert_eq!(buffer.current_line, 0, "buffer.current_line mismatch!");
+    }
+
+    #[test]
+    fn move_cursor_down_and_up_across_short_line() {
+        let mut buffer = Buffer::with_contents(String::from("abcdef\nab\nabcdef"));
+        buffer.current_line = 0;
+        buffer.cursor.node_offset = 5;
+        buffer.cursor.line_offset = 5;
+        buffer.cursor.line_idx = 0;
+        buffer.cursor.original_line_offset = buffer.cursor.line_offset;
+
+        buffer.move_cursor_down();
+
+        let cursor = buffer.cursor;
+        assert_eq!(cursor.node_offset, 9, "cursor.node_offset mismatch!");
+        assert_eq!(cursor.line_offset, 2, "cursor.line_offset mismatch!");
+        assert_eq!(cursor.line_idx, 1, "cursor.line_idx mismatch!");
+        assert_eq!(
+            cursor.original_line_offset, 5,
+            "cursor.original_line_offset mismatch!"
+        );
+        assert_eq!(buffer.current_line, 1, "buffer.current_line mismatch!");
+
+        buffer.move_cursor_down();
+
+        let cursor = buffer.cursor;
+        assert_eq!(cursor.node_offset, 15, "cursor.node_offset mismatch!");
+        assert_eq!(cursor.line_offset, 5, "cursor.line_offset mismatch!");
+        assert_eq!(cursor.line_idx, 2, "cursor.line_idx mismatch!");
+        assert_eq!(buffer.current_line, 2, "buffer.current_line mismatch!");
+
+        buffer.move_cursor_up();
+        buffer.move_cursor_up();
+
+        let cursor = buffer.cursor;
+        assert_eq!(cursor.node_offset, 5, "cursor.node_offset mismatch!");
+        assert_eq!(cursor.line_offset, 5, "cursor.line_offset mismatch!");
+        assert_eq!(cursor.line_idx, 0, "cursor.line_idx mismatch!");
+        assert_eq!(buffer.current_line, 0, "buffer.current_line mismatch!");
+
+        buffer.move_cursor_up();
+        assert_eq!(buffer.cursor.node_offset, 5, "cursor.node_offset mismatch!");
+        assert_eq!(buffer.current_line, 0, "buffer.current_line mismatch!");
+    }
+
+    #[test]
+    fn undo_on_new_buffer() {
+        let mut buffer = Buffer::new();
+        assert!(!buffer.undo());
+        assert!(!buffer.redo());
+    }
+
+    #[test]
+    fn undo_groups_typed_words() {
+        let mut buffer = Buffer::new();
+        for ch in "ab cd".chars() {
+            buffer.insert(ch);
+        }
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.as_str(), "ab ");
+        assert_eq!(buffer.cursor.line_offset, 3, "cursor.line_offset mismatch!");
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.as_str(), "");
+        assert_eq!(buffer.cursor, Cursor::new());
+        assert!(!buffer.undo());
+
+        assert!(buffer.redo());
+        assert_eq!(buffer.as_str(), "ab ");
+        assert!(buffer.redo());
+        assert_eq!(buffer.as_str(), "ab cd");
+        assert_eq!(buffer.cursor.line_offset, 5, "cursor.line_offset mismatch!");
+        assert!(!buffer.redo());
+    }
+
+    #[test]
+    fn undo_restores_cursor_after_mid_node_insert() {
+        let mut buffer = Buffer::with_contents(String::from("hello\nworld"));
+        buffer.move_cursor_down();
+        buffer.move_cursor_right();
+        buffer.move_cursor_right();
+        let cursor = buffer.cursor;
+
+        buffer.insert('\n');
+        buffer.insert('x');
+        assert_eq!(buffer.as_str(), "hello\nwo\nxrld");
+        assert_eq!(buffer.current_line, 2, "buffer.current_line mismatch!");
+
+        // The newline and the word after it are separate steps
+        assert!(buffer.undo());
+        assert_eq!(buffer.as_str(), "hello\nwo\nrld");
+        assert!(buffer.undo());
+        assert_eq!(buffer.as_str(), "hello\nworld");
+        assert_eq!(buffer.cursor, cursor);
+        assert_eq!(buffer.current_line, 1, "buffer.current_line mismatch!");
+
+        // Editing after an undo should carry on from the restored cursor
+        buffer.insert('!');
+        assert_eq!(buffer.as_str(), "hello\nwo!rld");
+        assert!(!buffer.redo());
+    }
+
+    #[test]
+    fn undo_groups_removes_until_cursor_moves() {
+        let mut buffer = Buffer::with_contents(String::from("abcdef"));
+        for _ in 0..6 {
+            buffer.move_cursor_right();
+        }
+        buffer.remove();
+        buffer.remove();
+        buffer.move_cursor_left();
+        buffer.remove();
+        assert_eq!(buffer.as_str(), "abd");
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.as_str(), "abcd");
+        assert_eq!(buffer.cursor.line_offset, 3, "cursor.line_offset mismatch!");
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.as_str(), "abcdef");
+        assert_eq!(buffer.cursor.line_offset, 6, "cursor.line_offset mismatch!");
+    }
+
+    #[test]
+    fn move_cursor_over_multibyte_graphemes() {
+        // "e" + combining acute accent, a family emoji made of several
+        // code points joined by zero width joiners, and a CJK character
+        let text = "e\u{301}\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{4E2D}";
+        let mut buffer = Buffer::with_contents(String::from(text));
+
+        buffer.move_cursor_right();
+        assert_eq!(buffer.cursor.node_offset, 3, "cursor.node_offset mismatch!");
+        assert_eq!(buffer.cursor.line_offset, 1, "cursor.line_offset mismatch!");
+
+        buffer.move_cursor_right();
+        assert_eq!(
+            buffer.cursor.node_offset, 21,
+            "cursor.node_offset mismatch!"
+        );
+        assert_eq!(buffer.cursor.line_offset, 2, "cursor.line_offset mismatch!");
+
+        buffer.move_cursor_right();
+        buffer.move_cursor_right();
+        assert_eq!(
+            buffer.cursor.node_offset, 24,
+            "cursor.node_offset mismatch!"
+        );
+        assert_eq!(buffer.cursor.line_offset, 3, "cursor.line_offset mismatch!");
+
+        buffer.move_cursor_left();
+        buffer.move_cursor_left();
+        assert_eq!(buffer.cursor.node_offset, 3, "cursor.node_offset mismatch!");
+        assert_eq!(buffer.cursor.line_offset, 1, "cursor.line_offset mismatch!");
+    }
+
+    #[test]
+    fn remove_grapheme_split_across_nodes() {
+        let mut buffer = Buffer::new();
+        buffer.insert('a');
+        buffer.insert('e');
+        buffer.insert('\u{301}');
+        assert_eq!(buffer.node_list.len(), 3, "node_list.len() mismatch!");
+        assert_eq!(buffer.cursor.line_offset, 2, "cursor.line_offset mismatch!");
+
+        buffer.move_cursor_left();
+        assert_eq!(buffer.cursor_position(), 1, "cursor position mismatch!");
+        buffer.move_cursor_right();
+
+        buffer.remove();
+        assert_eq!(buffer.as_str(), "a");
+        assert_eq!(buffer.cursor.line_offset, 1, "cursor.line_offset mismatch!");
+    }
+
+    #[test]
+    fn remove_multibyte_char_from_original() {
+        let mut buffer = Buffer::with_contents(String::from("\u{4E2D}\u{6587}x"));
+        buffer.move_cursor_right();
+        buffer.move_cursor_right();
+        buffer.remove();
+
+        assert_eq!(buffer.as_str(), "\u{4E2D}x");
+        assert_eq!(buffer.cursor.node_offset, 0, "cursor.node_offset mismatch!");
+        assert_eq!(buffer.cursor.line_offset, 1, "cursor.line_offset mismatch!");
+
+        buffer.insert('y');
+        assert_eq!(buffer.as_str(), "\u{4E2D}yx");
+    }
+
+    #[test]
+    fn move_cursor_down_keeps_screen_column() {
+        let mut buffer = Buffer::with_contents(String::from("abc\n\u{4E2D}\u{6587}\u{5B57}"));
+        buffer.move_cursor_right();
+        buffer.move_cursor_right();
+        buffer.move_cursor_down();
+
+        // Screen column 2 is the start of the second wide char
+        assert_eq!(buffer.cursor.node_offset, 7, "cursor.node_offset mismatch!");
+        assert_eq!(buffer.cursor.line_offset, 1, "cursor.line_offset mismatch!");
+        assert_eq!(buffer.current_line, 1, "buffer.current_line mismatch!");
+
+        buffer.move_cursor_right();
+        buffer.move_cursor_up();
+        assert_eq!(buffer.cursor.node_offset, 3, "cursor.node_offset mismatch!");
+        assert_eq!(buffer.cursor.line_offset, 3, "cursor.line_offset mismatch!");
+    }
+
+    #[test]
+    fn move_cursor_down_rounds_into_wide_char() {
+        let mut buffer = Buffer::with_contents(String::from("abc\n\u{4E2D}\u{6587}"));
+        buffer.move_cursor_right();
+        buffer.move_cursor_down();
+
+        // Screen column 1 falls inside the first wide char
+        assert_eq!(buffer.cursor.node_offset, 4, "cursor.node_offset mismatch!");
+        assert_eq!(buffer.cursor.line_offset, 0, "cursor.line_offset mismatch!");
+        assert_eq!(
+            buffer.cursor.original_line_offset, 1,
+            "cursor.original_line_offset mismatch!"
+        );
+    }
+
+    #[test]
+    fn move_cursor_down_across_tabs() {
+        let mut buffer = Buffer::with_contents(String::from("\tx\nabcdefghij"));
+        buffer.set_tab_stop(4);
+        buffer.move_cursor_right();
+        assert_eq!(
+            buffer.cursor.original_line_offset, 4,
+            "cursor.original_line_offset mismatch!"
+        );
+
+        buffer.move_cursor_down();
+        assert_eq!(buffer.cursor.line_offset, 4, "cursor.line_offset mismatch!");
+
+        buffer.move_cursor_left();
+        buffer.move_cursor_up();
+        assert_eq!(buffer.cursor.line_offset, 0, "cursor.line_offset mismatch!");
+        assert_eq!(buffer.cursor.node_offset, 0, "cursor.node_offset mismatch!");
+    }
+
+    #[test]
+    fn move_cursor_over_crlf() {
+        let mut buffer = Buffer::with_contents(String::from("a\r\nb"));
+        buffer.move_cursor_right();
+        buffer.move_cursor_right();
+        assert_eq!(buffer.cursor.node_offset, 3, "cursor.node_offset mismatch!");
+        assert_eq!(buffer.current_line, 1, "buffer.current_line mismatch!");
+
+        buffer.move_cursor_left();
+        assert_eq!(buffer.cursor.node_offset, 1, "cursor.node_offset mismatch!");
+        assert_eq!(buffer.cursor.line_offset, 1, "cursor.line_offset mismatch!");
+        assert_eq!(buffer.current_line, 0, "buffer.current_line mismatch!");
+    }
+
+    #[test]
+    fn as_tagged_text_cursor_covers_grapheme() {
+        let mut buffer = Buffer::with_contents(String::from("a\u{1F600}b"));
+        buffer.move_cursor_right();
+        let tagged_text = buffer.as_tagged_text();
+        assert_eq!(tagged_text.tags()[0], TextTag::new(Tag::Cursor, 1, 5));
+    }
+
+    #[test]
+    fn num_lines() {
+        assert_eq!(Buffer::new().num_lines(), 1);
+        assert_eq!(Buffer::with_contents(String::from("a\nb\n")).num_lines(), 3);
+
+        let mut buffer = Buffer::with_contents(String::from("a\nb"));
+        buffer.insert('\n');
+        assert_eq!(buffer.num_lines(), 3);
+    }
+
+    #[test]
+    fn as_tagged_text_for_lines_with_cursor() {
+        let mut buffer = Buffer::with_contents(String::from("ab\ncd\nef\ngh"));
+        buffer.move_cursor_down();
+        buffer.move_cursor_down();
+        buffer.move_cursor_right();
+        buffer.insert('x');
+
+        let tagged_text = buffer.as_tagged_text_for_lines(1, 2);
+        assert_eq!(tagged_text.as_str(), "cd\nexf\n");
+        assert_eq!(tagged_text.tags(), &vec![TextTag::new(Tag::Cursor, 5, 6)]);
+    }
+
+    #[test]
+    fn as_tagged_text_for_lines_without_cursor() {
+        let buffer = Buffer::with_contents(String::from("ab\ncd\nef"));
+
+        let tagged_text = buffer.as_tagged_text_for_lines(2, 5);
+        assert_eq!(tagged_text.as_str(), "ef");
+        assert!(tagged_text.tags().is_empty());
+
+        let tagged_text = buffer.as_tagged_text_for_lines(4, 5);
+        assert_eq!(tagged_text.as_str(), "");
+    }
+
+    #[test]
+    fn is_modified_after_edits() {
+        let mut buffer = Buffer::with_contents(String::from("ab"));
+        assert!(!buffer.is_modified());
+
+        buffer.move_cursor_right();
+        assert!(!buffer.is_modified());
+
+        buffer.remove();
+        assert!(buffer.is_modified());
+
+        buffer.set_unmodified();
+        assert!(!buffer.is_modified());
+
+        buffer.undo();
+        assert!(buffer.is_modified());
+    }
+
+    #[test]
+    fn move_cursor_to_offset() {
+        let mut buffer = Buffer::with_contents(String::from("ab\nc\u{4E2D}d"));
+        buffer.move_cursor_right();
+        buffer.insert('x');
+
+        buffer.move_cursor_to(8);
+        assert_eq!(buffer.cursor_position(), 8);
+        assert_eq!(buffer.current_line(), 1);
+        assert_eq!(buffer.cursor().line_offset, 2);
+        assert_eq!(buffer.cursor_display_column(), 3);
+
+        buffer.move_cursor_to(100);
+        assert_eq!(buffer.cursor_position(), buffer.len());
+        assert_eq!(buffer.cursor().line_offset, 3);
+
+        buffer.move_cursor_to(0);
+        buffer.move_cursor_down();
+        assert_eq!(buffer.current_line(), 1);
+    }
+
+    #[test]
+    fn delete_range_across_nodes() {
+        let mut buffer = Buffer::with_contents(String::from("abc\ndef"));
+        buffer.move_cursor_right();
+        buffer.insert('x');
+        buffer.move_cursor_to(6);
+        buffer.insert('y');
+        assert_eq!(buffer.as_str(), "axbc\ndyef");
+
+        assert_eq!(buffer.delete_range(2, 7), "bc\ndy");
+        assert_eq!(buffer.as_str(), "axef");
+        assert_eq!(buffer.cursor_position(), 2);
+        assert_eq!(buffer.current_line(), 0);
+        assert_eq!(buffer.num_lines(), 1);
+
+        buffer.insert('z');
+        assert_eq!(buffer.as_str(), "axzef");
+    }
+
+    #[test]
+    fn delete_range_is_one_undo_step() {
+        let mut buffer = Buffer::with_contents(String::from("one\ntwo\nthree"));
+        buffer.move_cursor_to(5);
+        buffer.delete_range(2, 10);
+        assert_eq!(buffer.as_str(), "onree");
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.as_str(), "one\ntwo\nthree");
+        assert_eq!(buffer.cursor_position(), 5);
+        assert_eq!(buffer.current_line(), 1);
+
+        assert!(buffer.redo());
+        assert_eq!(buffer.as_str(), "onree");
+        assert_eq!(buffer.cursor_position(), 2);
+    }
+
+    #[test]
+    fn delete_everything() {
+        let mut buffer = Buffer::with_contents(String::from("ab\ncd"));
+        assert_eq!(buffer.delete_range(0, 100), "ab\ncd");
+        assert_eq!(buffer.as_str(), "");
+        assert_eq!(buffer.cursor_position(), 0);
+        assert!(buffer.is_empty());
+
+        buffer.insert('x');
+        assert_eq!(buffer.as_str(), "x");
+        assert!(buffer.undo());
+        assert!(buffer.undo());
+        assert_eq!(buffer.as_str(), "ab\ncd");
+    }
+
+    #[test]
+    fn selection_follows_cursor() {
+        let mut buffer = Buffer::with_contents(String::from("abc\ndef"));
+        assert_eq!(buffer.selection(), None);
+
+        buffer.move_cursor_right();
+        buffer.set_anchor(false);
+        assert_eq!(buffer.selection(), None);
+
+        buffer.move_cursor_down();
+        assert_eq!(buffer.selection(), Some((1, 5)));
+
+        buffer.move_cursor_up();
+        buffer.move_cursor_left();
+        assert_eq!(buffer.selection(), Some((0, 1)));
+
+        // Setting the anchor again keeps the selection going
+        buffer.set_anchor(false);
+        assert_eq!(buffer.selection(), Some((0, 1)));
+
+        buffer.clear_anchor();
+        assert_eq!(buffer.selection(), None);
+    }
+
+    #[test]
+    fn inclusive_selection_covers_grapheme_under_cursor() {
+        let mut buffer = Buffer::with_contents(String::from("a\u{1F600}b"));
+        buffer.set_anchor(true);
+        assert_eq!(buffer.selection(), Some((0, 1)));
+
+        buffer.move_cursor_right();
+        assert_eq!(buffer.selection(), Some((0, 5)));
+    }
+
+    #[test]
+    fn as_tagged_text_highlights_selection() {
+        let mut buffer = Buffer::with_contents(String::from("ab\ncd\nef"));
+        buffer.move_cursor_right();
+        buffer.set_anchor(false);
+        buffer.move_cursor_down();
+        buffer.move_cursor_down();
+
+        let tagged_text = buffer.as_tagged_text();
+        assert_eq!(
+            tagged_text.tags(),
+            &vec![
+                TextTag::new(Tag::Highlighted, 1, 7),
+                TextTag::new(Tag::Cursor, 7, 8),
+            ]
+        );
+
+        let tagged_text = buffer.as_tagged_text_for_lines(1, 1);
+        assert_eq!(tagged_text.as_str(), "cd\n");
+        assert_eq!(
+            tagged_text.tags(),
+            &vec![TextTag::new(Tag::Highlighted, 0, 3)]
+        );
+    }
+
+    #[test]
+    fn delete_selection_as_one_undo_step() {
+        let mut buffer = Buffer::with_contents(String::from("abcdef"));
+        assert_eq!(buffer.delete_selection(), None);
+
+        buffer.move_cursor_right();
+        buffer.set_anchor(false);
+        buffer.move_cursor_right();
+        buffer.move_cursor_right();
+        assert_eq!(buffer.delete_selection(), Some(String::from("bc")));
+        assert_eq!(buffer.as_str(), "adef");
+        assert_eq!(buffer.selection(), None);
+
+        buffer.undo();
+        assert_eq!(buffer.as_str(), "abcdef");
+        assert_eq!(buffer.selection(), None);
+    }
+
+    #[test]
+    fn edits_drop_the_selection() {
+        let mut buffer = Buffer::with_contents(String::from("abc"));
+        buffer.set_anchor(false);
+        buffer.move_cursor_right();
+        buffer.insert('x');
+        assert_eq!(buffer.selection(), None);
+
+        buffer.set_anchor(false);
+        buffer.move_cursor_right();
+        buffer.remove();
+        assert_eq!(buffer.selection(), None);
+    }
+
+    fn buffer_with_cursors_below(contents: &str, extra: usize) -> Buffer {
+        let mut buffer = Buffer::with_contents(String::from(contents));
+        for _ in 0..extra {
+            assert!(buffer.add_cursor_below());
+        }
+        buffer
+    }
+
+    #[test]
+    fn add_cursor_below_keeps_column() {
+        let mut buffer = Buffer::with_contents(String::from("abcd\nx\nabcd"));
+        buffer.move_cursor_right();
+        buffer.move_cursor_right();
+        buffer.move_cursor_right();
+
+        assert!(buffer.add_cursor_below());
+        assert!(buffer.add_cursor_below());
+        assert!(!buffer.add_cursor_below());
+        assert_eq!(buffer.cursor_positions(), vec![3, 6, 10]);
+        assert_eq!(buffer.cursor_position(), 3);
+    }
+
+    #[test]
+    fn insert_at_every_cursor() {
+        let mut buffer = buffer_with_cursors_below("ab\ncd\nef", 2);
+        buffer.insert('x');
+        buffer.insert_str(String::from("yz"));
+        assert_eq!(buffer.as_str(), "xyzab\nxyzcd\nxyzef");
+        assert_eq!(buffer.cursor_positions(), vec![3, 9, 15]);
+        assert_eq!(buffer.cursor_position(), 3);
+        assert_eq!(buffer.current_line(), 0);
+        assert!(buffer.is_modified());
+
+        buffer.insert('\n');
+        assert_eq!(buffer.as_str(), "xyz\nab\nxyz\ncd\nxyz\nef");
+        assert_eq!(buffer.current_line(), 1);
+        assert_eq!(buffer.cursor_positions(), vec![4, 11, 18]);
+    }
+
+    #[test]
+    fn edits_at_every_cursor_undo_together() {
+        let mut buffer = buffer_with_cursors_below("ab\ncd\nef", 2);
+        buffer.insert('x');
+        buffer.insert('y');
+        buffer.move_cursor_left();
+        buffer.remove();
+        assert_eq!(buffer.as_str(), "yab\nycd\nyef");
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.as_str(), "xyab\nxycd\nxyef");
+        assert_eq!(buffer.cursor_positions(), vec![1]);
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.as_str(), "ab\ncd\nef");
+        assert!(!buffer.undo());
+
+        assert!(buffer.redo());
+        assert_eq!(buffer.as_str(), "xyab\nxycd\nxyef");
+    }
+
+    #[test]
+    fn cursors_that_collide_merge() {
+        let mut buffer = Buffer::with_contents(String::from("abc"));
+        buffer.move_cursor_right();
+        buffer.move_cursor_right();
+        buffer.extra_cursors.push(ExtraCursor {
+            position: 3,
+            anchor: None,
+            original_line_offset: 3,
+        });
+
+        buffer.remove();
+        assert_eq!(buffer.as_str(), "a");
+        assert_eq!(buffer.cursor_positions(), vec![1]);
+
+        let mut buffer = buffer_with_cursors_below("ab\ncd", 1);
+        buffer.move_cursor_right();
+        buffer.move_cursor_up();
+        assert_eq!(buffer.cursor_positions(), vec![1]);
+    }
+
+    #[test]
+    fn move_every_cursor() {
+        let mut buffer = buffer_with_cursors_below("abc\nd\nefg", 2);
+        buffer.move_cursor_right();
+        assert_eq!(buffer.cursor_positions(), vec![1, 5, 7]);
+
+        // Each cursor keeps its own screen column to return to
+        buffer.move_cursor_right();
+        buffer.move_cursor_right();
+        buffer.move_cursor_up();
+        assert_eq!(buffer.cursor_positions(), vec![3, 5]);
+        assert_eq!(buffer.cursor_position(), 3);
+    }
+
+    #[test]
+    fn add_cursor_at_next_match() {
+        let mut buffer = Buffer::with_contents(String::from("foo bar foo\nfoo"));
+        assert!(!buffer.add_cursor_at_next_match());
+
+        buffer.move_cursor_to(8);
+        buffer.set_anchor(false);
+        (0..3).for_each(|_| buffer.move_cursor_right());
+        assert!(buffer.add_cursor_at_next_match());
+        assert!(buffer.add_cursor_at_next_match());
+        assert!(!buffer.add_cursor_at_next_match());
+        assert_eq!(buffer.selections(), vec![(0, 3), (8, 11), (12, 15)]);
+        assert_eq!(buffer.cursor_positions(), vec![3, 11, 15]);
+
+        assert_eq!(
+            buffer.delete_selection(),
+            Some(String::from("foo\nfoo\nfoo"))
+        );
+        assert_eq!(buffer.as_str(), " bar \n");
+        buffer.insert('x');
+        assert_eq!(buffer.as_str(), "x bar x\nx");
+        assert_eq!(buffer.cursor_position(), 7);
+    }
+
+    #[test]
+    fn add_cursor_at_next_match_faces_the_same_way() {
+        let mut buffer = Buffer::with_contents(String::from("abc abc"));
+        buffer.move_cursor_to(2);
+        buffer.set_anchor(true);
+        buffer.move_cursor_left();
+        buffer.move_cursor_left();
+        assert_eq!(buffer.selection(), Some((0, 3)));
+
+        assert!(buffer.add_cursor_at_next_match());
+        assert_eq!(buffer.extra_cursors()[0].position, 4);
+        assert_eq!(buffer.extra_cursors()[0].anchor, Some(6));
+        assert_eq!(buffer.selections(), vec![(0, 3), (4, 7)]);
+    }
+
+    #[test]
+    fn add_cursor_at_next_match_across_blocks() {
+        // The second "ab" runs over the end of the first block read
+        let mut contents = String::from("xab");
+        contents.push_str(&"x".repeat(FIND_BLOCK_LEN - 1));
+        contents.push_str("ab");
+        let mut buffer = Buffer::with_contents(contents);
+        buffer.move_cursor_to(1);
+        buffer.set_anchor(false);
+        buffer.move_cursor_right();
+        buffer.move_cursor_right();
+
+        assert!(buffer.add_cursor_at_next_match());
+        assert_eq!(
+            buffer.selections(),
+            vec![(1, 3), (FIND_BLOCK_LEN + 2, FIND_BLOCK_LEN + 4)]
+        );
+        assert!(!buffer.add_cursor_at_next_match());
+    }
+
+    #[test]
+    fn every_cursor_is_tagged() {
+        let mut buffer = buffer_with_cursors_below("ab\ncd\nef", 2);
+        buffer.move_cursor_right();
+        assert_eq!(
+            buffer.as_str_split_by_cursors(),
+            vec!["a", "b\nc", "d\ne", "f"]
+        );
+        assert_eq!(
+            buffer.as_tagged_text().tags(),
+            &vec![
+                TextTag::new(Tag::Cursor, 1, 2),
+                TextTag::new(Tag::Cursor, 4, 5),
+                TextTag::new(Tag::Cursor, 7, 8),
+            ]
+        );
+
+        let tagged_text = buffer.as_tagged_text_for_lines(1, 2);
+        assert_eq!(
+            tagged_text.tags(),
+            &vec![
+                TextTag::new(Tag::Cursor, 1, 2),
+                TextTag::new(Tag::Cursor, 4, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn delete_range_drops_extra_cursors() {
+        let mut buffer = buffer_with_cursors_below("ab\ncd", 1);
+        buffer.delete_range(0, 1);
+        assert!(buffer.extra_cursors().is_empty());
+    }
+
+    #[test]
+    fn replace_ranges_as_one_undo_step() {
+        let mut buffer = Buffer::with_contents(String::from("one two three"));
+        buffer.replace_ranges(&[
+            (0, 3, String::from("1")),
+            (4, 4, String::from("and ")),
+            (8, 13, String::new()),
+        ]);
+        assert_eq!(buffer.as_str(), "1 and two ");
+        assert_eq!(buffer.cursor_position(), 10);
+        assert!(buffer.is_modified());
+
+        assert!(buffer.undo());
+        assert_eq!(buffer.as_str(), "one two three");
+        assert!(!buffer.undo());
+        assert!(buffer.redo());
+        assert_eq!(buffer.as_str(), "1 and two ");
+    }
+
+    #[test]
+    fn replace_ranges_in_empty_buffer() {
+        let mut buffer = Buffer::new();
+        buffer.replace_ranges(&[]);
+        assert!(!buffer.undo());
+
+        buffer.replace_ranges(&[(0, 0, String::from("a\nb"))]);
+        assert_eq!(buffer.as_str(), "a\nb");
+        assert_eq!(buffer.num_lines(), 2);
+    }
+
+    #[test]
+    fn unmodified_after_undoing_back_to_save() {
+        let mut buffer = Buffer::with_contents(String::from("ab"));
+        buffer.insert('c');
+        assert!(buffer.is_modified());
+        buffer.undo();
+        assert!(!buffer.is_modified());
+        buffer.redo();
+        assert!(buffer.is_modified());
+
+        buffer.set_unmodified();
+        buffer.undo();
+        assert!(buffer.is_modified());
+        buffer.redo();
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    fn modified_after_edits_that_cancel_out() {
+        let mut buffer = Buffer::new();
+        assert!(!buffer.is_modified());
+        buffer.insert('a');
+        buffer.remove();
+        assert_eq!(buffer.as_str(), "");
+        assert!(!buffer.is_modified());
+
+        let mut buffer = Buffer::with_contents(String::from("ab"));
+        buffer.move_cursor_right();
+        buffer.insert('c');
+        buffer.remove();
+        assert_eq!(buffer.as_str(), "ab");
+        // Only the pieces are compared, not the text they spell out
+        assert!(buffer.is_modified());
+    }
+
+    #[test]
+    fn restore_pieces_rebuilds_text() {
+        let mut buffer = Buffer::with_contents(String::from("ab\ncd"));
+        buffer.move_cursor_right();
+        buffer.insert_str(String::from("x\ny"));
+        let pieces: Vec<_> = buffer.pieces().collect();
+        let added = String::from_utf8(buffer.added().to_vec()).unwrap();
+
+        let mut rebuilt = Buffer::with_contents(String::from("ab\ncd"));
+        assert!(rebuilt.restore_pieces(added, &pieces));
+        assert_eq!(rebuilt.as_str(), "ax\nyb\ncd");
+        assert_eq!(rebuilt.num_lines(), 3);
+        assert_eq!(*rebuilt.original(), b"ab\ncd".to_vec());
+        assert!(rebuilt.is_modified());
+        assert!(!rebuilt.undo());
+
+        // Pieces that don't fit leave the buffer as it was
+        let mut rebuilt = Buffer::with_contents(String::from("a\u{4E2D}"));
+        let bad_pieces = [
+            vec![(BufferType::Added, 0, 1)],
+            vec![(BufferType::Original, 1, usize::MAX)],
+            vec![(BufferType::Original, 0, 2)],
+        ];
+        for pieces in &bad_pieces {
+            assert!(!rebuilt.restore_pieces(String::new(), pieces));
+            assert_eq!(rebuilt.as_str(), "a\u{4E2D}");
+            assert!(!rebuilt.is_modified());
+        }
+    }
+
+    #[test]
+    fn file_contents_keep_line_endings() {
+        let mut buffer = Buffer::with_file_contents(String::from("ab\r\ncd\r\n"), Encoding::Utf8);
+        assert_eq!(buffer.line_ending(), LineEnding::CrLf);
+        assert_eq!(buffer.as_str(), "ab\ncd\n");
+        assert_eq!(buffer.num_lines(), 3);
+        assert!(!buffer.is_modified());
+
+        buffer.move_cursor_to(2);
+        assert_eq!(buffer.cursor().line_offset, 2);
+        buffer.move_cursor_right();
+        assert_eq!(buffer.current_line(), 1);
+        buffer.insert('\n');
+        assert_eq!(buffer.file_contents().unwrap(), b"ab\r\n\r\ncd\r\n");
+
+        let buffer = Buffer::with_file_contents(String::from("ab\rcd"), Encoding::Utf8);
+        assert_eq!(buffer.line_ending(), LineEnding::Cr);
+        assert_eq!(buffer.num_lines(), 2);
+        assert_eq!(buffer.file_contents().unwrap(), b"ab\rcd");
+    }
+
+    #[test]
+    fn changing_line_ending_is_a_modification() {
+        let mut buffer = Buffer::with_file_contents(String::from("a\nb"), Encoding::Utf8);
+        assert_eq!(buffer.line_ending(), LineEnding::Lf);
+
+        buffer.set_line_ending(LineEnding::CrLf);
+        assert!(buffer.is_modified());
+        assert_eq!(buffer.file_contents().unwrap(), b"a\r\nb");
+        buffer.set_unmodified();
+        assert!(!buffer.is_modified());
+
+        buffer.set_line_ending(LineEnding::Lf);
+        assert!(buffer.is_modified());
+        buffer.set_line_ending(LineEnding::CrLf);
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    fn file_contents_keep_encoding() {
+        let mut buffer =
+            Buffer::with_file_contents(String::from("caf\u{e9}\r\n"), Encoding::Latin1);
+        assert_eq!(buffer.file_contents().unwrap(), b"caf\xE9\r\n");
+
+        buffer.set_encoding(Encoding::Utf16Le);
+        assert!(buffer.is_modified());
+        assert_eq!(
+            buffer.file_contents().unwrap(),
+            b"\xFF\xFEc\0a\0f\0\xE9\0\r\0\n\0"
+        );
+        buffer.set_encoding(Encoding::Latin1);
+        assert!(!buffer.is_modified());
+
+        buffer.insert('\u{20ac}');
+        assert!(buffer.file_contents().is_err());
+    }
+
+    #[test]
+    fn file_contents_put_back_invalid_bytes_of_mapped_file() -> std::io::Result<()> {
+        // Invalid bytes in the second piece, which isn't checked on mapping
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("file.txt");
+        let mut contents = "x".repeat(crate::model::original::PIECE_LEN).into_bytes();
+        contents.extend_from_slice(b"\na\xE4\xB8b\n");
+        std::fs::write(&path, &contents)?;
+        let file = std::fs::File::open(&path)?;
+        let mut buffer = Buffer::with_original(Original::map(&file, 0)?.unwrap());
+        let second = crate::model::original::PIECE_LEN + 1;
+        assert_eq!(buffer.text_between(second, buffer.len()), "a??b\n");
+        assert_eq!(buffer.file_contents().unwrap(), contents);
+
+        // Each "?" stands for a byte of its own, whatever goes between them
+        buffer.move_cursor_to(second + 2);
+        buffer.insert('x');
+        assert_eq!(&buffer.file_contents().unwrap()[second..], b"a\xE4x\xB8b\n");
+        buffer.delete_range(second + 1, second + 2);
+        assert_eq!(&buffer.file_contents().unwrap()[second..], b"ax\xB8b\n");
+        buffer.delete_range(second + 2, second + 3);
+        assert_eq!(&buffer.file_contents().unwrap()[second..], b"axb\n");
+        Ok(())
+    }
+
+    #[test]
+    fn file_contents_put_back_invalid_bytes() {
+        let decoded = Encoding::Utf8.decode(b"\x81a\r\nb\xFF\r\n");
+        let mut buffer = Buffer::with_decoded(decoded);
+        assert_eq!(buffer.as_str(), "\u{fffd}a\nb\u{fffd}\n");
+        assert_eq!(buffer.file_contents().unwrap(), b"\x81a\r\nb\xFF\r\n");
+
+        // A copy of a replacement character is only that
+        buffer.move_cursor_to(6);
+        buffer.insert('\u{fffd}');
+        assert_eq!(
+            buffer.file_contents().unwrap(),
+            b"\x81a\r\nb\xEF\xBF\xBD\xFF\r\n"
+        );
+
+        // and deleting one deletes the bytes it stood for
+        buffer.delete_range(0, 3);
+        assert_eq!(
+            buffer.file_contents().unwrap(),
+            b"a\r\nb\xEF\xBF\xBD\xFF\r\n"
+        );
+        assert!(buffer.undo());
+        assert_eq!(
+            buffer.file_contents().unwrap(),
+            b"\x81a\r\nb\xEF\xBF\xBD\xFF\r\n"
+        );
+
+        // UTF-16 has no way to hold them
+        buffer.set_encoding(Encoding::Utf16Le);
+        assert!(buffer
+            .file_contents()
+            .unwrap()
+            .starts_with(b"\xFF\xFE\xFD\xFFa\0"));
+    }
+
+    // Text long enough to be split into a few pieces when used as it is
+    fn long_text() -> String {
+        (0..250_000)
+            .map(|line| format!("line {:06}\n", line))
+            .collect()
+    }
+
+    #[test]
+    fn with_original_splits_at_line_ends() {
+        let text = long_text();
+        let buffer = Buffer::with_original(Original::new(text.clone().into_bytes()));
+        let pieces: Vec<_> = buffer.pieces().collect();
+        assert_eq!(pieces.len(), 3);
+        let mut start = 0;
+        for &(from, index, len) in pieces.iter() {
+            assert_eq!(from, BufferType::Original);
+            assert_eq!(index, start);
+            assert_eq!(text.as_bytes()[index + len - 1], b'\n');
+            start += len;
+        }
+        assert_eq!(start, text.len());
+        assert_eq!(buffer.num_lines(), 250_001);
+        assert_eq!(buffer.as_str(), text);
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    fn with_original_counts_lines_when_needed() {
+        let text = long_text();
+        let buffer = Buffer::with_original(Original::new(text.into_bytes()));
+        assert_eq!(buffer.num_lines_if_counted(), None);
+        assert_eq!(buffer.num_lines_up_to(10), 10);
+        assert_eq!(buffer.num_lines_if_counted(), None);
+        assert_eq!(buffer.num_lines_up_to(300_000), 250_001);
+        assert_eq!(buffer.num_lines_if_counted(), Some(250_001));
+
+        let buffer = Buffer::with_contents(String::from("a\nb"));
+        assert_eq!(buffer.num_lines_if_counted(), Some(2));
+        assert_eq!(buffer.num_lines_up_to(1), 1);
+    }
+
+    #[test]
+    fn with_original_acts_as_with_contents() {
+        let text = long_text();
+        let mut split = Buffer::with_original(Original::new(text.clone().into_bytes()));
+        let mut whole = Buffer::with_contents(text.clone());
+        let boundary = split.pieces().next().unwrap().2;
+
+        for &line in &[0, 87_381, 87_382, 174_762, 249_999, 250_000, 300_000] {
+            assert_eq!(split.offset_of_line(line), whole.offset_of_line(line));
+        }
+        for &pos in &[0, boundary - 1, boundary, boundary + 1, text.len()] {
+            assert_eq!(split.line_bounds(pos), whole.line_bounds(pos));
+        }
+
+        for buffer in [&mut split, &mut whole] {
+            buffer.move_cursor_to(boundary - 5);
+            buffer.move_cursor_down();
+            buffer.insert('x');
+            buffer.move_cursor_up();
+            buffer.move_cursor_up();
+            buffer.remove();
+        }
+        assert_eq!(split.cursor_position(), whole.cursor_position());
+        assert_eq!(split.current_line(), whole.current_line());
+        assert_eq!(split.cursor(), whole.cursor());
+        assert_eq!(split.as_str(), whole.as_str());
+        assert!(split.is_modified());
+    }
 }
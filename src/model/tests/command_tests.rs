@@ -0,0 +1,275 @@
+#[cfg(test)]
+mod command_tests {
+    use super::super::*;
+
+    #[test]
+    fn parse_write() {
+        assert_eq!(Command::parse(":w"), Ok(Command::Write(None)));
+        assert_eq!(Command::parse("write"), Ok(Command::Write(None)));
+    }
+
+    #[test]
+    fn parse_write_with_file_name() {
+        assert_eq!(
+            Command::parse(":w notes.txt"),
+            Ok(Command::Write(Some(String::from("notes.txt"))))
+        );
+        assert_eq!(
+            Command::parse(":w   spaced name.txt  "),
+            Ok(Command::Write(Some(String::from("spaced name.txt"))))
+        );
+    }
+
+    #[test]
+    fn parse_write_quit() {
+        assert_eq!(Command::parse(":wq"), Ok(Command::WriteQuit(None)));
+        assert_eq!(
+            Command::parse(":x notes.txt"),
+            Ok(Command::WriteQuit(Some(String::from("notes.txt"))))
+        );
+    }
+
+    #[test]
+    fn parse_edit() {
+        assert_eq!(
+            Command::parse(":e notes.txt"),
+            Ok(Command::Edit {
+                file_path: String::from("notes.txt"),
+                force: false,
+            })
+        );
+        assert_eq!(
+            Command::parse(":e! notes.txt"),
+            Ok(Command::Edit {
+                file_path: String::from("notes.txt"),
+                force: true,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_edit_with_encoding() {
+        assert_eq!(
+            Command::parse(":e ++enc=latin1"),
+            Ok(Command::EditWithEncoding {
+                encoding: String::from("latin1"),
+                file_path: None,
+                force: false,
+            })
+        );
+        assert_eq!(
+            Command::parse(":e ++enc=utf-16le notes.txt"),
+            Ok(Command::EditWithEncoding {
+                encoding: String::from("utf-16le"),
+                file_path: Some(String::from("notes.txt")),
+                force: false,
+            })
+        );
+        assert_eq!(
+            Command::parse(":e! ++enc=latin1"),
+            Ok(Command::EditWithEncoding {
+                encoding: String::from("latin1"),
+                file_path: None,
+                force: true,
+            })
+        );
+        assert_eq!(
+            Command::parse(":e ++enc="),
+            Err(String::from("Invalid argument: ++enc="))
+        );
+        assert_eq!(
+            Command::parse(":e ++bin"),
+            Err(String::from("Invalid argument: ++bin"))
+        );
+    }
+
+    #[test]
+    fn parse_edit_without_file_name() {
+        assert_eq!(Command::parse(":e"), Err(String::from("No file name")));
+        assert_eq!(Command::parse(":e!"), Err(String::from("No file name")));
+    }
+
+    #[test]
+    fn parse_quit() {
+        assert_eq!(Command::parse(":q"), Ok(Command::Quit { force: false }));
+        assert_eq!(Command::parse(":q!"), Ok(Command::Quit { force: true }));
+    }
+
+    #[test]
+    fn parse_quit_with_trailing_characters() {
+        assert_eq!(
+            Command::parse(":q now"),
+            Err(String::from("Trailing characters"))
+        );
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(Command::parse(":"), Err(String::from("No command given")));
+        assert_eq!(Command::parse(""), Err(String::from("No command given")));
+    }
+
+    #[test]
+    fn parse_unknown() {
+        assert_eq!(
+            Command::parse(":frobnicate"),
+            Err(String::from("Not an editor command: frobnicate"))
+        );
+    }
+
+    #[test]
+    fn parse_set() {
+        assert_eq!(
+            Command::parse(":set tabstop=4"),
+            Ok(Command::Set {
+                option: String::from("tabstop"),
+                value: Some(String::from("4"))
+            })
+        );
+        assert_eq!(
+            Command::parse(":se ts = 2"),
+            Ok(Command::Set {
+                option: String::from("ts"),
+                value: Some(String::from("2"))
+            })
+        );
+    }
+
+    #[test]
+    fn parse_set_without_value() {
+        assert_eq!(
+            Command::parse(":set"),
+            Err(String::from("Argument required"))
+        );
+        assert_eq!(
+            Command::parse(":set number"),
+            Ok(Command::Set {
+                option: String::from("number"),
+                value: None
+            })
+        );
+    }
+
+    #[test]
+    fn parse_no_highlight() {
+        assert_eq!(Command::parse(":noh"), Ok(Command::NoHighlight));
+        assert_eq!(Command::parse("nohlsearch"), Ok(Command::NoHighlight));
+    }
+
+    fn substitute(range: LineRange, pattern: &str, replacement: &str) -> Substitute {
+        Substitute {
+            range,
+            pattern: String::from(pattern),
+            replacement: String::from(replacement),
+            global: false,
+            confirm: false,
+            ignore_case: false,
+        }
+    }
+
+    #[test]
+    fn parse_substitute() {
+        assert_eq!(
+            Command::parse(":s/a/b/"),
+            Ok(Command::Substitute(substitute(
+                LineRange::current_line(),
+                "a",
+                "b"
+            )))
+        );
+        assert_eq!(
+            Command::parse(":%s/a(b)/\\1"),
+            Ok(Command::Substitute(substitute(
+                LineRange::whole_file(),
+                "a(b)",
+                "\\1"
+            )))
+        );
+        assert_eq!(
+            Command::parse("substitute#x##"),
+            Ok(Command::Substitute(substitute(
+                LineRange::current_line(),
+                "x",
+                ""
+            )))
+        );
+    }
+
+    #[test]
+    fn parse_substitute_flags() {
+        let mut expected = substitute(LineRange::whole_file(), "a", "b");
+        expected.global = true;
+        expected.confirm = true;
+        expected.ignore_case = true;
+        assert_eq!(
+            Command::parse(":%s/a/b/gci"),
+            Ok(Command::Substitute(expected))
+        );
+        assert_eq!(
+            Command::parse(":s/a/b/x"),
+            Err(String::from("Trailing characters"))
+        );
+    }
+
+    #[test]
+    fn parse_substitute_escaped_delimiter() {
+        assert_eq!(
+            Command::parse(r":s/a\/b/c\/d\.e/"),
+            Ok(Command::Substitute(substitute(
+                LineRange::current_line(),
+                "a/b",
+                r"c/d\.e"
+            )))
+        );
+    }
+
+    #[test]
+    fn parse_substitute_ranges() {
+        let parse_range = |input: &str| match Command::parse(input) {
+            Ok(Command::Substitute(substitute)) => Ok(substitute.range),
+            Ok(command) => panic!("{:?}", command),
+            Err(e) => Err(e),
+        };
+        assert_eq!(
+            parse_range(":3s/a/b/"),
+            Ok(LineRange {
+                start: Address::Line(3),
+                end: Address::Line(3)
+            })
+        );
+        assert_eq!(
+            parse_range(":.,$s/a/b/"),
+            Ok(LineRange {
+                start: Address::Current,
+                end: Address::Last
+            })
+        );
+        assert_eq!(
+            parse_range(":2,10s/a/b/"),
+            Ok(LineRange {
+                start: Address::Line(2),
+                end: Address::Line(10)
+            })
+        );
+        assert_eq!(parse_range(":0s/a/b/"), Err(String::from("Invalid range")));
+    }
+
+    #[test]
+    fn commands_that_are_not_substitutions() {
+        assert_eq!(
+            Command::parse(":s"),
+            Err(String::from("Not an editor command: s"))
+        );
+        assert_eq!(
+            Command::parse(":%w"),
+            Err(String::from("Not an editor command: %w"))
+        );
+        assert_eq!(
+            Command::parse(":se ts=2"),
+            Ok(Command::Set {
+                option: String::from("ts"),
+                value: Some(String::from("2"))
+            })
+        );
+    }
+}
@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod diff_tests {
+    use super::super::*;
+
+    #[test]
+    fn same_texts() {
+        assert_eq!(
+            diff_lines("a\nb", "a\nb"),
+            vec![LineDiff::Same("a\n"), LineDiff::Same("b")]
+        );
+        assert_eq!(diff_lines("", ""), vec![]);
+        assert_eq!(unified_diff("a\n", "a\n", "x", "y"), "");
+    }
+
+    #[test]
+    fn fewest_changed_lines() {
+        assert_eq!(
+            diff_lines("a\nb\nc\nd\n", "a\nc\nx\nd\n"),
+            vec![
+                LineDiff::Same("a\n"),
+                LineDiff::Removed("b\n"),
+                LineDiff::Same("c\n"),
+                LineDiff::Added("x\n"),
+                LineDiff::Same("d\n"),
+            ]
+        );
+        assert_eq!(diff_lines("", "a\n"), vec![LineDiff::Added("a\n")]);
+        assert_eq!(
+            diff_lines("a", "b"),
+            vec![LineDiff::Removed("a"), LineDiff::Added("b")]
+        );
+    }
+
+    #[test]
+    fn unified_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n";
+        assert_eq!(
+            unified_diff(old, new, "ours", "theirs"),
+            "--- ours\n+++ theirs\n\
+             @@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+             @@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n"
+        );
+    }
+
+    #[test]
+    fn unified_hunks_merge_when_close() {
+        let old = "a\nb\nc\nd\ne\n";
+        let new = "A\nb\nc\nd\nE\n";
+        assert_eq!(
+            unified_diff(old, new, "ours", "theirs"),
+            "--- ours\n+++ theirs\n\
+             @@ -1,5 +1,5 @@\n-a\n+A\n b\n c\n d\n-e\n+E\n"
+        );
+    }
+
+    #[test]
+    fn unified_empty_ranges() {
+        assert_eq!(
+            unified_diff("", "a\n", "ours", "theirs"),
+            "--- ours\n+++ theirs\n@@ -0,0 +1 @@\n+a\n"
+        );
+        assert_eq!(
+            unified_diff("a\n", "", "ours", "theirs"),
+            "--- ours\n+++ theirs\n@@ -1 +0,0 @@\n-a\n"
+        );
+    }
+}
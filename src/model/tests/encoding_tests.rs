@@ -0,0 +1,176 @@
+#[cfg(test)]
+mod encoding_tests {
+    use super::super::*;
+
+    #[test]
+    fn detect_encodings() {
+        assert_eq!(Encoding::detect(b""), Encoding::Utf8);
+        assert_eq!(Encoding::detect(b"abc"), Encoding::Utf8);
+        assert_eq!(Encoding::detect("caf\u{e9}".as_bytes()), Encoding::Utf8);
+        assert_eq!(Encoding::detect(b"\xEF\xBB\xBFabc"), Encoding::Utf8Bom);
+        assert_eq!(Encoding::detect(b"\xFF\xFEa\0"), Encoding::Utf16Le);
+        assert_eq!(Encoding::detect(b"\xFE\xFF\0a"), Encoding::Utf16Be);
+        assert_eq!(Encoding::detect(b"caf\xE9"), Encoding::Latin1);
+        // Latin-1 only has control characters here, but cp1252 has these
+        assert_eq!(Encoding::detect(b"don\x92t \x80"), Encoding::Cp1252);
+        // Even cp1252 doesn't have 0x81, so it is broken UTF-8 instead
+        assert_eq!(Encoding::detect(b"a\x81b\x80"), Encoding::Utf8);
+        // As is anything with UTF-8 beyond ASCII in it
+        assert_eq!(Encoding::detect(b"caf\xC3\xA9 \x80"), Encoding::Utf8);
+    }
+
+    #[test]
+    fn utf8_with_bom_round_trip() {
+        let bytes = b"\xEF\xBB\xBFa\nb";
+        let decoded = decode(bytes, None);
+        assert_eq!(decoded.text, "a\nb");
+        assert_eq!(decoded.encoding, Encoding::Utf8Bom);
+        assert!(!decoded.invalid);
+        assert_eq!(decoded.encoding.encode(&decoded.text).unwrap(), bytes);
+    }
+
+    #[test]
+    fn utf16_round_trips() {
+        let le = b"\xFF\xFEh\0\xE9\0=\xD8\0\xDE";
+        let decoded = decode(le, None);
+        assert_eq!(decoded.text, "h\u{e9}\u{1f600}");
+        assert_eq!(decoded.encoding, Encoding::Utf16Le);
+        assert!(!decoded.invalid);
+        assert_eq!(Encoding::Utf16Le.encode(&decoded.text).unwrap(), le);
+
+        let be = b"\xFE\xFF\0h\0\xE9\xD8=\xDE\0";
+        let decoded = decode(be, None);
+        assert_eq!(decoded.text, "h\u{e9}\u{1f600}");
+        assert_eq!(decoded.encoding, Encoding::Utf16Be);
+        assert_eq!(Encoding::Utf16Be.encode(&decoded.text).unwrap(), be);
+    }
+
+    #[test]
+    fn invalid_utf16_is_replaced() {
+        let decoded = Encoding::Utf16Le.decode(b"\xFF\xFE\0\xD8a\0b");
+        assert_eq!(decoded.text, "\u{fffd}a\u{fffd}");
+        assert!(decoded.invalid);
+    }
+
+    #[test]
+    fn latin1_round_trip() {
+        let decoded = decode(b"caf\xE9 \xA9", None);
+        assert_eq!(decoded.text, "caf\u{e9} \u{a9}");
+        assert_eq!(decoded.encoding, Encoding::Latin1);
+        assert_eq!(
+            Encoding::Latin1.encode(&decoded.text).unwrap(),
+            b"caf\xE9 \xA9"
+        );
+        assert_eq!(
+            Encoding::Latin1.encode("\u{20ac}"),
+            Err(String::from("Can't write '\u{20ac}' as latin1"))
+        );
+    }
+
+    #[test]
+    fn cp1252_round_trip() {
+        let bytes = b"\x93quoted\x94 \x80 caf\xE9 \x81";
+        let decoded = decode(bytes, None);
+        assert_eq!(decoded.encoding, Encoding::Utf8);
+        let decoded = Encoding::Cp1252.decode(bytes);
+        assert_eq!(
+            decoded.text,
+            "\u{201c}quoted\u{201d} \u{20ac} caf\u{e9} \u{81}"
+        );
+        assert_eq!(Encoding::Cp1252.encode(&decoded.text).unwrap(), bytes);
+        assert_eq!(
+            Encoding::Cp1252.encode("\u{80}"),
+            Err(String::from("Can't write '\u{80}' as cp1252"))
+        );
+        assert_eq!(Encoding::Latin1.encode("\u{80}").unwrap(), b"\x80");
+    }
+
+    fn raw_run(offset: usize, len: usize, bytes: &[u8]) -> RawRun {
+        RawRun {
+            offset,
+            len,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_keeps_its_bytes() {
+        let bytes = b"a\x81b\xE2\x82";
+        let decoded = decode(bytes, None);
+        assert_eq!(decoded.encoding, Encoding::Utf8);
+        assert!(decoded.invalid);
+        assert_eq!(decoded.text, "a\u{fffd}b\u{fffd}");
+        assert_eq!(
+            decoded.raw_bytes.within(0, decoded.text.len()),
+            &[raw_run(1, 3, b"\x81"), raw_run(5, 3, b"\xE2\x82")]
+        );
+        assert_eq!(decoded.raw_bytes.within(2, 5), &[]);
+    }
+
+    #[test]
+    fn valid_utf8_is_never_taken_for_raw_bytes() {
+        // From the end of the last private use plane, and a replacement
+        // character that really is in the file
+        let text = "\u{10ff80}\u{10ffff}\u{fffd}";
+        let decoded = decode(text.as_bytes(), None);
+        assert_eq!(decoded.text, text);
+        assert!(!decoded.invalid);
+        assert!(decoded.raw_bytes.is_empty());
+        assert_eq!(
+            Encoding::Utf8.encode(&decoded.text).unwrap(),
+            text.as_bytes()
+        );
+    }
+
+    #[test]
+    fn raw_bytes_follow_line_endings() {
+        let decoded = decode(b"a\r\n\x81\r\nb\r\n\x81", None);
+        let raw_bytes = decoded.raw_bytes.into_lf(&decoded.text, LineEnding::CrLf);
+        assert_eq!(
+            raw_bytes.within(0, 100),
+            &[raw_run(2, 3, b"\x81"), raw_run(8, 3, b"\x81")]
+        );
+    }
+
+    #[test]
+    fn marking_invalid_utf8_keeps_offsets() {
+        assert_eq!(mark_invalid_utf8("caf\u{e9}".as_bytes(), 0), None);
+
+        let (marked, raw_bytes) = mark_invalid_utf8(b"a\x81b\xE2\x82\n", 10).unwrap();
+        assert_eq!(marked, b"a?b??\n");
+        assert_eq!(
+            raw_bytes.within(0, 20),
+            &[
+                raw_run(11, 1, b"\x81"),
+                raw_run(13, 1, b"\xE2"),
+                raw_run(14, 1, b"\x82")
+            ]
+        );
+    }
+
+    #[test]
+    fn reading_as_a_chosen_encoding() {
+        let decoded = decode("caf\u{e9}".as_bytes(), Some(Encoding::Latin1));
+        assert_eq!(decoded.text, "caf\u{c3}\u{a9}");
+        assert_eq!(decoded.encoding, Encoding::Latin1);
+    }
+
+    #[test]
+    fn names() {
+        let encodings = [
+            Encoding::Utf8,
+            Encoding::Utf8Bom,
+            Encoding::Utf16Le,
+            Encoding::Utf16Be,
+            Encoding::Latin1,
+            Encoding::Cp1252,
+        ];
+        for encoding in encodings {
+            assert_eq!(Encoding::from_name(encoding.name()), Some(encoding));
+        }
+        assert_eq!(Encoding::from_name("UTF8"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::from_name("iso-8859-1"), Some(Encoding::Latin1));
+        assert_eq!(Encoding::from_name("windows-1252"), Some(Encoding::Cp1252));
+        assert_eq!(Encoding::from_name("koi8-r"), None);
+    }
+}
@@ -0,0 +1,42 @@
+#[cfg(test)]
+mod lineending_tests {
+    use super::super::*;
+
+    #[test]
+    fn detect_line_endings() {
+        assert_eq!(LineEnding::detect(""), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\nb\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), LineEnding::CrLf);
+        assert_eq!(LineEnding::detect("\r\n"), LineEnding::CrLf);
+        assert_eq!(LineEnding::detect("a\rb\r"), LineEnding::Cr);
+    }
+
+    #[test]
+    fn mixed_line_endings_are_left_alone() {
+        assert_eq!(LineEnding::detect("a\r\nb\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("\na\r\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\rb\n"), LineEnding::Lf);
+    }
+
+    #[test]
+    fn convert_to_and_from_lf() {
+        let crlf = String::from("a\r\nb\r\r\n");
+        let lf = LineEnding::CrLf.to_lf(crlf.clone());
+        assert_eq!(lf, "a\nb\r\n");
+        assert_eq!(LineEnding::CrLf.apply_to(lf), crlf);
+
+        assert_eq!(LineEnding::Cr.to_lf(String::from("a\rb")), "a\nb");
+        assert_eq!(LineEnding::Cr.apply_to(String::from("a\nb")), "a\rb");
+        assert_eq!(LineEnding::Lf.apply_to(String::from("a\r\n")), "a\r\n");
+    }
+
+    #[test]
+    fn names() {
+        for ending in [LineEnding::Lf, LineEnding::CrLf, LineEnding::Cr] {
+            assert_eq!(LineEnding::from_name(ending.name()), Some(ending));
+        }
+        assert_eq!(LineEnding::from_name("dos"), Some(LineEnding::CrLf));
+        assert_eq!(LineEnding::from_name("windows"), None);
+    }
+}
@@ -19,7 +19,7 @@ mod node_list_tests {
 
     #[test]
     fn len_on_new_list() {
-        let mut node_list = NodeList::new();
+        let node_list = NodeList::new();
         assert_eq!(node_list.len(), 0);
     }
 
@@ -234,7 +234,6 @@ mod node_list_tests {
     #[test]
     fn remove_curr_from_non_empty_1() {
         let node_0 = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
-        let node_0_copy = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let mut node_list = NodeList::with_contents(vec![node_0]);
         node_list.remove_curr();
         assert_eq!(node_list.left_list, []);
@@ -246,7 +245,6 @@ mod node_list_tests {
         let node_0 = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_0_copy = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_1 = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
-        let node_1_copy = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
         let mut node_list = NodeList::with_contents(vec![node_0, node_1]);
         node_list.move_right();
         node_list.remove_curr();
@@ -257,7 +255,6 @@ mod node_list_tests {
     #[test]
     fn remove_curr_from_non_empty_3() {
         let node_0 = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
-        let node_0_copy = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_1 = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
         let node_1_copy = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
         let mut node_list = NodeList::with_contents(vec![node_0, node_1]);
@@ -280,7 +277,6 @@ mod node_list_tests {
     #[test]
     fn remove_prev_from_non_empty_2() {
         let node_0 = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
-        let node_0_copy = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_1 = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
         let node_1_copy = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
         let mut node_list = NodeList::with_contents(vec![node_0, node_1]);
@@ -331,13 +327,46 @@ mod node_list_tests {
         let node_0 = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_0_copy = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_1 = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
-        let node_1_copy = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
         let mut node_list = NodeList::with_contents(vec![node_0, node_1]);
         node_list.remove_next();
         assert_eq!(node_list.left_list, [node_0_copy]);
         assert_eq!(node_list.right_list, []);
     }
 
+    #[test]
+    fn splice_into_empty() {
+        let node_0 = BufferNode::new(BufferType::Original, 0, 1, vec![0]);
+        let node_0_copy = BufferNode::new(BufferType::Original, 0, 1, vec![0]);
+        let mut node_list = NodeList::new();
+        node_list.splice(0, 0, vec![node_0]);
+        assert_eq!(node_list.left_list, [node_0_copy]);
+        assert_eq!(node_list.right_list, []);
+    }
+
+    #[test]
+    fn splice_replace_middle() {
+        let node_0 = BufferNode::new(BufferType::Original, 0, 1, vec![0]);
+        let node_1 = BufferNode::new(BufferType::Original, 1, 1, vec![0]);
+        let node_2 = BufferNode::new(BufferType::Original, 2, 1, vec![0]);
+        let node_3 = BufferNode::new(BufferType::Added, 0, 1, vec![0]);
+        let node_4 = BufferNode::new(BufferType::Added, 1, 1, vec![0]);
+        let mut node_list = NodeList::with_contents(vec![node_0.clone(), node_1, node_2.clone()]);
+        node_list.move_right();
+        node_list.move_right();
+        node_list.splice(1, 1, vec![node_3.clone(), node_4.clone()]);
+        assert_eq!(node_list, vec![node_0.clone(), node_3, node_4, node_2]);
+        assert_eq!(node_list.index(), 0);
+    }
+
+    #[test]
+    fn splice_remove_everything() {
+        let node_0 = BufferNode::new(BufferType::Original, 0, 1, vec![0]);
+        let node_1 = BufferNode::new(BufferType::Original, 1, 1, vec![0]);
+        let mut node_list = NodeList::with_contents(vec![node_0, node_1]);
+        node_list.splice(0, 2, vec![]);
+        assert!(node_list.is_empty());
+    }
+
     #[test]
     fn at_head_on_empty() {
         let node_list = NodeList::new();
@@ -413,10 +442,8 @@ mod node_list_tests {
     #[should_panic]
     fn get_with_larger_idx() {
         let node_0 = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
-        let node_0_copy = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_1 = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
-        let node_1_copy = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
-        let mut node_list = NodeList::with_contents(vec![node_0, node_1]);
+        let node_list = NodeList::with_contents(vec![node_0, node_1]);
         let idx = node_list.len();
         node_list.get(idx);
     }
@@ -426,9 +453,7 @@ mod node_list_tests {
         let node_0 = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_0_copy = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_1 = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
-        let node_1_copy = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
-        let mut node_list = NodeList::with_contents(vec![node_0, node_1]);
-        let idx = node_list.len();
+        let node_list = NodeList::with_contents(vec![node_0, node_1]);
         assert_eq!(*node_list.get(0), node_0_copy);
     }
 
@@ -443,9 +468,7 @@ mod node_list_tests {
     #[should_panic]
     fn get_mut_with_larger_idx() {
         let node_0 = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
-        let node_0_copy = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_1 = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
-        let node_1_copy = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
         let mut node_list = NodeList::with_contents(vec![node_0, node_1]);
         let idx = node_list.len();
         node_list.get_mut(idx);
@@ -456,9 +479,7 @@ mod node_list_tests {
         let node_0 = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_0_copy = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_1 = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
-        let node_1_copy = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
         let mut node_list = NodeList::with_contents(vec![node_0, node_1]);
-        let idx = node_list.len();
         assert_eq!(*node_list.get_mut(0), node_0_copy);
     }
 
@@ -487,7 +508,6 @@ mod node_list_tests {
         let node_0 = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_0_copy = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_1 = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
-        let node_1_copy = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
         let node_list = NodeList::with_contents(vec![node_0, node_1]);
         let mut iter = node_list.iter_until_curr();
         assert_eq!(*iter.next().unwrap(), node_0_copy);
@@ -497,7 +517,6 @@ mod node_list_tests {
     #[test]
     fn get_iter_from_after_curr_on_nonempty_list() {
         let node_0 = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
-        let node_0_copy = BufferNode::new(BufferType::Original, 0, 0, vec![0]);
         let node_1 = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
         let node_1_copy = BufferNode::new(BufferType::Original, 1, 0, vec![0]);
         let node_list = NodeList::with_contents(vec![node_0, node_1]);
@@ -526,7 +545,7 @@ mod node_list_tests {
         node_list.move_right();
         node_list.move_right();
 
-        node_list.move_to_prev_newline();
+        node_list.move_to_prev_newline(|_| &[]);
         assert_eq!(node_list.index(), 1);
     }
 
@@ -538,7 +557,47 @@ mod node_list_tests {
 
         let mut node_list = NodeList::with_contents(vec![node_0, node_1, node_2]);
 
-        node_list.move_to_next_newline();
+        node_list.move_to_next_newline(|_| &[]);
         assert_eq!(node_list.index(), 1);
     }
+
+    #[test]
+    fn counted_node_finds_line_offsets_when_needed() {
+        let bytes = b"ab\ncd\nef";
+        let node = BufferNode::counted(BufferType::Original, 0, bytes.len(), 2);
+        assert_eq!(node.line_offsets_len(bytes), 3);
+        assert!(node.has_newline(bytes));
+        assert!(matches!(
+            &node.line_offsets,
+            LineOffsets::Lazy { found, .. } if found.get().is_none()
+        ));
+
+        assert_eq!(node.line_idx_at(bytes, 4), 1);
+        assert_eq!(node.line_offsets(bytes), &[0, 3, 6]);
+        assert_eq!(node.next_line_offset(bytes, 4), Some(6));
+        assert_eq!(node.last_line_offset(bytes), 6);
+
+        let (left, right) = node.split_at(bytes, 4);
+        assert_eq!(
+            left,
+            BufferNode::new(BufferType::Original, 0, 4, vec![0, 3])
+        );
+        assert_eq!(
+            right,
+            BufferNode::new(BufferType::Original, 4, 4, vec![0, 2])
+        );
+    }
+
+    #[test]
+    fn lazy_node_counts_lines_when_needed() {
+        let bytes = b"ab\ncd\nef";
+        let node = BufferNode::lazy(BufferType::Original, 0, bytes.len());
+        assert_eq!(node.newlines_if_counted(), None);
+        assert_eq!(node.line_offsets_len(bytes), 3);
+        assert_eq!(node.newlines_if_counted(), Some(2));
+
+        let node = BufferNode::lazy(BufferType::Original, 0, bytes.len());
+        assert_eq!(node.line_offsets(bytes), &[0, 3, 6]);
+        assert_eq!(node.newlines_if_counted(), Some(2));
+    }
 }
@@ -0,0 +1,140 @@
+#[cfg(test)]
+mod original_tests {
+    use super::super::*;
+    use std::fs;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tempfile::tempdir;
+
+    fn map_contents(contents: &[u8], min_len: u64) -> io::Result<Option<Original>> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        fs::write(&path, contents)?;
+        Original::map(&File::open(&path)?, min_len)
+    }
+
+    #[test]
+    fn maps_text_that_needs_no_converting() -> io::Result<()> {
+        let contents = "caf\u{e9}\nline\n".as_bytes();
+        let original = map_contents(contents, 0)?.unwrap();
+        assert!(original.is_mapped());
+        assert_eq!(original, contents.to_vec());
+        assert_eq!(original.checksum(), checksum(contents));
+        Ok(())
+    }
+
+    #[test]
+    fn reads_what_needs_converting() -> io::Result<()> {
+        assert!(map_contents(b"a\r\nb\r\n", 0)?.is_none());
+        assert!(map_contents(b"\xEF\xBB\xBFabc", 0)?.is_none());
+        assert!(map_contents(b"\xFF\xFEa\0", 0)?.is_none());
+        assert!(map_contents(b"caf\xE9", 0)?.is_none());
+        assert!(map_contents(b"", 0)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn reads_small_files() -> io::Result<()> {
+        assert!(map_contents(b"abc", 4)?.is_none());
+        assert!(map_contents(b"abcd", 4)?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn only_the_first_piece_is_checked_on_mapping() -> io::Result<()> {
+        let mut contents = "line\n".repeat(PIECE_LEN / 5 + 1).into_bytes();
+        let second = contents.len();
+        contents.extend_from_slice(b"a\x81b\r\n\xEF\xBF\xBD\n");
+        let original = map_contents(&contents, 0)?.unwrap();
+        assert_eq!(original.pieces(), vec![0..second, second..contents.len()]);
+        assert_eq!(original.piece_end(second - 1), second);
+
+        // Invalid bytes are marked without moving anything, and kept
+        let end = contents.len();
+        assert_eq!(original.bytes(second..end), "a?b\r\n\u{fffd}\n".as_bytes());
+        let runs = original.raw_bytes_within(0, end);
+        assert_eq!(runs.len(), 1);
+        assert_eq!((runs[0].offset, runs[0].len), (second + 1, 1));
+        assert_eq!(runs[0].bytes, b"\x81");
+
+        let read_in = original.read_in();
+        assert_eq!(read_in.bytes(second..end), original.bytes(second..end));
+        assert_eq!(read_in.raw_bytes_within(0, end), runs);
+        assert_eq!(read_in.checksum(), checksum(&contents));
+        Ok(())
+    }
+
+    #[test]
+    fn long_lines_are_split_between_characters() {
+        let contents = "\u{4E2D}".repeat(PIECE_LEN / 3 + PIECE_SLACK);
+        let original = Original::new(contents.clone().into_bytes());
+        let pieces = original.pieces();
+        assert_eq!(pieces.len(), 2);
+        assert!(contents.is_char_boundary(pieces[1].start));
+        assert!(pieces[0].len() - PIECE_LEN < 3);
+    }
+
+    #[test]
+    fn files_open_for_writing_are_read() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "abc")?;
+        let _writer = File::options().append(true).open(&path)?;
+        assert!(Original::map(&File::open(&path)?, 0)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn truncating_waits_until_read_in() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "abc\n")?;
+        let original = Original::map(&File::open(&path)?, 0)?.unwrap();
+        assert!(!original.lease_broken());
+
+        let truncating = path.clone();
+        let truncate = thread::spawn(move || File::create(truncating).map(|_| ()));
+        let start = Instant::now();
+        while !original.lease_broken() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        // Still waiting, so the file is as it was
+        assert_eq!(fs::metadata(&path)?.len(), 4);
+
+        let read_in = original.read_in();
+        drop(original);
+        truncate.join().unwrap()?;
+        assert_eq!(fs::metadata(&path)?.len(), 0);
+        assert_eq!(read_in, b"abc\n".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn knows_the_file_it_is_mapped_from() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        let other = dir.path().join("other.txt");
+        fs::write(&path, "abc")?;
+        fs::write(&other, "abc")?;
+        let original = Original::map(&File::open(&path)?, 0)?.unwrap();
+        assert!(original.is_mapped_from(&fs::metadata(&path)?));
+        assert!(!original.is_mapped_from(&fs::metadata(&other)?));
+
+        let read_in = original.read_in();
+        assert!(!read_in.is_mapped());
+        assert!(!read_in.is_mapped_from(&fs::metadata(&path)?));
+        assert_eq!(read_in, b"abc".to_vec());
+        assert_eq!(read_in.checksum(), original.checksum());
+        Ok(())
+    }
+
+    #[test]
+    fn debug_leaves_out_contents() {
+        let original = Original::new(b"abc".to_vec());
+        assert_eq!(
+            format!("{:?}", original),
+            "Original { len: 3, mapped: false }"
+        );
+    }
+}
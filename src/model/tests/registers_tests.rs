@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod registers_tests {
+    use super::super::*;
+
+    fn text(registers: &Registers, name: Option<char>) -> Option<&str> {
+        registers.get(name).map(|register| register.text.as_str())
+    }
+
+    #[test]
+    fn empty_registers() {
+        let registers = Registers::new();
+        assert_eq!(text(&registers, None), None);
+        assert_eq!(text(&registers, Some('a')), None);
+        assert_eq!(text(&registers, Some('0')), None);
+    }
+
+    #[test]
+    fn store_in_unnamed_register() {
+        let mut registers = Registers::new();
+        registers.store(None, Register::new(String::from("abc"), false));
+        assert_eq!(text(&registers, None), Some("abc"));
+        assert_eq!(text(&registers, Some('"')), Some("abc"));
+        assert_eq!(text(&registers, Some('a')), None);
+    }
+
+    #[test]
+    fn store_in_named_register() {
+        let mut registers = Registers::new();
+        registers.store(Some('a'), Register::new(String::from("one"), false));
+        registers.store(None, Register::new(String::from("two"), false));
+        assert_eq!(text(&registers, Some('a')), Some("one"));
+        assert_eq!(text(&registers, None), Some("two"));
+    }
+
+    #[test]
+    fn uppercase_name_appends() {
+        let mut registers = Registers::new();
+        registers.store(Some('a'), Register::new(String::from("one"), false));
+        registers.store(Some('A'), Register::new(String::from("two\n"), true));
+
+        let register = registers.get(Some('a')).unwrap();
+        assert_eq!(register.text, "onetwo\n");
+        assert!(register.linewise);
+        assert_eq!(text(&registers, None), Some("onetwo\n"));
+        // The ring only gets what was added each time
+        let ring: Vec<&str> = registers.ring.iter().map(|r| r.text.as_str()).collect();
+        assert_eq!(ring, vec!["two\n", "one"]);
+    }
+
+    #[test]
+    fn ring_entries_go_round() {
+        let mut registers = Registers::new();
+        assert_eq!(registers.ring_entry(0), None);
+        registers.store(None, Register::new(String::from("old"), false));
+        registers.store(None, Register::new(String::from("new"), false));
+        let entry = |index| registers.ring_entry(index).map(|r| r.text.as_str());
+        assert_eq!(entry(0), Some("new"));
+        assert_eq!(entry(1), Some("old"));
+        assert_eq!(entry(2), Some("new"));
+    }
+
+    #[test]
+    fn ring_keeps_latest_first() {
+        let mut registers = Registers::new();
+        for i in 0..RING_SIZE + 2 {
+            registers.store(None, Register::new(i.to_string(), false));
+        }
+
+        assert_eq!(registers.ring.len(), RING_SIZE);
+        assert_eq!(text(&registers, Some('0')), Some("11"));
+        assert_eq!(text(&registers, Some('1')), Some("10"));
+        assert_eq!(text(&registers, Some('9')), Some("2"));
+    }
+
+    #[test]
+    fn empty_text_is_not_stored() {
+        let mut registers = Registers::new();
+        registers.store(None, Register::new(String::from("abc"), false));
+        registers.store(None, Register::new(String::new(), false));
+        assert_eq!(text(&registers, None), Some("abc"));
+        assert_eq!(registers.ring.len(), 1);
+    }
+
+    #[test]
+    fn valid_names() {
+        assert!(Registers::is_valid_name('a'));
+        assert!(Registers::is_valid_name('Z'));
+        assert!(Registers::is_valid_name('3'));
+        assert!(Registers::is_valid_name('"'));
+        assert!(!Registers::is_valid_name('-'));
+    }
+}
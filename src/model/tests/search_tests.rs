@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod search_tests {
+    use super::super::*;
+
+    fn regex(pattern: &str) -> Regex {
+        compile(pattern, false).unwrap()
+    }
+
+    #[test]
+    fn find_matches_overlapping() {
+        assert_eq!(find_matches("abcabc", &regex("bc")), vec![(1, 3), (4, 6)]);
+        assert_eq!(find_matches("aaa", &regex("aa")), vec![(0, 2), (1, 3)]);
+        assert_eq!(find_matches("abc", &regex("x")), vec![]);
+    }
+
+    #[test]
+    fn find_matches_after_wide_chars() {
+        assert_eq!(
+            find_matches("\u{4E2D}\u{4E2D}x", &regex("\u{4E2D}")),
+            vec![(0, 3), (3, 6)]
+        );
+    }
+
+    #[test]
+    fn find_matches_with_regex() {
+        assert_eq!(
+            find_matches("a1 b22 c", &regex(r"[a-z]\d+")),
+            vec![(0, 2), (3, 6)]
+        );
+        assert_eq!(find_matches("ab\nab", &regex("^a")), vec![(0, 1), (3, 4)]);
+        assert_eq!(find_matches("ab\nab", &regex("b$")), vec![(1, 2), (4, 5)]);
+        assert_eq!(find_matches("", &regex("^")), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn compile_errors() {
+        assert!(compile("(", false).is_err());
+        assert_eq!(compile("a(", false).unwrap_err(), "Invalid pattern: a(");
+        assert_eq!(
+            find_matches("aA", &compile("a", true).unwrap()),
+            vec![(0, 1), (1, 2)]
+        );
+    }
+
+    #[test]
+    fn find_next_forward() {
+        let text = &Buffer::with_contents(String::from("foo bar foo"));
+        let found = find_next(text, &regex("foo"), 0, SearchDirection::Forward).unwrap();
+        assert_eq!((found.start, found.end, found.wrapped), (8, 11, false));
+
+        let found = find_next(text, &regex("foo"), 8, SearchDirection::Forward).unwrap();
+        assert_eq!((found.start, found.wrapped), (0, true));
+
+        assert_eq!(
+            find_next(text, &regex("baz"), 0, SearchDirection::Forward),
+            None
+        );
+    }
+
+    #[test]
+    fn find_next_backward() {
+        let text = &Buffer::with_contents(String::from("foo bar foo"));
+        let found = find_next(text, &regex("foo"), 8, SearchDirection::Backward).unwrap();
+        assert_eq!((found.start, found.wrapped), (0, false));
+
+        let found = find_next(text, &regex("foo"), 0, SearchDirection::Backward).unwrap();
+        assert_eq!((found.start, found.wrapped), (8, true));
+    }
+
+    #[test]
+    fn only_match_at_cursor_wraps_to_itself() {
+        let text = &Buffer::with_contents(String::from("a foo"));
+        let found = find_next(text, &regex("foo"), 2, SearchDirection::Forward).unwrap();
+        assert_eq!((found.start, found.wrapped), (2, true));
+    }
+
+    #[test]
+    fn find_next_across_blocks() {
+        // Lines of 100 bytes, with "foo" only at the start of line 1000 and
+        // no empty line at the end
+        let line = format!("{}\n", "x".repeat(99));
+        let mut contents = line.repeat(2000);
+        contents.replace_range(100_000..100_003, "foo");
+        contents.pop();
+        let text = &Buffer::with_contents(contents);
+
+        let found = find_next(text, &regex("foo"), 10, SearchDirection::Forward).unwrap();
+        assert_eq!((found.start, found.wrapped), (100_000, false));
+        let found = find_next(text, &regex("foo"), 150_000, SearchDirection::Forward).unwrap();
+        assert_eq!((found.start, found.wrapped), (100_000, true));
+        let found = find_next(text, &regex("foo"), 150_000, SearchDirection::Backward).unwrap();
+        assert_eq!((found.start, found.wrapped), (100_000, false));
+        let found = find_next(text, &regex("foo"), 10, SearchDirection::Backward).unwrap();
+        assert_eq!((found.start, found.wrapped), (100_000, true));
+        assert_eq!(
+            find_next(text, &regex("bar"), 10, SearchDirection::Backward),
+            None
+        );
+
+        // `$` at the end of a block isn't an empty line
+        assert_eq!(
+            find_next(text, &regex("^$"), 10, SearchDirection::Forward),
+            None
+        );
+    }
+
+    #[test]
+    fn reversed_direction() {
+        assert_eq!(
+            SearchDirection::Forward.reversed(),
+            SearchDirection::Backward
+        );
+        assert_eq!(
+            SearchDirection::Backward.reversed(),
+            SearchDirection::Forward
+        );
+    }
+
+    #[test]
+    fn expand_replacement_groups() {
+        let regex = regex(r"(\w+)=(\w+)");
+        let captures = regex.captures("key=value").unwrap();
+        assert_eq!(expand_replacement(r"\2=\1", &captures), "value=key");
+        assert_eq!(expand_replacement("[&]", &captures), "[key=value]");
+        assert_eq!(expand_replacement(r"\0 \& \\", &captures), r"key=value & \");
+        assert_eq!(expand_replacement(r"\1\r\2\t", &captures), "key\nvalue\t");
+        assert_eq!(expand_replacement(r"\5", &captures), "");
+    }
+
+    #[test]
+    fn replacements_per_line() {
+        let text = "a a\r\nb\na a";
+        let lines = [(0, 5), (5, 7), (7, 10)];
+        let found = replacements(text, &lines, &regex("a"), "x", false);
+        assert_eq!(
+            found
+                .iter()
+                .map(|found| (found.start, found.end, found.line))
+                .collect::<Vec<_>>(),
+            vec![(0, 1, 0), (7, 8, 2)]
+        );
+
+        let found = replacements(text, &lines, &regex("a"), "x", true);
+        assert_eq!(found.len(), 4);
+        assert_eq!(found[1].start, 2);
+
+        // `$` matches before the line break, "\r\n" included
+        let found = replacements(text, &lines, &regex("$"), ";", false);
+        assert_eq!(
+            found.iter().map(|found| found.start).collect::<Vec<_>>(),
+            vec![3, 6, 10]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+#[cfg(test)]
+mod swap_tests {
+    use super::super::*;
+    use crate::model::original::Original;
+    use std::fs::File;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    fn edited_buffer() -> Buffer {
+        let mut buffer = Buffer::with_contents(String::from("one\ntwo\n"));
+        buffer.move_cursor_down();
+        buffer.insert_str(String::from("2 "));
+        buffer.move_cursor_to(0);
+        buffer.insert_str(String::from("\u{4E2D}\n"));
+        buffer
+    }
+
+    // The buffer recovered from `swap` for a file holding `original`
+    fn recover(swap: &Swap, original: &str) -> Result<Buffer, String> {
+        let mut buffer = Buffer::with_contents(String::from(original));
+        swap.restore(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    #[test]
+    fn round_trip() {
+        let buffer = edited_buffer();
+        let swap = Swap::from_buffer(&buffer);
+        assert_eq!(swap.pid, process::id());
+        assert_eq!(swap.original_len, 8);
+
+        let recovered = recover(&swap, "one\ntwo\n").unwrap();
+        assert_eq!(recovered.as_str(), "\u{4E2D}\none\n2 two\n");
+        assert_eq!(recovered.num_lines(), 4);
+        assert_eq!(recovered.cursor_position(), buffer.cursor_position());
+        assert!(recovered.is_modified());
+    }
+
+    #[test]
+    fn restore_keeps_mapped_original() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "one\ntwo\n")?;
+        let file = File::open(&path)?;
+        let mut buffer = Buffer::with_original(Original::map(&file, 0)?.unwrap());
+
+        Swap::from_buffer(&edited_buffer())
+            .restore(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer.as_str(), "\u{4E2D}\none\n2 two\n");
+        assert!(buffer.is_mapped_from(&file.metadata()?));
+        Ok(())
+    }
+
+    #[test]
+    fn changed_file_is_not_recovered() {
+        let swap = Swap::from_buffer(&edited_buffer());
+        assert_eq!(
+            recover(&swap, "one\ntwo").unwrap_err(),
+            "File changed since the swap file was written"
+        );
+        assert!(recover(&swap, "one\ntwO\n").is_err());
+    }
+
+    #[test]
+    fn corrupt_pieces_are_not_recovered() {
+        let mut swap = Swap::from_buffer(&edited_buffer());
+        swap.pieces.push(Piece {
+            added: true,
+            start: 0,
+            len: 100,
+        });
+        assert_eq!(
+            recover(&swap, "one\ntwo\n").unwrap_err(),
+            "Swap file is corrupt"
+        );
+
+        // Half of a wide character
+        let mut swap = Swap::from_buffer(&edited_buffer());
+        swap.pieces = vec![Piece {
+            added: true,
+            start: 3,
+            len: 1,
+        }];
+        assert!(recover(&swap, "one\ntwo\n").is_err());
+    }
+
+    #[test]
+    fn empty_buffer_round_trip() {
+        let mut buffer = Buffer::with_contents(String::from("ab"));
+        buffer.move_cursor_to(2);
+        buffer.remove();
+        buffer.remove();
+
+        let recovered = recover(&Swap::from_buffer(&buffer), "ab").unwrap();
+        assert_eq!(recovered.as_str(), "");
+        assert_eq!(recovered.cursor_position(), 0);
+        assert!(recovered.is_modified());
+    }
+
+    #[test]
+    fn write_and_read() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join(".file.swp");
+        let swap = Swap::from_buffer(&edited_buffer());
+        swap.create(&path)?;
+        assert_eq!(Swap::read(&path)?, swap);
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        swap.write(&path)?;
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+
+        fs::write(&path, "{")?;
+        assert_eq!(
+            Swap::read(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn create_leaves_what_is_there_alone() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join(".file.swp");
+        let swap = Swap::from_buffer(&edited_buffer());
+        swap.create(&path)?;
+        let error = swap.create(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+
+        // Nor is a planted symlink followed
+        fs::remove_file(&path)?;
+        let victim = dir.path().join("victim.txt");
+        fs::write(&victim, "victim")?;
+        std::os::unix::fs::symlink(&victim, &path)?;
+        assert!(swap.create(&path).is_err());
+        assert_eq!(fs::read_to_string(&victim)?, "victim");
+        Ok(())
+    }
+
+    #[test]
+    fn write_replaces_a_symlink_rather_than_following_it() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join(".file.swp");
+        let victim = dir.path().join("victim.txt");
+        fs::write(&victim, "victim")?;
+        std::os::unix::fs::symlink(&victim, &path)?;
+
+        let swap = Swap::from_buffer(&edited_buffer());
+        swap.write(&path)?;
+        assert_eq!(fs::read_to_string(&victim)?, "victim");
+        assert!(fs::symlink_metadata(&path)?.file_type().is_file());
+        assert_eq!(Swap::read(&path)?, swap);
+        // Without leaving the temporary file behind
+        assert_eq!(fs::read_dir(dir.path())?.count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn owner_is_running() {
+        let mut swap = Swap::from_buffer(&edited_buffer());
+        // A swap file with this process's id was left by an earlier one
+        assert!(!swap.owner_is_running());
+        swap.pid = std::os::unix::process::parent_id();
+        assert!(swap.owner_is_running());
+        swap.pid = u32::MAX;
+        assert!(!swap.owner_is_running());
+    }
+
+    #[test]
+    fn swap_path_is_hidden_next_to_file() {
+        assert_eq!(
+            swap_path(Path::new("dir/file.txt")),
+            PathBuf::from("dir/.file.txt.swp")
+        );
+        assert_eq!(swap_path(Path::new("file")), PathBuf::from(".file.swp"));
+    }
+}
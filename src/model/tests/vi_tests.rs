@@ -0,0 +1,247 @@
+#[cfg(test)]
+mod vi_tests {
+    use super::super::*;
+
+    fn feed_keys(state: &mut ViState, keys: &str) -> Vec<ViCommand> {
+        keys.chars()
+            .filter_map(|c| state.feed(Key::Char(c), false))
+            .collect()
+    }
+
+    #[test]
+    fn motions_with_counts() {
+        let mut state = ViState::new();
+        assert_eq!(
+            feed_keys(&mut state, "w3b$0"),
+            vec![
+                ViCommand::Move(Motion::WordForward, None),
+                ViCommand::Move(Motion::WordBackward, Some(3)),
+                ViCommand::Move(Motion::LineEnd, None),
+                ViCommand::Move(Motion::LineStart, None),
+            ]
+        );
+        assert!(!state.is_pending());
+    }
+
+    #[test]
+    fn zero_after_a_count_is_a_digit() {
+        let mut state = ViState::new();
+        assert_eq!(
+            feed_keys(&mut state, "10j"),
+            vec![ViCommand::Move(Motion::Down, Some(10))]
+        );
+    }
+
+    #[test]
+    fn go_to_lines() {
+        let mut state = ViState::new();
+        assert_eq!(
+            feed_keys(&mut state, "gg5GG"),
+            vec![
+                ViCommand::Move(Motion::FirstLine, None),
+                ViCommand::Move(Motion::LastLine, Some(5)),
+                ViCommand::Move(Motion::LastLine, None),
+            ]
+        );
+
+        assert_eq!(feed_keys(&mut state, "gx"), vec![]);
+        assert!(!state.is_pending());
+    }
+
+    #[test]
+    fn operators_with_motions() {
+        let mut state = ViState::new();
+        assert_eq!(
+            feed_keys(&mut state, "dw2c3eyG"),
+            vec![
+                ViCommand::Operate(Operator::Delete, Motion::WordForward, None),
+                ViCommand::Operate(Operator::Change, Motion::WordEnd, Some(6)),
+                ViCommand::Operate(Operator::Yank, Motion::LastLine, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn doubled_operators_act_on_lines() {
+        let mut state = ViState::new();
+        assert_eq!(
+            feed_keys(&mut state, "dd3yy2c2c"),
+            vec![
+                ViCommand::OperateLines(Operator::Delete, 1),
+                ViCommand::OperateLines(Operator::Yank, 3),
+                ViCommand::OperateLines(Operator::Change, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_keys_after_operator_are_dropped() {
+        let mut state = ViState::new();
+        assert_eq!(feed_keys(&mut state, "dy"), vec![]);
+        assert!(!state.is_pending());
+        assert_eq!(feed_keys(&mut state, "di"), vec![]);
+        assert_eq!(
+            feed_keys(&mut state, "i"),
+            vec![ViCommand::Insert(InsertAt::Cursor)]
+        );
+    }
+
+    #[test]
+    fn escape_cancels_pending_keys() {
+        let mut state = ViState::new();
+        feed_keys(&mut state, "3d");
+        assert!(state.is_pending());
+        assert_eq!(state.feed(Key::Esc, false), Some(ViCommand::Escape));
+        assert!(!state.is_pending());
+    }
+
+    #[test]
+    fn operators_in_visual_mode() {
+        let mut state = ViState::new();
+        assert_eq!(
+            state.feed(Key::Char('d'), true),
+            Some(ViCommand::OperateSelection(Operator::Delete))
+        );
+        assert_eq!(
+            state.feed(Key::Char('x'), true),
+            Some(ViCommand::OperateSelection(Operator::Delete))
+        );
+        assert_eq!(
+            state.feed(Key::Char('x'), false),
+            Some(ViCommand::DeleteChar(1))
+        );
+    }
+
+    #[test]
+    fn word_motions() {
+        let text = "foo.bar  baz\n  qux";
+        assert_eq!(next_word_start(text, 0), 3);
+        assert_eq!(next_word_start(text, 3), 4);
+        assert_eq!(next_word_start(text, 4), 9);
+        assert_eq!(next_word_start(text, 9), 15);
+        assert_eq!(next_word_start(text, 15), text.len());
+
+        assert_eq!(prev_word_start(text, 15), 9);
+        assert_eq!(prev_word_start(text, 10), 9);
+        assert_eq!(prev_word_start(text, 9), 4);
+        assert_eq!(prev_word_start(text, 2), 0);
+
+        assert_eq!(word_end(text, 0), 2);
+        assert_eq!(word_end(text, 2), 3);
+        assert_eq!(word_end(text, 6), 11);
+        assert_eq!(word_end(text, 17), 17);
+    }
+
+    #[test]
+    fn word_motions_over_wide_chars() {
+        let text = "\u{4E2D}\u{6587} ab";
+        assert_eq!(next_word_start(text, 0), 7);
+        assert_eq!(word_end(text, 0), 3);
+        assert_eq!(prev_word_start(text, 7), 0);
+    }
+
+    #[test]
+    fn word_motions_across_lines() {
+        let mut buffer = Buffer::with_contents(String::from("ab\n\n \n  cd.\nef"));
+        let mut target = |position, motion| {
+            buffer.move_cursor_to(position);
+            motion_target(&buffer, motion, None)
+        };
+        assert_eq!(target(0, Motion::WordForward), 8);
+        assert_eq!(target(8, Motion::WordForward), 10);
+        assert_eq!(target(10, Motion::WordForward), 12);
+        assert_eq!(target(12, Motion::WordForward), 14);
+        assert_eq!(target(8, Motion::WordBackward), 0);
+        assert_eq!(target(12, Motion::WordBackward), 10);
+        assert_eq!(target(1, Motion::WordEnd), 9);
+        assert_eq!(target(10, Motion::WordEnd), 13);
+        assert_eq!(target(13, Motion::WordEnd), 13);
+    }
+
+    #[test]
+    fn ranges_for_operators() {
+        let mut buffer = Buffer::with_contents(String::from("one two\nthree"));
+        buffer.move_cursor_to(4);
+
+        let range =
+            |buffer: &Buffer, operator, motion| motion_range(buffer, operator, motion, None);
+        assert_eq!(
+            range(&buffer, Operator::Delete, Motion::WordForward),
+            (4, 7)
+        );
+        assert_eq!(
+            range(&buffer, Operator::Change, Motion::WordForward),
+            (4, 7)
+        );
+        assert_eq!(
+            range(&buffer, Operator::Delete, Motion::WordBackward),
+            (0, 4)
+        );
+        assert_eq!(range(&buffer, Operator::Delete, Motion::LineEnd), (4, 7));
+        assert_eq!(range(&buffer, Operator::Delete, Motion::Down), (0, 13));
+
+        buffer.move_cursor_to(0);
+        assert_eq!(
+            range(&buffer, Operator::Delete, Motion::WordForward),
+            (0, 4)
+        );
+        assert_eq!(
+            range(&buffer, Operator::Change, Motion::WordForward),
+            (0, 3)
+        );
+    }
+
+    #[test]
+    fn ranges_for_whole_lines() {
+        let mut buffer = Buffer::with_contents(String::from("a\nb\nc"));
+        assert_eq!(count_lines_range(&buffer, Operator::Delete, 2), (0, 4));
+        assert_eq!(count_lines_range(&buffer, Operator::Change, 2), (0, 3));
+
+        // The last line has no line break of its own to take
+        buffer.move_cursor_to(4);
+        assert_eq!(count_lines_range(&buffer, Operator::Delete, 1), (3, 5));
+        assert_eq!(count_lines_range(&buffer, Operator::Yank, 1), (4, 5));
+    }
+
+    #[test]
+    fn register_prefix() {
+        let mut state = ViState::new();
+        assert_eq!(
+            feed_keys(&mut state, "\"add"),
+            vec![ViCommand::OperateLines(Operator::Delete, 1)]
+        );
+        assert_eq!(state.command_register(), Some('a'));
+
+        assert_eq!(
+            feed_keys(&mut state, "2p"),
+            vec![ViCommand::Paste {
+                before: false,
+                count: 2
+            }]
+        );
+        assert_eq!(state.command_register(), None);
+
+        assert_eq!(feed_keys(&mut state, "\"-"), vec![]);
+        assert!(!state.is_pending());
+    }
+
+    #[test]
+    fn search_commands() {
+        let mut state = ViState::new();
+        assert_eq!(
+            feed_keys(&mut state, "/?n3N"),
+            vec![
+                ViCommand::Search(SearchDirection::Forward),
+                ViCommand::Search(SearchDirection::Backward),
+                ViCommand::SearchNext {
+                    reverse: false,
+                    count: 1
+                },
+                ViCommand::SearchNext {
+                    reverse: true,
+                    count: 3
+                },
+            ]
+        );
+    }
+}
@@ -11,6 +11,8 @@ pub struct TextTag {
 pub enum Tag {
     Cursor,
     Highlighted,
+    // A match of the search pattern
+    Match,
 }
 
 impl TextTag {
@@ -0,0 +1,543 @@
+use termion::event::Key;
+
+use super::buffer::Buffer;
+use super::registers::Registers;
+use super::search::SearchDirection;
+
+// Vi style Normal and Visual mode. Keys are fed one at a time to a
+// `ViState`, which collects counts, operators and motions until they add
+// up to a `ViCommand` for the app to carry out.
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    // Start of the next word
+    WordForward,
+    // Start of the current or previous word
+    WordBackward,
+    // Last char of the current or next word
+    WordEnd,
+    LineStart,
+    LineEnd,
+    // `gg`, or line `count` if one is given
+    FirstLine,
+    // `G`, or line `count` if one is given
+    LastLine,
+}
+
+impl Motion {
+    // Whether an operator with this motion acts on whole lines
+    pub fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine
+        )
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+// Where `i`, `a`, `I`, `A`, `o` and `O` start inserting
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum InsertAt {
+    Cursor,
+    AfterCursor,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ViCommand {
+    Move(Motion, Option<usize>),
+    // An operator followed by a motion, e.g. `d3w`
+    Operate(Operator, Motion, Option<usize>),
+    // A doubled operator such as `dd` acting on whole lines
+    OperateLines(Operator, usize),
+    // An operator applied to the Visual mode selection
+    OperateSelection(Operator),
+    DeleteChar(usize),
+    // `p` pastes after the cursor and `P` before it
+    Paste { before: bool, count: usize },
+    Insert(InsertAt),
+    ToggleVisual,
+    Escape,
+    Undo(usize),
+    Redo(usize),
+    CommandLine,
+    // `/` and `?`
+    Search(SearchDirection),
+    // `n` repeats the last search and `N` repeats it the other way
+    SearchNext { reverse: bool, count: usize },
+}
+
+#[derive(Default, Debug)]
+pub struct ViState {
+    count: Option<usize>,
+    // Operator waiting for its motion, with the count typed before it
+    operator: Option<(Operator, Option<usize>)>,
+    // Whether a `g` was typed, waiting for the second `g` of `gg`
+    pending_g: bool,
+    // Register named with `"x` for the command being typed
+    register: Option<char>,
+    // Whether a `"` was typed, waiting for the register name
+    pending_register: bool,
+    // Register named for the command `feed` last returned
+    command_register: Option<char>,
+}
+
+impl ViState {
+    pub fn new() -> ViState {
+        ViState::default()
+    }
+
+    // Whether keys typed so far are waiting for more to complete them
+    #[cfg(test)]
+    pub fn is_pending(&self) -> bool {
+        self.count.is_some()
+            || self.operator.is_some()
+            || self.pending_g
+            || self.register.is_some()
+            || self.pending_register
+    }
+
+    pub fn reset(&mut self) {
+        *self = ViState {
+            command_register: self.command_register,
+            ..ViState::default()
+        };
+    }
+
+    // Register the last command returned by `feed` should use, if one was
+    // named with `"x` before it
+    pub fn command_register(&self) -> Option<char> {
+        self.command_register
+    }
+
+    // Feeds the next key press. Returns a command once the keys so far make
+    // one up; a key that makes no sense where it is typed drops them all.
+    pub fn feed(&mut self, key: Key, visual: bool) -> Option<ViCommand> {
+        let register = self.register;
+        let command = self.parse(key, visual);
+        if command.is_some() {
+            self.command_register = register;
+        }
+        command
+    }
+
+    fn parse(&mut self, key: Key, visual: bool) -> Option<ViCommand> {
+        if self.pending_register {
+            self.pending_register = false;
+            match key {
+                Key::Char(name) if Registers::is_valid_name(name) => self.register = Some(name),
+                _ => self.reset(),
+            }
+            return None;
+        }
+
+        if self.pending_g {
+            self.pending_g = false;
+            if key == Key::Char('g') {
+                return self.finish_motion(Motion::FirstLine);
+            }
+            self.reset();
+            return None;
+        }
+
+        match key {
+            // A leading `0` is the motion to the start of the line
+            Key::Char(c @ '0'..='9') if c != '0' || self.count.is_some() => {
+                let digit = c.to_digit(10).unwrap() as usize;
+                let count = self.count.unwrap_or(0);
+                self.count = Some(count.saturating_mul(10).saturating_add(digit));
+                return None;
+            }
+            Key::Esc => {
+                self.reset();
+                return Some(ViCommand::Escape);
+            }
+            Key::Char('g') => {
+                self.pending_g = true;
+                return None;
+            }
+            Key::Char('"') if self.operator.is_none() => {
+                self.pending_register = true;
+                return None;
+            }
+            _ => {}
+        }
+
+        if let Some(motion) = motion_for(key) {
+            return self.finish_motion(motion);
+        }
+
+        if let Some(operator) = operator_for(key, visual) {
+            if visual {
+                self.reset();
+                return Some(ViCommand::OperateSelection(operator));
+            }
+            return match self.operator.take() {
+                Some((pending, count)) if pending == operator => {
+                    let count = multiply_counts(count, self.count.take()).unwrap_or(1);
+                    self.reset();
+                    Some(ViCommand::OperateLines(operator, count))
+                }
+                Some(_) => {
+                    self.reset();
+                    None
+                }
+                None => {
+                    self.operator = Some((operator, self.count.take()));
+                    None
+                }
+            };
+        }
+
+        // Everything else is complete on its own and can't follow an operator
+        let pending_operator = self.operator.is_some();
+        let count = self.count.unwrap_or(1);
+        self.reset();
+        if pending_operator {
+            return None;
+        }
+
+        match key {
+            Key::Char('x') | Key::Delete => Some(ViCommand::DeleteChar(count)),
+            Key::Char('p') => Some(ViCommand::Paste {
+                before: false,
+                count,
+            }),
+            Key::Char('P') => Some(ViCommand::Paste {
+                before: true,
+                count,
+            }),
+            Key::Char('i') | Key::Insert => Some(ViCommand::Insert(InsertAt::Cursor)),
+            Key::Char('a') => Some(ViCommand::Insert(InsertAt::AfterCursor)),
+            Key::Char('I') => Some(ViCommand::Insert(InsertAt::LineStart)),
+            Key::Char('A') => Some(ViCommand::Insert(InsertAt::LineEnd)),
+            Key::Char('o') => Some(ViCommand::Insert(InsertAt::LineBelow)),
+            Key::Char('O') => Some(ViCommand::Insert(InsertAt::LineAbove)),
+            Key::Char('v') => Some(ViCommand::ToggleVisual),
+            Key::Char('u') => Some(ViCommand::Undo(count)),
+            Key::Ctrl('r') => Some(ViCommand::Redo(count)),
+            Key::Char(':') => Some(ViCommand::CommandLine),
+            Key::Char('/') => Some(ViCommand::Search(SearchDirection::Forward)),
+            Key::Char('?') => Some(ViCommand::Search(SearchDirection::Backward)),
+            Key::Char('n') => Some(ViCommand::SearchNext {
+                reverse: false,
+                count,
+            }),
+            Key::Char('N') => Some(ViCommand::SearchNext {
+                reverse: true,
+                count,
+            }),
+            _ => None,
+        }
+    }
+
+    fn finish_motion(&mut self, motion: Motion) -> Option<ViCommand> {
+        let count = self.count.take();
+        let command = match self.operator.take() {
+            Some((operator, operator_count)) => {
+                ViCommand::Operate(operator, motion, multiply_counts(operator_count, count))
+            }
+            None => ViCommand::Move(motion, count),
+        };
+        self.reset();
+        Some(command)
+    }
+}
+
+// `2d3w` deletes six words
+fn multiply_counts(first: Option<usize>, second: Option<usize>) -> Option<usize> {
+    match (first, second) {
+        (None, None) => None,
+        (first, second) => Some(first.unwrap_or(1).saturating_mul(second.unwrap_or(1))),
+    }
+}
+
+fn motion_for(key: Key) -> Option<Motion> {
+    match key {
+        Key::Char('h') | Key::Left | Key::Backspace => Some(Motion::Left),
+        Key::Char('l') | Key::Right | Key::Char(' ') => Some(Motion::Right),
+        Key::Char('k') | Key::Up => Some(Motion::Up),
+        Key::Char('j') | Key::Down | Key::Char('\n') => Some(Motion::Down),
+        Key::Char('w') => Some(Motion::WordForward),
+        Key::Char('b') => Some(Motion::WordBackward),
+        Key::Char('e') => Some(Motion::WordEnd),
+        Key::Char('0') | Key::Home => Some(Motion::LineStart),
+        Key::Char('$') | Key::End => Some(Motion::LineEnd),
+        Key::Char('G') => Some(Motion::LastLine),
+        _ => None,
+    }
+}
+
+fn operator_for(key: Key, visual: bool) -> Option<Operator> {
+    match key {
+        Key::Char('d') => Some(Operator::Delete),
+        Key::Char('c') => Some(Operator::Change),
+        Key::Char('y') => Some(Operator::Yank),
+        Key::Char('x') | Key::Delete if visual => Some(Operator::Delete),
+        _ => None,
+    }
+}
+
+// Absolute offset `motion` moves the cursor to. Up and down are left to
+// the buffer so that the cursor keeps its screen column.
+pub fn motion_target(buffer: &Buffer, motion: Motion, count: Option<usize>) -> usize {
+    let position = buffer.cursor_position();
+    let times = count.unwrap_or(1);
+    match motion {
+        Motion::Left => {
+            let (line_start, _) = buffer.line_bounds(position);
+            let mut target = position;
+            for _ in 0..times {
+                if target <= line_start {
+                    break;
+                }
+                target -= buffer.grapheme_len_before(target);
+            }
+            target
+        }
+        Motion::Right => {
+            let line_end = line_content_end(buffer, position);
+            let mut target = position;
+            for _ in 0..times {
+                if target >= line_end {
+                    break;
+                }
+                target += buffer.grapheme_len_after(target);
+            }
+            target
+        }
+        Motion::WordForward | Motion::WordBackward | Motion::WordEnd => {
+            (0..times).fold(position, |target, _| word_target(buffer, motion, target))
+        }
+        Motion::LineStart => buffer.line_bounds(position).0,
+        Motion::LineEnd => line_content_end(buffer, position),
+        Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine => {
+            buffer.offset_of_line(target_line(buffer, motion, count).unwrap())
+        }
+    }
+}
+
+// Start and end offsets of the text an operator followed by `motion`
+// acts on. Motions between lines take whole lines with them.
+pub fn motion_range(
+    buffer: &Buffer,
+    operator: Operator,
+    motion: Motion,
+    count: Option<usize>,
+) -> (usize, usize) {
+    let position = buffer.cursor_position();
+    if let Some(line) = target_line(buffer, motion, count) {
+        let current_line = buffer.current_line();
+        return lines_range(
+            buffer,
+            operator,
+            current_line.min(line),
+            current_line.max(line),
+        );
+    }
+
+    // `cw` changes up to the end of the word like `ce`, leaving the space
+    // after it alone
+    let motion = match (operator, motion) {
+        (Operator::Change, Motion::WordForward) => Motion::WordEnd,
+        _ => motion,
+    };
+    let target = motion_target(buffer, motion, count);
+    let (start, mut end) = (position.min(target), position.max(target));
+    match motion {
+        Motion::WordEnd => end += buffer.grapheme_len_after(end),
+        // A word motion that runs onto the next line stops at the end of
+        // this one
+        Motion::WordForward => {
+            let line_end = line_content_end(buffer, position);
+            if line_end > start {
+                end = end.min(line_end);
+            }
+        }
+        _ => {}
+    }
+    (start, end)
+}
+
+// Range covering lines `first` through `last`. Changing them keeps the
+// final line break; deleting the last line takes the break before it.
+pub fn lines_range(
+    buffer: &Buffer,
+    operator: Operator,
+    first: usize,
+    last: usize,
+) -> (usize, usize) {
+    let mut start = buffer.offset_of_line(first);
+    let last_start = buffer.offset_of_line(last);
+    if operator == Operator::Change {
+        return (start, line_content_end(buffer, last_start));
+    }
+
+    let end = buffer.offset_of_line(last + 1);
+    let ends_with_break = buffer.text_between(start, end).ends_with('\n');
+    if operator == Operator::Delete && !ends_with_break && first > 0 {
+        start = line_content_end(buffer, buffer.offset_of_line(first - 1));
+    }
+    (start, end)
+}
+
+// Range for a doubled operator such as `3dd`, starting at the cursor line
+pub fn count_lines_range(buffer: &Buffer, operator: Operator, count: usize) -> (usize, usize) {
+    let first = buffer.current_line();
+    let last = (first + count.max(1) - 1).min(buffer.num_lines() - 1);
+    lines_range(buffer, operator, first, last)
+}
+
+// Range for `x`, which never takes the line break with it
+pub fn chars_range(buffer: &Buffer, count: usize) -> (usize, usize) {
+    let position = buffer.cursor_position();
+    (position, motion_target(buffer, Motion::Right, Some(count)))
+}
+
+// Offset of the line break ending the line that `pos` is on, or the end of
+// the text on the last line
+pub fn line_content_end(buffer: &Buffer, pos: usize) -> usize {
+    let (line_start, next_line_start) = buffer.line_bounds(pos);
+    let line = buffer.text_between(line_start, next_line_start);
+    let content = line.strip_suffix('\n').unwrap_or(&line);
+    let content = content.strip_suffix('\r').unwrap_or(content);
+    line_start + content.len()
+}
+
+// Where a single word motion from `pos` lands. Only the line `pos` is on
+// is read at first, taking in the lines before or after it one at a time
+// while the answer could lie beyond them.
+fn word_target(buffer: &Buffer, motion: Motion, pos: usize) -> usize {
+    let len = buffer.len();
+    let (mut start, mut end) = buffer.line_bounds(pos);
+    loop {
+        let text = buffer.text_between(start, end);
+        let at = pos - start;
+        let (target, found) = match motion {
+            Motion::WordBackward => {
+                let target = prev_word_start(&text, at);
+                (target, target > 0 || start == 0)
+            }
+            Motion::WordForward => {
+                let target = next_word_start(&text, at);
+                (target, target < text.len() || end == len)
+            }
+            _ => {
+                // The word may go on past the end of the text read so far
+                let target = word_end(&text, at);
+                let word_end = target + text[target..].chars().next().map_or(0, char::len_utf8);
+                (target, (target > at && word_end < text.len()) || end == len)
+            }
+        };
+        if found {
+            return start + target;
+        }
+        if motion == Motion::WordBackward {
+            start = buffer.line_bounds(start - 1).0;
+        } else {
+            end = buffer.line_bounds(end).1;
+        }
+    }
+}
+
+// Line that a motion between lines ends up on
+fn target_line(buffer: &Buffer, motion: Motion, count: Option<usize>) -> Option<usize> {
+    let current_line = buffer.current_line();
+    let last_line = buffer.num_lines() - 1;
+    let times = count.unwrap_or(1);
+    let line = match motion {
+        Motion::Up => current_line.saturating_sub(times),
+        Motion::Down => current_line.saturating_add(times),
+        Motion::FirstLine => count.map_or(0, |line| line.saturating_sub(1)),
+        Motion::LastLine => count.map_or(last_line, |line| line.saturating_sub(1)),
+        _ => return None,
+    };
+    Some(line.min(last_line))
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum CharClass {
+    Space,
+    Word,
+    Punctuation,
+}
+
+fn char_class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Space
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+// Words are runs of word chars or runs of punctuation, so `foo.bar` is
+// three words
+fn next_word_start(text: &str, pos: usize) -> usize {
+    let mut chars = text[pos..].char_indices().peekable();
+    if let Some(&(_, c)) = chars.peek() {
+        let class = char_class(c);
+        if class != CharClass::Space {
+            while chars.next_if(|&(_, c)| char_class(c) == class).is_some() {}
+        }
+    }
+    while chars
+        .next_if(|&(_, c)| char_class(c) == CharClass::Space)
+        .is_some()
+    {}
+    chars.peek().map_or(text.len(), |&(idx, _)| pos + idx)
+}
+
+fn prev_word_start(text: &str, pos: usize) -> usize {
+    let mut chars = text[..pos].char_indices().rev().peekable();
+    while chars
+        .next_if(|&(_, c)| char_class(c) == CharClass::Space)
+        .is_some()
+    {}
+
+    let class = match chars.peek() {
+        Some(&(_, c)) => char_class(c),
+        None => return 0,
+    };
+    let mut start = pos;
+    while let Some((idx, _)) = chars.next_if(|&(_, c)| char_class(c) == class) {
+        start = idx;
+    }
+    start
+}
+
+fn word_end(text: &str, pos: usize) -> usize {
+    let mut chars = text[pos..].char_indices().skip(1).peekable();
+    while chars
+        .next_if(|&(_, c)| char_class(c) == CharClass::Space)
+        .is_some()
+    {}
+
+    let class = match chars.peek() {
+        Some(&(_, c)) => char_class(c),
+        None => return pos,
+    };
+    let mut end = pos;
+    while let Some((idx, _)) = chars.next_if(|&(_, c)| char_class(c) == class) {
+        end = pos + idx;
+    }
+    end
+}
+
+#[cfg(test)]
+#[path = "tests/vi_tests.rs"]
+mod tests;
@@ -0,0 +1,127 @@
+use std::io::{self, Write};
+use std::mem;
+use std::process::{Command, Stdio};
+
+// The host's clipboard, which yanked and cut text is shared with
+pub trait Clipboard {
+    fn copy(&mut self, text: &str) -> io::Result<()>;
+    // Text currently on the clipboard, or None if it can't be read back
+    fn paste(&mut self) -> io::Result<Option<String>>;
+    // Escape sequences still to be written to the terminal
+    fn take_terminal_output(&mut self) -> String {
+        String::new()
+    }
+}
+
+// Sets the clipboard of whatever terminal the editor is drawn in with an
+// OSC 52 escape sequence, which also works over SSH. The sequences wait
+// here for the view to write them through the terminal backend, so they
+// never land in the middle of a frame. Terminals rarely let programs read
+// the clipboard back, so pasting is left to the registers.
+#[derive(Default)]
+pub struct Osc52Clipboard {
+    queued: String,
+}
+
+impl Osc52Clipboard {
+    pub fn new() -> Osc52Clipboard {
+        Osc52Clipboard::default()
+    }
+}
+
+// "ESC ] 52 ; c ; <base64 text> BEL" sets the system clipboard
+pub fn osc52_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64::encode(text))
+}
+
+impl Clipboard for Osc52Clipboard {
+    fn copy(&mut self, text: &str) -> io::Result<()> {
+        self.queued.push_str(&osc52_sequence(text));
+        Ok(())
+    }
+
+    fn paste(&mut self) -> io::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn take_terminal_output(&mut self) -> String {
+        mem::take(&mut self.queued)
+    }
+}
+
+// Pipes text to and from helper programs such as `xclip` or `wl-copy`.
+// Commands are split on whitespace; there is no shell quoting.
+#[derive(Debug)]
+pub struct CommandClipboard {
+    copy_command: Vec<String>,
+    paste_command: Option<Vec<String>>,
+}
+
+impl CommandClipboard {
+    // Takes a copy command and an optional paste command separated by a
+    // comma, e.g. "xclip -selection clipboard,xclip -selection clipboard -o"
+    pub fn from_spec(spec: &str) -> Option<CommandClipboard> {
+        let (copy, paste) = match spec.split_once(',') {
+            Some((copy, paste)) => (copy, Some(paste)),
+            None => (spec, None),
+        };
+        let split = |command: &str| -> Vec<String> {
+            command.split_whitespace().map(String::from).collect()
+        };
+
+        let copy_command = split(copy);
+        if copy_command.is_empty() {
+            return None;
+        }
+        Some(CommandClipboard {
+            copy_command,
+            paste_command: paste.map(split).filter(|command| !command.is_empty()),
+        })
+    }
+}
+
+impl Clipboard for CommandClipboard {
+    fn copy(&mut self, text: &str) -> io::Result<()> {
+        let mut child = Command::new(&self.copy_command[0])
+            .args(&self.copy_command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // Dropping stdin closes it so that the helper sees the end of the text
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(command_failed(&self.copy_command[0], status));
+        }
+        Ok(())
+    }
+
+    fn paste(&mut self) -> io::Result<Option<String>> {
+        let paste_command = match &self.paste_command {
+            Some(paste_command) => paste_command,
+            None => return Ok(None),
+        };
+
+        let output = Command::new(&paste_command[0])
+            .args(&paste_command[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            return Err(command_failed(&paste_command[0], output.status));
+        }
+        Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+}
+
+fn command_failed(program: &str, status: std::process::ExitStatus) -> io::Error {
+    io::Error::other(format!("{} {}", program, status))
+}
+
+#[cfg(test)]
+#[path = "tests/clipboard_tests.rs"]
+mod tests;
@@ -7,19 +7,19 @@ use std::sync::{
 use std::thread;
 use std::time::{Duration, SystemTime};
 
-use termion::event::Key;
+use termion::event::{Event as TermEvent, Key};
 use termion::input::TermRead;
 
 pub enum Event {
     Tick { time: SystemTime },
     Input { key: Key, time: SystemTime },
+    // An arrow key pressed with shift held down
+    ShiftedInput { key: Key },
 }
 
 pub struct Events {
     rx: mpsc::Receiver<Event>,
-    input_handle: thread::JoinHandle<()>,
     ignore_exit_key: Arc<AtomicBool>,
-    tick_handle: thread::JoinHandle<()>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -47,42 +47,38 @@ impl Events {
         let (tx, rx) = mpsc::channel();
         let ignore_exit_key = Arc::new(AtomicBool::new(false));
 
-        let input_handle = {
+        // Both threads run for as long as the editor does
+        {
             let tx = tx.clone();
             let ignore_exit_key = ignore_exit_key.clone();
-            thread::spawn(move || Events::input_thread(ignore_exit_key, tx, config.exit_key))
-        };
-
-        let tick_handle = {
-            let tx = tx.clone();
-            thread::spawn(move || Events::tick_thread(tx, config.tick_rate))
-        };
+            thread::spawn(move || Events::input_thread(ignore_exit_key, tx, config.exit_key));
+        }
+        thread::spawn(move || Events::tick_thread(tx, config.tick_rate));
         Events {
             rx,
             ignore_exit_key,
-            input_handle,
-            tick_handle,
         }
     }
 
     fn input_thread(ignore_exit_key: Arc<AtomicBool>, tx: Sender<Event>, exit_key: Key) {
         let stdin = io::stdin();
-        for evt in stdin.keys() {
-            match evt {
-                Ok(key) => {
-                    let to_send = Event::Input {
-                        key: key,
-                        time: SystemTime::now(),
-                    };
+        for event in stdin.events().flatten() {
+            let time = SystemTime::now();
+            let to_send = match event {
+                TermEvent::Key(key) => Event::Input { key, time },
+                TermEvent::Unsupported(bytes) => match shifted_key(&bytes) {
+                    Some(key) => Event::ShiftedInput { key },
+                    None => continue,
+                },
+                TermEvent::Mouse(_) => continue,
+            };
+            let is_exit_key = matches!(to_send, Event::Input { key, .. } if key == exit_key);
 
-                    if let Err(_) = tx.send(to_send) {
-                        return;
-                    }
-                    if !ignore_exit_key.load(Ordering::Relaxed) && key == exit_key {
-                        return;
-                    }
-                }
-                Err(_) => {}
+            if tx.send(to_send).is_err() {
+                return;
+            }
+            if !ignore_exit_key.load(Ordering::Relaxed) && is_exit_key {
+                return;
             }
         }
     }
@@ -110,3 +106,15 @@ impl Events {
         self.ignore_exit_key.store(false, Ordering::Relaxed);
     }
 }
+
+// termion passes xterm's shift+arrow sequences such as "\x1b[1;2D" through
+// unparsed
+fn shifted_key(bytes: &[u8]) -> Option<Key> {
+    match bytes {
+        b"\x1b[1;2A" => Some(Key::Up),
+        b"\x1b[1;2B" => Some(Key::Down),
+        b"\x1b[1;2C" => Some(Key::Right),
+        b"\x1b[1;2D" => Some(Key::Left),
+        _ => None,
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+use termion::event::Key;
+
+// Editor actions that keys can be bound to. In the config file these are
+// written in snake_case, e.g. "open_file".
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Save,
+    SaveAs,
+    OpenFile,
+    CommandLine,
+    NormalMode,
+    SubmitCommand,
+    CancelCommand,
+    Undo,
+    Redo,
+    DeleteBack,
+    Cut,
+    Copy,
+    Paste,
+    // Swaps the text just pasted for the yank before it
+    PastePrevious,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    SelectLeft,
+    SelectRight,
+    SelectUp,
+    SelectDown,
+    AddCursorBelow,
+    AddCursorAtNextMatch,
+    SearchForward,
+    SearchBackward,
+    SearchNext,
+    SearchPrevious,
+    PageUp,
+    PageDown,
+}
+
+// Which set of bindings applies
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum KeymapMode {
+    Edit,
+    // Edit mode with modal editing on, i.e. vi's Insert mode
+    Insert,
+    Command,
+}
+
+// Outcome of feeding a key to the keymap
+#[derive(Clone, PartialEq, Debug)]
+pub enum KeyResult {
+    Action(Action),
+    // The keys so far are the start of a longer binding
+    Pending,
+    // No binding matched; these keys should be handled as plain input
+    Unbound(Vec<Key>),
+    // The keys so far went on past a binding without completing a longer
+    // one. That binding's action is taken, and the keys after it are to be
+    // fed again.
+    ActionThen(Action, Vec<Key>),
+}
+
+// How long a key that both is bound and starts a longer binding waits for
+// the rest of it, as vi's `timeoutlen`
+pub const KEY_TIMEOUT: Duration = Duration::from_millis(1000);
+
+// Layout of the JSON config file. Each section maps a key sequence such as
+// "ctrl-s" or "ctrl-x ctrl-s" to an action and is merged over the defaults.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct KeymapConfig {
+    #[serde(default)]
+    edit: HashMap<String, Action>,
+    #[serde(default)]
+    insert: HashMap<String, Action>,
+    #[serde(default)]
+    command: HashMap<String, Action>,
+}
+
+#[derive(Debug)]
+pub struct Keymap {
+    bindings: HashMap<KeymapMode, HashMap<Vec<Key>, Action>>,
+    pending: Vec<Key>,
+    // When the first of the pending keys was pressed
+    pending_since: Option<SystemTime>,
+}
+
+const DEFAULT_EDIT_BINDINGS: &[(&str, Action)] = &[
+    ("esc", Action::CommandLine),
+    ("ctrl-s", Action::Save),
+    ("ctrl-o", Action::OpenFile),
+    ("ctrl-q", Action::Quit),
+    ("ctrl-z", Action::Undo),
+    ("ctrl-y", Action::Redo),
+    ("backspace", Action::DeleteBack),
+    ("ctrl-x", Action::Cut),
+    ("ctrl-c", Action::Copy),
+    ("ctrl-v", Action::Paste),
+    ("alt-v", Action::PastePrevious),
+    ("left", Action::MoveLeft),
+    ("right", Action::MoveRight),
+    ("up", Action::MoveUp),
+    ("down", Action::MoveDown),
+    ("ctrl-n", Action::AddCursorBelow),
+    ("ctrl-d", Action::AddCursorAtNextMatch),
+    ("ctrl-f", Action::SearchForward),
+    ("ctrl-r", Action::SearchBackward),
+    ("alt-n", Action::SearchNext),
+    ("alt-p", Action::SearchPrevious),
+    ("pageup", Action::PageUp),
+    ("pagedown", Action::PageDown),
+];
+
+const DEFAULT_COMMAND_BINDINGS: &[(&str, Action)] = &[
+    ("esc", Action::CancelCommand),
+    ("enter", Action::SubmitCommand),
+    ("ctrl-s", Action::Save),
+    ("ctrl-o", Action::OpenFile),
+    ("ctrl-z", Action::Undo),
+    ("ctrl-y", Action::Redo),
+    ("backspace", Action::DeleteBack),
+    ("ctrl-x", Action::Cut),
+    ("ctrl-c", Action::Copy),
+    ("ctrl-v", Action::Paste),
+    ("left", Action::MoveLeft),
+    ("right", Action::MoveRight),
+];
+
+impl Keymap {
+    pub fn new() -> Keymap {
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+            pending: Vec::new(),
+            pending_since: None,
+        };
+        // Insert mode is Edit mode except that Esc goes back to Normal mode
+        for &(keys, action) in DEFAULT_EDIT_BINDINGS {
+            keymap.bind(KeymapMode::Edit, parse_keys(keys).unwrap(), action);
+            keymap.bind(KeymapMode::Insert, parse_keys(keys).unwrap(), action);
+        }
+        keymap.bind(KeymapMode::Insert, vec![Key::Esc], Action::NormalMode);
+        for &(keys, action) in DEFAULT_COMMAND_BINDINGS {
+            keymap.bind(KeymapMode::Command, parse_keys(keys).unwrap(), action);
+        }
+        keymap
+    }
+
+    // The defaults with the bindings from a JSON config merged over them
+    pub fn from_json(json: &str) -> Result<Keymap, String> {
+        let config: KeymapConfig = serde_json::from_str(json).map_err(|e| e.to_string())?;
+
+        let mut keymap = Keymap::new();
+        for (mode, section, bindings) in [
+            (KeymapMode::Edit, "edit", config.edit),
+            (KeymapMode::Insert, "insert", config.insert),
+            (KeymapMode::Command, "command", config.command),
+        ] {
+            for (keys, action) in bindings {
+                let parsed = parse_keys(&keys).map_err(|e| format!("{} in \"{}\"", e, section))?;
+                keymap.bind(mode, parsed, action);
+            }
+        }
+        Ok(keymap)
+    }
+
+    // Loads the keymap at `config_path()`. A missing file means the
+    // defaults; an unreadable or invalid one is an error naming the file.
+    pub fn load() -> Result<Keymap, String> {
+        let path = match Keymap::config_path() {
+            Some(path) => path,
+            None => return Ok(Keymap::new()),
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(json) => Keymap::from_json(&json)
+                .map_err(|e| format!("Invalid keymap {}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Keymap::new()),
+            Err(e) => Err(format!("Unable to read keymap {}: {}", path.display(), e)),
+        }
+    }
+
+    // $XDG_CONFIG_HOME/raccoon/keymap.json, falling back to ~/.config
+    pub fn config_path() -> Option<PathBuf> {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_dir.join("raccoon").join("keymap.json"))
+    }
+
+    pub fn bind(&mut self, mode: KeymapMode, keys: Vec<Key>, action: Action) {
+        self.bindings.entry(mode).or_default().insert(keys, action);
+    }
+
+    pub fn binding(&self, mode: KeymapMode, keys: &[Key]) -> Option<Action> {
+        self.bindings.get(&mode)?.get(keys).copied()
+    }
+
+    // Feeds the next key press, made at `time`, waiting for more keys
+    // while they could still complete a longer binding. A binding that is
+    // also the start of a longer one is only taken once the next key
+    // doesn't go on with it, or KEY_TIMEOUT after the first was pressed.
+    pub fn resolve(&mut self, mode: KeymapMode, key: Key, time: SystemTime) -> KeyResult {
+        self.pending.push(key);
+        let is_prefix = self.bindings.get(&mode).is_some_and(|bindings| {
+            bindings
+                .keys()
+                .any(|keys| keys.len() > self.pending.len() && keys.starts_with(&self.pending))
+        });
+        if is_prefix {
+            if self.pending_since.is_none() {
+                self.pending_since = Some(time);
+            }
+            KeyResult::Pending
+        } else {
+            self.settle(mode)
+        }
+    }
+
+    // Settles the pending keys once they have waited KEY_TIMEOUT as of
+    // `now`, or returns None if there is still time
+    pub fn time_out(&mut self, mode: KeymapMode, now: SystemTime) -> Option<KeyResult> {
+        let since = self.pending_since?;
+        if now.duration_since(since).unwrap_or_default() < KEY_TIMEOUT {
+            return None;
+        }
+        Some(self.settle(mode))
+    }
+
+    // Takes the longest of the pending keys that is bound, giving back
+    // the rest
+    fn settle(&mut self, mode: KeymapMode) -> KeyResult {
+        let keys: Vec<Key> = self.pending.drain(..).collect();
+        self.pending_since = None;
+        for len in (1..=keys.len()).rev() {
+            if let Some(action) = self.binding(mode, &keys[..len]) {
+                return if len == keys.len() {
+                    KeyResult::Action(action)
+                } else {
+                    KeyResult::ActionThen(action, keys[len..].to_vec())
+                };
+            }
+        }
+        KeyResult::Unbound(keys)
+    }
+}
+
+// Action for an arrow key pressed with shift, which termion has no keys
+// of its own for
+pub fn shifted_action(key: Key) -> Option<Action> {
+    match key {
+        Key::Left => Some(Action::SelectLeft),
+        Key::Right => Some(Action::SelectRight),
+        Key::Up => Some(Action::SelectUp),
+        Key::Down => Some(Action::SelectDown),
+        _ => None,
+    }
+}
+
+// Parses a space separated key sequence such as "ctrl-x ctrl-s"
+pub fn parse_keys(keys: &str) -> Result<Vec<Key>, String> {
+    let parsed: Vec<Key> = keys
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Result<_, _>>()?;
+    if parsed.is_empty() {
+        return Err(String::from("Empty key sequence"));
+    }
+    Ok(parsed)
+}
+
+// Parses a single key such as "a", "enter", "ctrl-s", "alt-x" or "f5"
+pub fn parse_key(key: &str) -> Result<Key, String> {
+    let single_char = |name: &str| {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+
+    let parsed = match key.to_lowercase().as_str() {
+        "esc" | "escape" => Some(Key::Esc),
+        "enter" | "return" => Some(Key::Char('\n')),
+        "tab" => Some(Key::Char('\t')),
+        "space" => Some(Key::Char(' ')),
+        "backspace" => Some(Key::Backspace),
+        "delete" | "del" => Some(Key::Delete),
+        "insert" => Some(Key::Insert),
+        "left" => Some(Key::Left),
+        "right" => Some(Key::Right),
+        "up" => Some(Key::Up),
+        "down" => Some(Key::Down),
+        "home" => Some(Key::Home),
+        "end" => Some(Key::End),
+        "pageup" => Some(Key::PageUp),
+        "pagedown" => Some(Key::PageDown),
+        "backtab" => Some(Key::BackTab),
+        _ => None,
+    };
+    if let Some(parsed) = parsed {
+        return Ok(parsed);
+    }
+
+    if let Some(c) = single_char(key) {
+        return Ok(Key::Char(c));
+    }
+    // Modifiers are named in any case, as keys are, but the char after
+    // "alt-" keeps its own
+    if let Some(c) = strip_prefix_ignore_case(key, "ctrl-").and_then(single_char) {
+        return Ok(Key::Ctrl(c.to_ascii_lowercase()));
+    }
+    if let Some(c) = strip_prefix_ignore_case(key, "alt-").and_then(single_char) {
+        return Ok(Key::Alt(c));
+    }
+    if let Some(n) = strip_prefix_ignore_case(key, "f").and_then(|n| n.parse::<u8>().ok()) {
+        if (1..=12).contains(&n) {
+            return Ok(Key::F(n));
+        }
+    }
+    Err(format!("Unknown key \"{}\"", key))
+}
+
+fn strip_prefix_ignore_case<'a>(key: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = key.get(..prefix.len())?;
+    if head.eq_ignore_ascii_case(prefix) {
+        Some(&key[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+#[path = "tests/keymap_tests.rs"]
+mod tests;
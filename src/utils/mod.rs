@@ -1,5 +1,11 @@
+pub mod clipboard;
 pub mod events;
+pub mod keymap;
+pub mod save;
+pub mod stamp;
+pub mod width;
 
+#[derive(PartialEq, Debug)]
 pub enum QuitOption {
     Quitting,
     NotQuitting,
@@ -0,0 +1,87 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{self as unix_fs, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use tempfile::Builder;
+
+// Writes `contents` to `path` without ever leaving it half written. The
+// text goes to a temporary file in the same directory, which is synced and
+// then renamed over `path`, so a crash or a full disk leaves either the old
+// file or the new one. The new file keeps the old one's permissions and,
+// where allowed, its owner. With `backup` the old file is also kept as
+// `path~`.
+pub fn write_atomically(path: &Path, contents: &[u8], backup: bool) -> io::Result<()> {
+    // Saving through a symlink replaces the file it points to
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let metadata = match fs::metadata(&path) {
+        Ok(metadata) => metadata,
+        // There is nothing to lose, and creating the file directly gives it
+        // the usual permissions
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return write_in_place(&path, contents),
+        Err(e) => return Err(e),
+    };
+
+    // Renaming over a read-only file would work, but shouldn't be allowed
+    OpenOptions::new().write(true).open(&path)?;
+    if backup {
+        fs::copy(&path, backup_path(&path))?;
+    }
+    // Other hard links would be left pointing at the old contents
+    if metadata.nlink() > 1 {
+        return write_in_place(&path, contents);
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut prefix = OsString::from(".");
+    prefix.push(path.file_name().unwrap_or_default());
+    prefix.push(".");
+    let mut temp = match Builder::new()
+        .prefix(&prefix)
+        .suffix(".tmp")
+        .tempfile_in(dir)
+    {
+        Ok(temp) => temp,
+        // Without leave to add files to the directory, the file can only
+        // be overwritten
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            return write_in_place(&path, contents)
+        }
+        Err(e) => return Err(e),
+    };
+
+    // The temporary file is removed if anything goes wrong from here on
+    temp.write_all(contents)?;
+    temp.as_file().set_permissions(metadata.permissions())?;
+    // Only root can give files away, which is no reason to fail the save
+    let _ = unix_fs::fchown(temp.as_file(), Some(metadata.uid()), Some(metadata.gid()));
+    temp.as_file().sync_all()?;
+    temp.persist(&path).map_err(|e| e.error)?;
+
+    // The rename only lasts through a crash once the directory is synced
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+// Where the old contents of `path` are kept, as vi's `backup` option does
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push("~");
+    PathBuf::from(backup)
+}
+
+fn write_in_place(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+#[path = "tests/save_tests.rs"]
+mod tests;
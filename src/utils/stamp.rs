@@ -0,0 +1,89 @@
+use std::fs::{File, Metadata};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::time::SystemTime;
+
+// What a file looked like when it was last read or written, to tell when
+// something else changes it. The modification time and length are cheap to
+// check often; the checksum settles whether a file that was only touched
+// really changed.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FileStamp {
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+    // None when the contents weren't read, so any change to the file counts
+    pub checksum: Option<u64>,
+}
+
+impl FileStamp {
+    pub fn new(contents: &[u8], metadata: &Metadata) -> FileStamp {
+        FileStamp::with_checksum(checksum(contents), metadata)
+    }
+
+    // Stamp for contents whose checksum is already known
+    pub fn with_checksum(checksum: u64, metadata: &Metadata) -> FileStamp {
+        FileStamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            checksum: Some(checksum),
+        }
+    }
+
+    // Stamp from `metadata` alone, for contents that weren't read
+    pub fn unread(metadata: &Metadata) -> FileStamp {
+        FileStamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            checksum: None,
+        }
+    }
+
+    // Reads the file at `path` a buffer at a time to stamp it as it is now
+    pub fn read(path: &Path) -> io::Result<FileStamp> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let mut reader = BufReader::new(file);
+        let mut hash = CHECKSUM_START;
+        loop {
+            let bytes = reader.fill_buf()?;
+            if bytes.is_empty() {
+                break;
+            }
+            hash = add_to_checksum(hash, bytes);
+            let len = bytes.len();
+            reader.consume(len);
+        }
+        Ok(FileStamp::with_checksum(hash, &metadata))
+    }
+
+    // Whether a file stamped `current` has the same contents, as far as can
+    // be told
+    pub fn same_contents(&self, current: &FileStamp) -> bool {
+        self.len == current.len && self.checksum.is_some() && self.checksum == current.checksum
+    }
+
+    // Whether a file with `metadata` can be taken to be unchanged without
+    // reading it
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        self.modified == metadata.modified().ok() && self.len == metadata.len()
+    }
+}
+
+const CHECKSUM_START: u64 = 0xcbf2_9ce4_8422_2325;
+
+// 64-bit FNV-1a, which unlike the std hashers stays the same between runs
+pub fn checksum(bytes: &[u8]) -> u64 {
+    add_to_checksum(CHECKSUM_START, bytes)
+}
+
+// Carries a checksum on over the next `bytes`, so that it can be worked out
+// a piece at a time
+fn add_to_checksum(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+#[path = "tests/stamp_tests.rs"]
+mod tests;
@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod clipboard_tests {
+    use super::super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn osc52_sequence_is_base64() {
+        assert_eq!(osc52_sequence("hello"), "\x1b]52;c;aGVsbG8=\x07");
+        assert_eq!(osc52_sequence(""), "\x1b]52;c;\x07");
+    }
+
+    #[test]
+    fn osc52_copy_queues_sequence() -> io::Result<()> {
+        let mut clipboard = Osc52Clipboard::new();
+        clipboard.copy("a\nb")?;
+        clipboard.copy("c")?;
+        assert_eq!(
+            clipboard.take_terminal_output(),
+            "\x1b]52;c;YQpi\x07\x1b]52;c;Yw==\x07"
+        );
+        assert_eq!(clipboard.take_terminal_output(), "");
+        assert_eq!(clipboard.paste()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn command_clipboard_from_spec() {
+        assert!(CommandClipboard::from_spec("  ").is_none());
+        assert!(CommandClipboard::from_spec(",wl-paste").is_none());
+
+        let clipboard = CommandClipboard::from_spec("xclip -selection clipboard").unwrap();
+        assert_eq!(
+            clipboard.copy_command,
+            vec!["xclip", "-selection", "clipboard"]
+        );
+        assert_eq!(clipboard.paste_command, None);
+
+        let clipboard = CommandClipboard::from_spec("wl-copy, wl-paste -n").unwrap();
+        assert_eq!(clipboard.copy_command, vec!["wl-copy"]);
+        assert_eq!(
+            clipboard.paste_command,
+            Some(vec![String::from("wl-paste"), String::from("-n")])
+        );
+    }
+
+    #[test]
+    fn command_clipboard_round_trip() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("clipboard");
+        let spec = format!("tee {0},cat {0}", path.display());
+        let mut clipboard = CommandClipboard::from_spec(&spec).unwrap();
+
+        clipboard.copy("one\ntwo")?;
+        assert_eq!(clipboard.paste()?, Some(String::from("one\ntwo")));
+        Ok(())
+    }
+
+    #[test]
+    fn command_clipboard_failures() {
+        let mut clipboard = CommandClipboard::from_spec("false,false").unwrap();
+        assert!(clipboard.copy("text").is_err());
+        assert!(clipboard.paste().is_err());
+
+        let mut clipboard = CommandClipboard::from_spec("raccoon-no-such-command").unwrap();
+        assert!(clipboard.copy("text").is_err());
+        assert_eq!(clipboard.paste().unwrap(), None);
+    }
+}
@@ -0,0 +1,224 @@
+#[cfg(test)]
+mod keymap_tests {
+    use super::super::*;
+
+    #[test]
+    fn parse_named_keys() {
+        assert_eq!(parse_key("esc"), Ok(Key::Esc));
+        assert_eq!(parse_key("Enter"), Ok(Key::Char('\n')));
+        assert_eq!(parse_key("space"), Ok(Key::Char(' ')));
+        assert_eq!(parse_key("pagedown"), Ok(Key::PageDown));
+        assert_eq!(parse_key("f5"), Ok(Key::F(5)));
+    }
+
+    #[test]
+    fn parse_modified_keys() {
+        assert_eq!(parse_key("ctrl-s"), Ok(Key::Ctrl('s')));
+        assert_eq!(parse_key("ctrl-S"), Ok(Key::Ctrl('s')));
+        assert_eq!(parse_key("alt-x"), Ok(Key::Alt('x')));
+        assert_eq!(parse_key("q"), Ok(Key::Char('q')));
+        assert_eq!(parse_key("Q"), Ok(Key::Char('Q')));
+        assert_eq!(parse_key("Ctrl-s"), Ok(Key::Ctrl('s')));
+        assert_eq!(parse_key("CTRL-X"), Ok(Key::Ctrl('x')));
+        assert_eq!(parse_key("Alt-X"), Ok(Key::Alt('X')));
+        assert_eq!(parse_key("F5"), Ok(Key::F(5)));
+    }
+
+    #[test]
+    fn parse_bad_keys() {
+        assert_eq!(
+            parse_key("ctrl-"),
+            Err(String::from("Unknown key \"ctrl-\""))
+        );
+        assert_eq!(parse_key("f13"), Err(String::from("Unknown key \"f13\"")));
+        assert_eq!(parse_keys("  "), Err(String::from("Empty key sequence")));
+    }
+
+    #[test]
+    fn parse_key_sequence() {
+        assert_eq!(
+            parse_keys("ctrl-x ctrl-s"),
+            Ok(vec![Key::Ctrl('x'), Key::Ctrl('s')])
+        );
+    }
+
+    #[test]
+    fn defaults_leave_q_unbound() {
+        let mut keymap = Keymap::new();
+        assert_eq!(
+            keymap.resolve(KeymapMode::Edit, Key::Char('q'), SystemTime::now()),
+            KeyResult::Unbound(vec![Key::Char('q')])
+        );
+        assert_eq!(
+            keymap.resolve(KeymapMode::Edit, Key::Ctrl('q'), SystemTime::now()),
+            KeyResult::Action(Action::Quit)
+        );
+    }
+
+    #[test]
+    fn enter_depends_on_mode() {
+        let mut keymap = Keymap::new();
+        assert_eq!(
+            keymap.resolve(KeymapMode::Edit, Key::Char('\n'), SystemTime::now()),
+            KeyResult::Unbound(vec![Key::Char('\n')])
+        );
+        assert_eq!(
+            keymap.resolve(KeymapMode::Command, Key::Char('\n'), SystemTime::now()),
+            KeyResult::Action(Action::SubmitCommand)
+        );
+    }
+
+    #[test]
+    fn resolve_key_sequence() {
+        let mut keymap = Keymap::new();
+        keymap.bind(
+            KeymapMode::Edit,
+            vec![Key::Ctrl('k'), Key::Ctrl('s')],
+            Action::Save,
+        );
+
+        assert_eq!(
+            keymap.resolve(KeymapMode::Edit, Key::Ctrl('k'), SystemTime::now()),
+            KeyResult::Pending
+        );
+        assert_eq!(
+            keymap.resolve(KeymapMode::Edit, Key::Ctrl('s'), SystemTime::now()),
+            KeyResult::Action(Action::Save)
+        );
+
+        // A key that breaks the sequence gives back everything typed so far
+        keymap.resolve(KeymapMode::Edit, Key::Ctrl('k'), SystemTime::now());
+        assert_eq!(
+            keymap.resolve(KeymapMode::Edit, Key::Char('a'), SystemTime::now()),
+            KeyResult::Unbound(vec![Key::Ctrl('k'), Key::Char('a')])
+        );
+    }
+
+    #[test]
+    fn longer_binding_waits_for_its_next_key() {
+        // The example from the config file layout, which starts with the
+        // default ctrl-x
+        let json = r#"{ "edit": { "ctrl-x ctrl-s": "save" } }"#;
+        let mut keymap = Keymap::from_json(json).unwrap();
+
+        assert_eq!(
+            keymap.resolve(KeymapMode::Edit, Key::Ctrl('x'), SystemTime::now()),
+            KeyResult::Pending
+        );
+        assert_eq!(
+            keymap.resolve(KeymapMode::Edit, Key::Ctrl('s'), SystemTime::now()),
+            KeyResult::Action(Action::Save)
+        );
+
+        // Any other key falls back to ctrl-x alone
+        keymap.resolve(KeymapMode::Edit, Key::Ctrl('x'), SystemTime::now());
+        assert_eq!(
+            keymap.resolve(KeymapMode::Edit, Key::Char('a'), SystemTime::now()),
+            KeyResult::ActionThen(Action::Cut, vec![Key::Char('a')])
+        );
+    }
+
+    #[test]
+    fn pending_binding_times_out() {
+        let json = r#"{ "edit": { "ctrl-x ctrl-s": "save" } }"#;
+        let mut keymap = Keymap::from_json(json).unwrap();
+        assert_eq!(keymap.time_out(KeymapMode::Edit, SystemTime::now()), None);
+
+        keymap.resolve(KeymapMode::Edit, Key::Ctrl('x'), SystemTime::now());
+        assert_eq!(keymap.time_out(KeymapMode::Edit, SystemTime::now()), None);
+        assert_eq!(
+            keymap.time_out(KeymapMode::Edit, SystemTime::now() + KEY_TIMEOUT),
+            Some(KeyResult::Action(Action::Cut))
+        );
+        assert_eq!(
+            keymap.resolve(KeymapMode::Edit, Key::Ctrl('x'), SystemTime::now()),
+            KeyResult::Pending
+        );
+    }
+
+    #[test]
+    fn from_json_merges_over_defaults() {
+        let json = r#"{
+            "edit": { "ctrl-w": "save", "esc": "quit" },
+            "command": { "tab": "submit_command" }
+        }"#;
+        let keymap = Keymap::from_json(json).unwrap();
+
+        assert_eq!(
+            keymap.binding(KeymapMode::Edit, &[Key::Ctrl('w')]),
+            Some(Action::Save)
+        );
+        assert_eq!(
+            keymap.binding(KeymapMode::Edit, &[Key::Esc]),
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            keymap.binding(KeymapMode::Edit, &[Key::Ctrl('s')]),
+            Some(Action::Save)
+        );
+        assert_eq!(
+            keymap.binding(KeymapMode::Command, &[Key::Char('\t')]),
+            Some(Action::SubmitCommand)
+        );
+    }
+
+    #[test]
+    fn from_json_with_unknown_key() {
+        let json = r#"{ "edit": { "ctrl-": "save" } }"#;
+        assert_eq!(
+            Keymap::from_json(json).unwrap_err(),
+            "Unknown key \"ctrl-\" in \"edit\""
+        );
+    }
+
+    #[test]
+    fn from_json_with_unknown_action() {
+        let json = r#"{ "edit": { "ctrl-w": "explode" } }"#;
+        let error = Keymap::from_json(json).unwrap_err();
+        assert!(error.contains("unknown variant `explode`"), "{}", error);
+        assert!(error.contains("line 1"), "{}", error);
+    }
+
+    #[test]
+    fn from_json_with_unknown_section() {
+        let json = r#"{ "visual": {} }"#;
+        let error = Keymap::from_json(json).unwrap_err();
+        assert!(error.contains("unknown field `visual`"), "{}", error);
+    }
+
+    #[test]
+    fn from_empty_json_object() {
+        let keymap = Keymap::from_json("{}").unwrap();
+        assert_eq!(
+            keymap.binding(KeymapMode::Edit, &[Key::Esc]),
+            Some(Action::CommandLine)
+        );
+    }
+
+    #[test]
+    fn escape_in_insert_mode() {
+        let mut keymap = Keymap::new();
+        assert_eq!(
+            keymap.resolve(KeymapMode::Insert, Key::Esc, SystemTime::now()),
+            KeyResult::Action(Action::NormalMode)
+        );
+        assert_eq!(
+            keymap.resolve(KeymapMode::Insert, Key::Ctrl('s'), SystemTime::now()),
+            KeyResult::Action(Action::Save)
+        );
+
+        let keymap = Keymap::from_json(r#"{ "insert": { "ctrl-g": "normal_mode" } }"#).unwrap();
+        assert_eq!(
+            keymap.binding(KeymapMode::Insert, &[Key::Ctrl('g')]),
+            Some(Action::NormalMode)
+        );
+        assert_eq!(keymap.binding(KeymapMode::Edit, &[Key::Ctrl('g')]), None);
+    }
+
+    #[test]
+    fn shifted_arrows_select() {
+        assert_eq!(shifted_action(Key::Left), Some(Action::SelectLeft));
+        assert_eq!(shifted_action(Key::Down), Some(Action::SelectDown));
+        assert_eq!(shifted_action(Key::Char('a')), None);
+    }
+}
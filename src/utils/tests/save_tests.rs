@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod save_tests {
+    use super::super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    // Names of the files in `dir`, in order
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn write_new_file() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("new.txt");
+
+        write_atomically(&path, b"hello", false)?;
+        assert_eq!(fs::read_to_string(&path)?, "hello");
+        assert_eq!(file_names(dir.path()), vec!["new.txt"]);
+        Ok(())
+    }
+
+    #[test]
+    fn replace_existing_file() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a much longer old text")?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640))?;
+
+        write_atomically(&path, b"new", false)?;
+        assert_eq!(fs::read_to_string(&path)?, "new");
+        let mode = fs::metadata(&path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        // No temporary file is left behind
+        assert_eq!(file_names(dir.path()), vec!["file.txt"]);
+        Ok(())
+    }
+
+    #[test]
+    fn keep_backup() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "old")?;
+
+        write_atomically(&path, b"new", true)?;
+        assert_eq!(fs::read_to_string(&path)?, "new");
+        assert_eq!(fs::read_to_string(backup_path(&path))?, "old");
+        assert_eq!(file_names(dir.path()), vec!["file.txt", "file.txt~"]);
+
+        // There is nothing to back up yet for a new file
+        write_atomically(&dir.path().join("a"), b"", true)?;
+        assert!(!dir.path().join("a~").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn write_through_symlink() -> io::Result<()> {
+        let dir = tempdir()?;
+        let target = dir.path().join("target.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&target, "old")?;
+        unix_fs::symlink(&target, &link)?;
+
+        write_atomically(&link, b"new", false)?;
+        assert!(fs::symlink_metadata(&link)?.file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target)?, "new");
+        Ok(())
+    }
+
+    #[test]
+    fn write_keeps_hard_links() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        let other = dir.path().join("other.txt");
+        fs::write(&path, "old")?;
+        fs::hard_link(&path, &other)?;
+
+        write_atomically(&path, b"new", false)?;
+        assert_eq!(fs::read_to_string(&other)?, "new");
+        Ok(())
+    }
+
+    #[test]
+    fn read_only_file_is_left_alone() -> io::Result<()> {
+        // Root can write to the file whatever its permissions
+        if unsafe { libc::geteuid() } == 0 {
+            return Ok(());
+        }
+
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "old")?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o444))?;
+
+        assert!(write_atomically(&path, b"new", false).is_err());
+        assert_eq!(fs::read_to_string(&path)?, "old");
+        Ok(())
+    }
+
+    #[test]
+    fn file_under_regular_file_fails() -> io::Result<()> {
+        // Unlike permissions, this stops root too
+        let dir = tempdir()?;
+        let parent = dir.path().join("file.txt");
+        fs::write(&parent, "old")?;
+        assert!(write_atomically(&parent.join("file.txt"), b"new", true).is_err());
+        assert_eq!(fs::read_to_string(&parent)?, "old");
+        Ok(())
+    }
+
+    #[test]
+    fn missing_directory_fails() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("missing").join("file.txt");
+        assert!(write_atomically(&path, b"new", false).is_err());
+    }
+
+    #[test]
+    fn backup_path_appends_tilde() {
+        assert_eq!(backup_path(Path::new("a/b.txt")), PathBuf::from("a/b.txt~"));
+    }
+}
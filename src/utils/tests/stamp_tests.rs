@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod stamp_tests {
+    use super::super::*;
+    use std::fs::{self, File};
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn checksum_is_fnv1a() {
+        assert_eq!(checksum(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(checksum(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn read_stamps_contents() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "abc")?;
+
+        let stamp = FileStamp::read(&path)?;
+        assert_eq!(stamp.len, 3);
+        assert_eq!(stamp.checksum, Some(checksum(b"abc")));
+        assert!(stamp.matches(&fs::metadata(&path)?));
+        Ok(())
+    }
+
+    #[test]
+    fn read_checksums_a_buffer_at_a_time() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        let contents: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        fs::write(&path, &contents)?;
+
+        assert_eq!(FileStamp::read(&path)?.checksum, Some(checksum(&contents)));
+        Ok(())
+    }
+
+    #[test]
+    fn unread_stamp_never_has_same_contents() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "abc")?;
+        let unread = FileStamp::unread(&fs::metadata(&path)?);
+        let read = FileStamp::read(&path)?;
+
+        assert!(unread.matches(&fs::metadata(&path)?));
+        assert!(!unread.same_contents(&read));
+        assert!(!read.same_contents(&unread));
+        assert!(read.same_contents(&read));
+        Ok(())
+    }
+
+    #[test]
+    fn matches_notices_touch_and_resize() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "abc")?;
+        let stamp = FileStamp::read(&path)?;
+
+        let modified = stamp.modified.unwrap() + Duration::from_secs(10);
+        File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(modified)?;
+        assert!(!stamp.matches(&fs::metadata(&path)?));
+
+        fs::write(&path, "abcd")?;
+        assert!(!stamp.matches(&fs::metadata(&path)?));
+        Ok(())
+    }
+}
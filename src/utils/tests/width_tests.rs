@@ -0,0 +1,48 @@
+#[cfg(test)]
+mod width_tests {
+    use super::super::*;
+
+    #[test]
+    fn grapheme_width_of_ascii_and_wide_chars() {
+        assert_eq!(grapheme_width("a", 0, 8), 1);
+        assert_eq!(grapheme_width("\u{4E2D}", 0, 8), 2);
+        assert_eq!(grapheme_width("\u{1F600}", 0, 8), 2);
+    }
+
+    #[test]
+    fn grapheme_width_of_zero_width_clusters() {
+        assert_eq!(grapheme_width("\u{200D}", 0, 8), 0);
+        assert_eq!(grapheme_width("e\u{301}", 0, 8), 1);
+        assert_eq!(
+            grapheme_width("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}", 0, 8),
+            2
+        );
+    }
+
+    #[test]
+    fn grapheme_width_of_tabs() {
+        assert_eq!(grapheme_width("\t", 0, 4), 4);
+        assert_eq!(grapheme_width("\t", 1, 4), 3);
+        assert_eq!(grapheme_width("\t", 4, 4), 4);
+        assert_eq!(grapheme_width("\t", 3, 0), 1);
+    }
+
+    #[test]
+    fn display_width_of_mixed_line() {
+        assert_eq!(display_width("a\u{4E2D}b", 8), 4);
+        assert_eq!(display_width("a\tb", 4), 5);
+        assert_eq!(display_width("\u{4E2D}\tb", 4), 5);
+        assert_eq!(display_width("", 4), 0);
+    }
+
+    #[test]
+    fn column_at_display_column() {
+        let line = "a\u{4E2D}\tb";
+        assert_eq!(column_at_display(line, 0, 4), 0);
+        assert_eq!(column_at_display(line, 1, 4), 1);
+        assert_eq!(column_at_display(line, 2, 4), 1);
+        assert_eq!(column_at_display(line, 3, 4), 2);
+        assert_eq!(column_at_display(line, 4, 4), 3);
+        assert_eq!(column_at_display(line, 10, 4), 4);
+    }
+}
@@ -0,0 +1,51 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+pub const DEFAULT_TAB_STOP: usize = 8;
+
+// Number of screen columns taken up by a grapheme cluster that starts at
+// screen column `column`. Tabs reach to the next tab stop, wide chars take
+// two columns and zero width chars such as joiners take none.
+pub fn grapheme_width(grapheme: &str, column: usize, tab_stop: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_stop = tab_stop.max(1);
+        return tab_stop - column % tab_stop;
+    }
+
+    // A cluster is drawn as a single glyph, so combining marks and the
+    // parts of a joined emoji don't add up
+    grapheme
+        .chars()
+        .filter_map(UnicodeWidthChar::width)
+        .max()
+        .unwrap_or(0)
+        .min(2)
+}
+
+// Screen width of a single line of text
+pub fn display_width(line: &str, tab_stop: usize) -> usize {
+    line.graphemes(true).fold(0, |column, grapheme| {
+        column + grapheme_width(grapheme, column, tab_stop)
+    })
+}
+
+// Grapheme column of `line` drawn at screen column `display_column`. A
+// screen column in the middle of a wide char or tab maps to its start,
+// and one past the end of the line maps to the end.
+pub fn column_at_display(line: &str, display_column: usize, tab_stop: usize) -> usize {
+    let mut column = 0;
+    let mut width_so_far = 0;
+    for grapheme in line.graphemes(true) {
+        let width = grapheme_width(grapheme, width_so_far, tab_stop);
+        if width_so_far + width > display_column {
+            break;
+        }
+        width_so_far += width;
+        column += 1;
+    }
+    column
+}
+
+#[cfg(test)]
+#[path = "tests/width_tests.rs"]
+mod tests;
@@ -1,40 +1,436 @@
 use std::io;
 
-use crate::model::app::App;
-#[allow(unused_imports)]
+use crate::model::app::{App, AppMode, CommandMode, LineNumbers};
+use crate::model::encoding::Encoding;
+use crate::model::lineending::LineEnding;
+use crate::model::search::{self, SearchDirection};
+use crate::model::taggedtext::TaggedText;
+use crate::model::texttag::{Tag, TextTag};
+use crate::utils::width;
 use tui::{
-    backend::{Backend, TermionBackend},
-    layout::{Alignment, Constraint, Direction, Layout},
+    backend::Backend,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, List, Paragraph, Text},
+    widgets::{Paragraph, Text},
     Terminal,
 };
+use unicode_segmentation::UnicodeSegmentation;
 
 pub struct View<B: Backend> {
     terminal: Terminal<B>,
-    // Probably want to include layout details
-    //  here eventually.
+    // First buffer line and screen column shown in the text area
+    top_line: usize,
+    left_column: usize,
+    // Size of the text area as of the last draw
+    text_area: Rect,
 }
 
 impl<B: Backend> View<B> {
     pub fn new(terminal: Terminal<B>) -> View<B> {
-        View { terminal: terminal }
+        View {
+            terminal,
+            top_line: 0,
+            left_column: 0,
+            text_area: Rect::default(),
+        }
+    }
+
+    // Number of lines PageUp and PageDown move by
+    pub fn page_height(&self) -> usize {
+        (self.text_area.height as usize).max(1)
     }
 
     pub fn update_display(&mut self, app: &App) -> Result<(), io::Error> {
-        let text = app.get_text_as_iter(); // Get a copy of the text to be rendered
-                                           // For now let's not do anything fancy formatting
-        let text: Vec<_> = text.iter().map(|x| Text::raw(x)).collect();
+        let buffer = app.shown_buffer();
+        let size = self.terminal.size()?;
+        // A large file that hasn't been gone through yet only has as many
+        // lines as can be seen for now
+        let num_lines = buffer.num_lines_if_counted().unwrap_or_else(|| {
+            buffer.num_lines_up_to(self.top_line.max(buffer.current_line()) + size.height as usize)
+        });
+        let gutter_width = gutter_width(app.line_numbers(), num_lines);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Min(1),
+                    Constraint::Length(1),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
+            .split(size);
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(gutter_width as u16), Constraint::Min(1)].as_ref())
+            .split(rows[0]);
+        self.text_area = columns[1];
+
+        let tab_stop = buffer.tab_stop();
+        self.scroll_to_cursor(
+            buffer.current_line(),
+            buffer.cursor_display_column(),
+            app.scroll_margin(),
+        );
+
+        let height = self.text_area.height as usize;
+        let mut tagged_text = buffer.as_tagged_text_for_lines(self.top_line, height);
+        if let Some(Ok(regex)) = app
+            .search_highlight()
+            .map(|pattern| search::compile(&pattern, false))
+        {
+            tagged_text.add_tags(
+                search::find_matches(tagged_text.as_str(), &regex)
+                    .into_iter()
+                    .filter(|(start, end)| start < end)
+                    .map(|(start, end)| TextTag::new(Tag::Match, start, end)),
+            );
+        }
+        let base_style = Style::default().fg(Color::White).bg(Color::Black);
+        let text = tagged_text_to_spans(&tagged_text, base_style, tab_stop, self.left_column);
+        let gutter = gutter_lines(
+            app.line_numbers(),
+            self.top_line,
+            height,
+            buffer.current_line(),
+            num_lines,
+            gutter_width,
+        );
+        let status = [Text::raw(status_line(app, rows[1].width as usize))];
+        let message = [Text::raw(message_line(app))];
+
+        let mut cursor_area = self.text_area;
         self.terminal.draw(|mut f| {
-            let size = f.size();
-            let block = Paragraph::new(text.iter())
-                .block(Block::default().title("Paragraph").borders(Borders::ALL))
-                .style(Style::default().fg(Color::White).bg(Color::Black))
-                .alignment(Alignment::Left)
-                .wrap(true);
-            f.render_widget(block, size);
+            let gutter = Paragraph::new(gutter.iter())
+                .style(base_style.fg(Color::DarkGray))
+                .alignment(Alignment::Left);
+            f.render_widget(gutter, columns[0]);
+
+            // Wrapping is left off so that rows in the text line up with
+            // rows on the screen when placing the cursor
+            let paragraph = Paragraph::new(text.iter())
+                .style(base_style)
+                .alignment(Alignment::Left);
+            f.render_widget(paragraph, columns[1]);
+
+            let status_line = Paragraph::new(status.iter())
+                .style(base_style.modifier(Modifier::REVERSED))
+                .alignment(Alignment::Left);
+            f.render_widget(status_line, rows[1]);
+
+            let message_line = Paragraph::new(message.iter())
+                .style(base_style)
+                .alignment(Alignment::Left);
+            f.render_widget(message_line, rows[2]);
+
+            if let Some(prompt) = command_prompt(app.mode()) {
+                let prompt_len = width::display_width(prompt, tab_stop) as u16;
+                cursor_area = Rect {
+                    x: rows[2].x + prompt_len,
+                    width: rows[2].width.saturating_sub(prompt_len),
+                    ..rows[2]
+                };
+            }
         })?;
 
+        let cursor = match app.mode() {
+            // Questions leave the cursor in the text, e.g. on the match a
+            // replacement is being asked about
+            AppMode::Command(mode) if !mode.asks_question() => {
+                cursor_position(&app.command_buffer().as_tagged_text(), tab_stop)
+            }
+            // Extra cursors are tagged too, so go by where the main one is
+            _ => Some((
+                buffer.current_line().saturating_sub(self.top_line),
+                buffer
+                    .cursor_display_column()
+                    .saturating_sub(self.left_column),
+            )),
+        };
+        match cursor {
+            Some((row, column)) if cursor_area.width > 0 && cursor_area.height > 0 => {
+                let x = cursor_area.x + (column as u16).min(cursor_area.width - 1);
+                let y = cursor_area.y + (row as u16).min(cursor_area.height - 1);
+                self.terminal.set_cursor(x, y)?;
+                self.terminal.show_cursor()?;
+            }
+            _ => self.terminal.hide_cursor()?,
+        }
+        self.terminal.backend_mut().flush()?;
+
         Ok(())
     }
 }
+
+impl<B: Backend + io::Write> View<B> {
+    // Writes escape sequences other than drawing ones, such as OSC 52,
+    // through the same backend that frames are drawn with
+    pub fn write_to_terminal(&mut self, output: &str) -> Result<(), io::Error> {
+        let backend = self.terminal.backend_mut();
+        backend.write_all(output.as_bytes())?;
+        io::Write::flush(backend)
+    }
+}
+
+impl<B: Backend> View<B> {
+    // Scrolls just enough to keep the cursor `scroll_margin` lines and
+    // columns away from the edges of the text area
+    fn scroll_to_cursor(&mut self, line: usize, column: usize, scroll_margin: usize) {
+        self.top_line = scroll_offset(
+            self.top_line,
+            line,
+            self.text_area.height as usize,
+            scroll_margin,
+        );
+        self.left_column = scroll_offset(
+            self.left_column,
+            column,
+            self.text_area.width as usize,
+            scroll_margin,
+        );
+    }
+}
+
+// New offset of a viewport `size` long that currently starts at `offset`,
+// such that `position` is at least `margin` away from either end. The
+// margin shrinks on viewports too small to honour it.
+fn scroll_offset(offset: usize, position: usize, size: usize, margin: usize) -> usize {
+    if size == 0 {
+        return offset;
+    }
+
+    let margin = margin.min((size - 1) / 2);
+    if position < offset + margin {
+        position.saturating_sub(margin)
+    } else if position + margin >= offset + size {
+        position + margin + 1 - size
+    } else {
+        offset
+    }
+}
+
+fn command_prompt(app_mode: AppMode) -> Option<&'static str> {
+    match app_mode {
+        AppMode::Command(CommandMode::Read) => Some("Open file: "),
+        AppMode::Command(CommandMode::Write) => Some("Save as: "),
+        AppMode::Command(CommandMode::Execute) => Some(":"),
+        AppMode::Command(CommandMode::Search(SearchDirection::Forward)) => Some("/"),
+        AppMode::Command(CommandMode::Search(SearchDirection::Backward)) => Some("?"),
+        _ => None,
+    }
+}
+
+fn mode_name(app_mode: AppMode, modal: bool) -> &'static str {
+    match app_mode {
+        AppMode::Edit if modal => "INSERT",
+        AppMode::Edit => "EDIT",
+        AppMode::Normal => "NORMAL",
+        AppMode::Visual => "VISUAL",
+        AppMode::Command(CommandMode::Read) => "OPEN",
+        AppMode::Command(CommandMode::Write) => "SAVE AS",
+        AppMode::Command(CommandMode::Execute) => "COMMAND",
+        AppMode::Command(CommandMode::Search(_)) => "SEARCH",
+        AppMode::Command(CommandMode::Confirm) | AppMode::Command(CommandMode::SaveBeforeQuit) => {
+            "CONFIRM"
+        }
+        AppMode::Command(CommandMode::Recover) => "RECOVER",
+        AppMode::Command(CommandMode::Reload) => "RELOAD",
+    }
+}
+
+// File name and modified flag on the left; mode, cursor position and
+// line count on the right, padded out to `width`
+fn status_line(app: &App, width: usize) -> String {
+    let buffer = app.buffer();
+    let file_name = app.file_path().map_or("[No Name]", String::as_str);
+    let modified = if buffer.is_modified() { " [+]" } else { "" };
+    let changed = if app.changed_on_disk() {
+        " [changed on disk]"
+    } else {
+        ""
+    };
+    let encoding = match buffer.encoding() {
+        Encoding::Utf8 => String::new(),
+        encoding => format!(" [{}]", encoding.name()),
+    };
+    let line_ending = match buffer.line_ending() {
+        LineEnding::Lf => String::new(),
+        line_ending => format!(" [{}]", line_ending.name()),
+    };
+    let left = format!(
+        " {}{}{}{}{}",
+        file_name, modified, encoding, line_ending, changed
+    );
+
+    // Lines aren't all counted just to show how many there are
+    let num_lines = match buffer.num_lines_if_counted() {
+        Some(1) => String::from("1 line"),
+        Some(num_lines) => format!("{} lines", num_lines),
+        None => String::from("? lines"),
+    };
+    let right = format!(
+        "{}  {}:{}  {} ",
+        mode_name(app.mode(), app.is_modal()),
+        buffer.current_line() + 1,
+        buffer.cursor().line_offset + 1,
+        num_lines,
+    );
+
+    let used = width::display_width(&left, 1) + width::display_width(&right, 1);
+    let padding = width.saturating_sub(used).max(1);
+    format!("{}{}{}", left, " ".repeat(padding), right)
+}
+
+// Shows the prompt while in command mode, otherwise the latest message
+fn message_line(app: &App) -> String {
+    match command_prompt(app.mode()) {
+        Some(prompt) => format!("{}{}", prompt, app.get_command_buffer_text()),
+        None => app.message().cloned().unwrap_or_default(),
+    }
+}
+
+// Room for the widest line number plus a space, or nothing at all
+fn gutter_width(line_numbers: LineNumbers, num_lines: usize) -> usize {
+    match line_numbers {
+        LineNumbers::Off => 0,
+        LineNumbers::Absolute | LineNumbers::Relative => num_lines.to_string().len().max(3) + 1,
+    }
+}
+
+// One gutter row per screen row from `top_line`, with a `~` past the end
+// of the text like vi
+fn gutter_lines(
+    line_numbers: LineNumbers,
+    top_line: usize,
+    height: usize,
+    current_line: usize,
+    num_lines: usize,
+    gutter_width: usize,
+) -> Vec<Text<'static>> {
+    if line_numbers == LineNumbers::Off {
+        return Vec::new();
+    }
+
+    let number_width = gutter_width.saturating_sub(1);
+    (top_line..top_line + height)
+        .map(|line| {
+            let row = if line >= num_lines {
+                format!("{:<width$}", "~", width = gutter_width)
+            } else if line_numbers == LineNumbers::Relative && line != current_line {
+                let distance = line.abs_diff(current_line);
+                format!("{:>width$} ", distance, width = number_width)
+            } else {
+                format!("{:>width$} ", line + 1, width = number_width)
+            };
+            Text::raw(row + "\n")
+        })
+        .collect()
+}
+
+fn tag_style(tag: Tag, style: Style) -> Style {
+    match tag {
+        Tag::Cursor => style.modifier(style.modifier | Modifier::REVERSED),
+        Tag::Highlighted => style.bg(Color::Blue),
+        Tag::Match => style.fg(Color::Black).bg(Color::Yellow),
+    }
+}
+
+// Nearest char boundary at or after `idx`, so that tags never split a char
+fn ceil_char_boundary(text: &str, idx: usize) -> usize {
+    let mut idx = idx.min(text.len());
+    while !text.is_char_boundary(idx) {
+        idx += 1;
+    }
+    idx
+}
+
+// Splits the text at every tag boundary and styles each span with the
+// tags that cover it. Tabs are expanded to spaces since tui gives them no
+// width of their own, and everything left of `left_column` is cut off.
+fn tagged_text_to_spans(
+    tagged_text: &TaggedText,
+    base_style: Style,
+    tab_stop: usize,
+    left_column: usize,
+) -> Vec<Text<'_>> {
+    let text = tagged_text.as_str();
+    let mut boundaries = vec![0, text.len()];
+    for tag in tagged_text.tags() {
+        boundaries.push(ceil_char_boundary(text, tag.start_idx()));
+        boundaries.push(ceil_char_boundary(text, tag.end_idx()));
+    }
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut column = 0;
+    boundaries
+        .windows(2)
+        .map(|window| {
+            let (start, end) = (window[0], window[1]);
+            let style = tagged_text
+                .tags()
+                .iter()
+                .filter(|tag| tag.start_idx() <= start && end <= tag.end_idx())
+                .fold(base_style, |style, tag| tag_style(tag.tag(), style));
+
+            let span = &text[start..end];
+            if left_column == 0 && !span.contains('\t') {
+                let line_start = span.rfind('\n').map_or(0, |idx| idx + 1);
+                if line_start > 0 {
+                    column = 0;
+                }
+                column += width::display_width(&span[line_start..], tab_stop);
+                return Text::styled(span, style);
+            }
+
+            let (visible, end_column) = visible_text(span, column, left_column, tab_stop);
+            column = end_column;
+            Text::styled(visible, style)
+        })
+        .collect()
+}
+
+// The part of `text` that is drawn once the view is scrolled to
+// `left_column`, given that `text` starts at screen column `column`. Tabs
+// become spaces and a wide char cut in half is padded with spaces. Also
+// returns the screen column the text ends on.
+fn visible_text(text: &str, column: usize, left_column: usize, tab_stop: usize) -> (String, usize) {
+    let mut visible = String::with_capacity(text.len());
+    let mut column = column;
+    for grapheme in text.graphemes(true) {
+        if grapheme.ends_with('\n') {
+            visible.push_str(grapheme);
+            column = 0;
+            continue;
+        }
+
+        let width = width::grapheme_width(grapheme, column, tab_stop);
+        if column >= left_column && grapheme != "\t" {
+            visible.push_str(grapheme);
+        } else if column + width > left_column {
+            visible.push_str(&" ".repeat(column + width - left_column.max(column)));
+        }
+        column += width;
+    }
+    (visible, column)
+}
+
+// Row and screen column of the cursor tag within the text
+fn cursor_position(tagged_text: &TaggedText, tab_stop: usize) -> Option<(usize, usize)> {
+    let text = tagged_text.as_str();
+    let cursor = tagged_text
+        .tags()
+        .iter()
+        .find(|tag| tag.tag() == Tag::Cursor)?;
+    let before = &text[..ceil_char_boundary(text, cursor.start_idx())];
+
+    let row = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    let column = width::display_width(&before[line_start..], tab_stop);
+    Some((row, column))
+}
+
+#[cfg(test)]
+#[path = "tests/view_tests.rs"]
+mod tests;
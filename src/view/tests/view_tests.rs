@@ -0,0 +1,285 @@
+#[cfg(test)]
+mod view_tests {
+    use super::super::*;
+    use crate::model::texttag::TextTag;
+
+    #[test]
+    fn spans_without_tags() {
+        let tagged_text = TaggedText::new(String::from("abc"), vec![]);
+        let style = Style::default();
+
+        let spans = tagged_text_to_spans(&tagged_text, style, 8, 0);
+        assert_eq!(spans, vec![Text::styled("abc", style)]);
+    }
+
+    #[test]
+    fn spans_with_cursor() {
+        let tagged_text =
+            TaggedText::new(String::from("abc"), vec![TextTag::new(Tag::Cursor, 1, 2)]);
+        let style = Style::default();
+        let cursor_style = style.modifier(Modifier::REVERSED);
+
+        let spans = tagged_text_to_spans(&tagged_text, style, 8, 0);
+        assert_eq!(
+            spans,
+            vec![
+                Text::styled("a", style),
+                Text::styled("b", cursor_style),
+                Text::styled("c", style),
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_with_cursor_past_the_end() {
+        let tagged_text =
+            TaggedText::new(String::from("abc"), vec![TextTag::new(Tag::Cursor, 3, 4)]);
+        let style = Style::default();
+
+        let spans = tagged_text_to_spans(&tagged_text, style, 8, 0);
+        assert_eq!(spans, vec![Text::styled("abc", style)]);
+    }
+
+    #[test]
+    fn spans_with_overlapping_tags() {
+        let tagged_text = TaggedText::new(
+            String::from("abcd"),
+            vec![
+                TextTag::new(Tag::Highlighted, 0, 3),
+                TextTag::new(Tag::Cursor, 2, 3),
+            ],
+        );
+        let style = Style::default();
+        let highlighted_style = style.bg(Color::Blue);
+        let both_style = highlighted_style.modifier(Modifier::REVERSED);
+
+        let spans = tagged_text_to_spans(&tagged_text, style, 8, 0);
+        assert_eq!(
+            spans,
+            vec![
+                Text::styled("ab", highlighted_style),
+                Text::styled("c", both_style),
+                Text::styled("d", style),
+            ]
+        );
+    }
+
+    #[test]
+    fn cursor_position_on_first_line() {
+        let tagged_text =
+            TaggedText::new(String::from("abc"), vec![TextTag::new(Tag::Cursor, 2, 3)]);
+        assert_eq!(cursor_position(&tagged_text, 8), Some((0, 2)));
+    }
+
+    #[test]
+    fn cursor_position_after_newlines() {
+        let tagged_text = TaggedText::new(
+            String::from("ab\ncd\nef"),
+            vec![TextTag::new(Tag::Cursor, 7, 8)],
+        );
+        assert_eq!(cursor_position(&tagged_text, 8), Some((2, 1)));
+    }
+
+    #[test]
+    fn cursor_position_at_start_of_line() {
+        let tagged_text =
+            TaggedText::new(String::from("ab\n"), vec![TextTag::new(Tag::Cursor, 3, 4)]);
+        assert_eq!(cursor_position(&tagged_text, 8), Some((1, 0)));
+    }
+
+    #[test]
+    fn cursor_position_without_cursor() {
+        let tagged_text = TaggedText::new(String::from("ab"), vec![]);
+        assert_eq!(cursor_position(&tagged_text, 8), None);
+    }
+
+    #[test]
+    fn spans_expand_tabs_across_tags() {
+        let tagged_text = TaggedText::new(
+            String::from("a\tb\n\tc"),
+            vec![TextTag::new(Tag::Cursor, 1, 2)],
+        );
+        let style = Style::default();
+        let cursor_style = style.modifier(Modifier::REVERSED);
+
+        let spans = tagged_text_to_spans(&tagged_text, style, 4, 0);
+        assert_eq!(
+            spans,
+            vec![
+                Text::styled("a", style),
+                Text::styled("   ", cursor_style),
+                Text::styled("b\n    c", style),
+            ]
+        );
+    }
+
+    #[test]
+    fn cursor_position_after_wide_chars_and_tabs() {
+        let tagged_text = TaggedText::new(
+            String::from("\u{4E2D}\tx"),
+            vec![TextTag::new(Tag::Cursor, 4, 5)],
+        );
+        assert_eq!(cursor_position(&tagged_text, 4), Some((0, 4)));
+
+        let tagged_text = TaggedText::new(
+            String::from("e\u{301}\u{200D}x"),
+            vec![TextTag::new(Tag::Cursor, 6, 7)],
+        );
+        assert_eq!(cursor_position(&tagged_text, 4), Some((0, 1)));
+    }
+
+    #[test]
+    fn spans_scrolled_right() {
+        let tagged_text = TaggedText::new(
+            String::from("abcdef\n\u{4E2D}\u{6587}x"),
+            vec![TextTag::new(Tag::Cursor, 3, 4)],
+        );
+        let style = Style::default();
+        let cursor_style = style.modifier(Modifier::REVERSED);
+
+        let spans = tagged_text_to_spans(&tagged_text, style, 8, 3);
+        assert_eq!(
+            spans,
+            vec![
+                Text::styled("", style),
+                Text::styled("d", cursor_style),
+                Text::styled("ef\n x", style),
+            ]
+        );
+    }
+
+    #[test]
+    fn visible_text_cuts_tabs_and_wide_chars() {
+        assert_eq!(visible_text("a\tb", 0, 0, 4), (String::from("a   b"), 5));
+        assert_eq!(visible_text("\tb", 2, 0, 4), (String::from("  b"), 5));
+        assert_eq!(visible_text("a\tb", 0, 2, 4), (String::from("  b"), 5));
+        assert_eq!(
+            visible_text("\u{4E2D}\u{6587}", 0, 1, 4),
+            (String::from(" \u{6587}"), 4)
+        );
+        assert_eq!(visible_text("ab\ncd", 0, 1, 4), (String::from("b\nd"), 2));
+    }
+
+    #[test]
+    fn scroll_offset_follows_position() {
+        // Already visible
+        assert_eq!(scroll_offset(0, 5, 10, 0), 0);
+        // Past the bottom and above the top
+        assert_eq!(scroll_offset(0, 12, 10, 0), 3);
+        assert_eq!(scroll_offset(5, 2, 10, 0), 2);
+    }
+
+    #[test]
+    fn scroll_offset_keeps_margin() {
+        assert_eq!(scroll_offset(0, 7, 10, 3), 1);
+        assert_eq!(scroll_offset(5, 7, 10, 3), 4);
+        assert_eq!(scroll_offset(0, 1, 10, 3), 0);
+        // The margin can't be more than half the viewport
+        assert_eq!(scroll_offset(0, 2, 3, 5), 1);
+        assert_eq!(scroll_offset(0, 2, 0, 5), 0);
+    }
+
+    #[test]
+    fn gutter_width_grows_with_line_count() {
+        assert_eq!(gutter_width(LineNumbers::Off, 5), 0);
+        assert_eq!(gutter_width(LineNumbers::Absolute, 5), 4);
+        assert_eq!(gutter_width(LineNumbers::Relative, 12345), 6);
+    }
+
+    #[test]
+    fn gutter_lines_absolute() {
+        let lines = gutter_lines(LineNumbers::Absolute, 8, 4, 9, 10, 4);
+        assert_eq!(
+            lines,
+            vec![
+                Text::raw("  9 \n"),
+                Text::raw(" 10 \n"),
+                Text::raw("~   \n"),
+                Text::raw("~   \n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn gutter_lines_relative() {
+        let lines = gutter_lines(LineNumbers::Relative, 0, 3, 1, 10, 4);
+        assert_eq!(
+            lines,
+            vec![
+                Text::raw("  1 \n"),
+                Text::raw("  2 \n"),
+                Text::raw("  1 \n"),
+            ]
+        );
+        assert!(gutter_lines(LineNumbers::Off, 0, 3, 1, 10, 0).is_empty());
+    }
+
+    #[test]
+    fn status_line_for_new_buffer() {
+        let mut app = App::new(&[]);
+        assert_eq!(
+            status_line(&app, 40),
+            " [No Name]            EDIT  1:1  1 line "
+        );
+
+        app.add_char('a');
+        app.add_char('\n');
+        assert_eq!(
+            status_line(&app, 40),
+            " [No Name] [+]       EDIT  2:1  2 lines "
+        );
+    }
+
+    #[test]
+    fn status_line_shows_line_ending() {
+        let mut app = App::new(&[]);
+        app.handle_command_line();
+        for c in "set ff=dos".chars() {
+            app.add_char(c);
+        }
+        app.submit_command();
+        assert_eq!(
+            status_line(&app, 40),
+            " [No Name] [+] [dos]  EDIT  1:1  1 line "
+        );
+    }
+
+    #[test]
+    fn status_line_shows_encoding() {
+        let mut app = App::new(&[]);
+        app.handle_command_line();
+        for c in "set fenc=latin1".chars() {
+            app.add_char(c);
+        }
+        app.submit_command();
+        assert_eq!(
+            status_line(&app, 40),
+            " [No Name] [+] [latin1] EDIT  1:1  1 line "
+        );
+    }
+
+    #[test]
+    fn status_line_narrower_than_contents() {
+        let app = App::new(&[]);
+        assert_eq!(status_line(&app, 10), " [No Name] EDIT  1:1  1 line ");
+    }
+
+    #[test]
+    fn message_line_shows_prompt_in_command_mode() {
+        let mut app = App::new(&[]);
+        assert_eq!(message_line(&app), "");
+
+        app.handle_command_line();
+        app.add_char('w');
+        assert_eq!(message_line(&app), ":w");
+    }
+
+    #[test]
+    fn message_line_shows_search_prompt() {
+        let mut app = App::new(&[]);
+        app.start_search(SearchDirection::Backward);
+        app.add_char('x');
+        assert_eq!(message_line(&app), "?x");
+        assert_eq!(mode_name(app.mode(), app.is_modal()), "SEARCH");
+    }
+}